@@ -0,0 +1,75 @@
+// git-sheets: Small-table benchmarks - hashing and diffing the many small
+// sheets that make up the common case
+//
+// Run with `cargo bench --bench small_tables`. Uses no benchmark framework:
+// each case runs in batches of a fixed number of iterations and prints the
+// mean time of the fastest batch, which is the least disturbed by other load.
+
+use gitsheets::{Snapshot, Table, TableHashes, diff::SnapshotDiff};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// `rows` rows of `cols` columns, keyed on the first
+fn table(rows: usize, cols: usize, salt: usize) -> Table {
+    Table {
+        headers: (0..cols).map(|c| format!("Col{c}")).collect(),
+        rows: (0..rows)
+            .map(|r| {
+                (0..cols)
+                    .map(|c| match c {
+                        0 => r.to_string(),
+                        _ if r % 7 == salt % 7 => format!("changed{r}_{c}"),
+                        _ => format!("value{r}_{c}"),
+                    })
+                    .collect()
+            })
+            .collect(),
+        primary_key: Some(vec![0]),
+    }
+}
+
+/// Batches of `f` run before timing any
+const WARMUP_BATCHES: u32 = 1;
+/// Batches of `f` timed per case
+const BATCHES: u32 = 5;
+
+/// Mean time of `iterations` runs of `f` in the fastest batch, printed
+/// under `name`
+fn bench(name: &str, iterations: u32, mut f: impl FnMut()) {
+    let mut fastest = Duration::MAX;
+    for batch in 0..WARMUP_BATCHES + BATCHES {
+        let start = Instant::now();
+        for _ in 0..iterations {
+            f();
+        }
+        if batch >= WARMUP_BATCHES {
+            fastest = fastest.min(start.elapsed() / iterations);
+        }
+    }
+    println!("{name:<32} {fastest:>12.2?}");
+}
+
+fn main() {
+    for (rows, cols) in [(5, 4), (20, 8), (32, 8), (50, 10), (1000, 10)] {
+        let iterations = if rows * cols > 1000 { 500 } else { 10_000 };
+        let from = Snapshot::new(table(rows, cols, 0), None);
+        let to = Snapshot::new(table(rows, cols, 1), None);
+
+        bench(&format!("hashes {rows}x{cols}"), iterations, || {
+            black_box(TableHashes::compute(black_box(&from.table)));
+        });
+        bench(&format!("diff {rows}x{cols}"), iterations, || {
+            black_box(SnapshotDiff::compute(black_box(&from), black_box(&to)).unwrap());
+        });
+        bench(&format!("summarize {rows}x{cols}"), iterations, || {
+            black_box(SnapshotDiff::summarize(black_box(&from), black_box(&to)));
+        });
+        let unchanged = Snapshot::new(table(rows, cols, 0), None);
+        bench(&format!("unchanged {rows}x{cols}"), iterations, || {
+            black_box(SnapshotDiff::summarize(
+                black_box(&from),
+                black_box(&unchanged),
+            ));
+        });
+    }
+}
@@ -0,0 +1,24 @@
+// git-sheets: Diffing in-memory tables without filesystem or git access
+//
+// Builds for the browser with:
+//   cargo build --example wasm_diff --target wasm32-unknown-unknown --no-default-features
+
+use gitsheets::{LoadOptions, Snapshot, SnapshotDiff, Table};
+
+fn table(csv: &str) -> Table {
+    let (mut table, _) =
+        Table::from_csv_bytes(csv.as_bytes().to_vec(), &LoadOptions::default()).expect("valid CSV");
+    table.set_primary_key(vec![0]);
+    table
+}
+
+fn main() {
+    let before = Snapshot::new(table("id,item,qty\n1,apples,3\n2,pears,5\n"), None);
+    let after = Snapshot::new(table("id,item,qty\n1,apples,4\n3,plums,2\n"), None);
+
+    let diff = SnapshotDiff::compute(&before, &after).expect("diff");
+    println!("{}", diff.summary);
+    for change in &diff.changes {
+        println!("{change}");
+    }
+}
@@ -1,320 +1,3484 @@
 // git-sheets: CLI module - command parsing and implementations
 // A tool for Excel sufferers who deserve better
 
-use crate::core::Table;
-use crate::core::{GitSheetsError, Result, Snapshot};
-use crate::diff::{Change, SnapshotDiff};
-use clap::{Parser, Subcommand};
-use std::io::Write;
-use std::path::Path;
+use crate::core::lock::DEFAULT_LOCK_TIMEOUT;
+use crate::core::{
+    Config, DEFAULT_ID_HASH_LEN, LineEnding, LoadOptions, QuoteStyle, Schema, Table, WriteOptions,
+    logical_hash, parse_number, read_at_revision, read_committed, relative_to_repo_root,
+    write_atomic,
+};
+use crate::core::{
+    FsStorage, GitSheetsError, Result, Snapshot, SnapshotStorage, SnapshotStore, TableHashes,
+};
+use crate::core::{RepoLock, is_snapshot_file, snapshot_path};
+use crate::diff::{
+    CellChangeKind, Change, DiffOptions, DiffSummary, RowMatching, SeverityWeights, SnapshotDiff,
+    rank_by_severity,
+};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Parser)]
 #[command(name = "git-sheets")]
 #[command(about = "Version control for spreadsheets", long_about = None)]
 pub struct Cli {
+    /// Log what git-sheets is doing to stderr (-vv for more detail);
+    /// RUST_LOG takes precedence
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Never read from or write to git, e.g. for snapshots kept outside a
+    /// repository (also `no_git = true` in .gitsheets/config.toml)
+    #[arg(long, global = true)]
+    no_git: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
-impl Cli {
-    /// Execute the command
-    pub fn execute(&self) -> Result<()> {
-        match &self.command {
-            Commands::Init { path } => init_repository(&Path::new(path)),
-            Commands::Snapshot {
-                file,
-                message,
-                primary_key,
-                auto_commit,
-            } => create_snapshot(Path::new(file), message.clone(), primary_key.clone(), *auto_commit),
-            Commands::Diff { from, to, format } => {
-                let format_str = format.as_ref().map(|s| s.as_str()).unwrap_or("text");
-                show_diff(&Path::new(from), &Path::new(to), format_str)
-            }
-            Commands::Verify { file } => verify_snapshot(&Path::new(file)),
-            Commands::Status => show_status(),
-            Commands::Log { limit } => show_log(*limit),
+impl Cli {
+    /// Execute the command
+    pub fn execute(&self) -> Result<()> {
+        init_tracing(self.verbose);
+        NO_GIT.store(self.no_git, Ordering::Relaxed);
+        match &self.command {
+            Commands::Init { path } => init_repository(Path::new(path)),
+            Commands::Snapshot { files, args } => create_snapshot(files, args),
+            Commands::SnapshotDir {
+                dir,
+                incremental,
+                logical,
+                args,
+            } => snapshot_dir(Path::new(dir), *incremental, *logical, args),
+            Commands::Diff { from, to, args } => {
+                run_diff(Path::new(from), to.as_deref().map(Path::new), args)
+            }
+            Commands::DiffChain { files, no_color } => show_diff_chain(files, use_color(*no_color)),
+            Commands::Changelog { col, output } => {
+                export_changelog(col, output.as_deref().map(Path::new))
+            }
+            Commands::Catalog { output } => export_catalog(output.as_deref().map(Path::new)),
+            Commands::Verify {
+                file,
+                spot_check,
+                seed,
+                ignore_column_order,
+                logical,
+                jobs,
+                ..
+            } => {
+                let tolerance = Tolerance::new(*ignore_column_order, *logical);
+                match (file, spot_check) {
+                    (Some(file), Some(sample)) => {
+                        spot_check_snapshot(Path::new(file), *sample, *seed)
+                    }
+                    (Some(file), None) => verify_snapshot(Path::new(file), tolerance),
+                    (None, _) => verify_all(tolerance, *jobs),
+                }
+            }
+            Commands::Status => show_status(),
+            Commands::Fsck => check_repository(),
+            Commands::Log {
+                limit,
+                since,
+                until,
+                grep,
+            } => show_log(*limit, *since, *until, grep.as_deref()),
+            Commands::Columns { file, json } => show_columns(Path::new(file), *json),
+            Commands::Normalize {
+                input,
+                output,
+                load,
+                format,
+            } => normalize_csv(input, output.as_deref().map(Path::new), load, format),
+            Commands::Amend {
+                file,
+                message,
+                refresh,
+                force,
+                load,
+            } => amend_snapshot(Path::new(file), message.clone(), *refresh, *force, load),
+            Commands::Rekey { file, key } => rekey_snapshot(Path::new(file), key),
+            Commands::Restore { file, keys, target } => {
+                let mut snapshot = Snapshot::load_materialized(Path::new(file))?;
+                if let Some(keys) = keys {
+                    snapshot.table = snapshot.table.select_keys(&parse_key_list(keys))?;
+                }
+                restore_snapshot(&snapshot, target)
+            }
+            Commands::Undo { file, target } => undo_snapshot(Path::new(file), target),
+            Commands::Checkout {
+                file,
+                as_path,
+                force,
+                format,
+            } => checkout_snapshot(Path::new(file), as_path.as_deref(), *force, format),
+            Commands::Export { file, output } => {
+                export_snapshot(Path::new(file), Path::new(output))
+            }
+            Commands::History {
+                file,
+                row,
+                col,
+                aggregate,
+            } => show_history(Path::new(file), row.as_deref(), col, *aggregate),
+            Commands::Report {
+                source,
+                since,
+                until,
+                top,
+            } => show_report(source, *since, *until, *top),
+        }
+    }
+}
+
+/// Set from `--no-git` for the running command
+static NO_GIT: AtomicBool = AtomicBool::new(false);
+
+/// Whether git may be used, i.e. neither `--no-git` nor `no_git` in the config
+fn git_enabled() -> Result<bool> {
+    Ok(!NO_GIT.load(Ordering::Relaxed) && !Config::load(Path::new("."))?.no_git)
+}
+
+/// Take the lock on the snapshot store of the repository in the working
+/// directory, for the steps that read and then change it
+fn lock_repo() -> Result<RepoLock> {
+    RepoLock::acquire(Path::new("."), DEFAULT_LOCK_TIMEOUT)
+}
+
+/// Fail unless git may be used, for commands that can't work without it
+fn require_git(what: &str) -> Result<()> {
+    if git_enabled()? {
+        Ok(())
+    } else {
+        Err(GitSheetsError::GitDisabled(format!(
+            "{what} needs git, but git integration is disabled"
+        )))
+    }
+}
+
+/// Send log events to stderr when asked for with `RUST_LOG` or `--verbose`
+///
+/// Without either only warnings get through, shown without timestamps like
+/// the CLI's own, so the instrumentation costs next to nothing. Spans report
+/// their timing when they close.
+fn init_tracing(verbose: u8) {
+    use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
+
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) if verbose == 0 => {
+            let _ = tracing_subscriber::fmt()
+                .with_env_filter(EnvFilter::new("gitsheets=warn"))
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal())
+                .without_time()
+                .with_target(false)
+                .try_init();
+            return;
+        }
+        Err(_) if verbose == 1 => EnvFilter::new("gitsheets=debug"),
+        Err(_) => EnvFilter::new("gitsheets=trace"),
+    };
+    // Fails only if a subscriber is already set, e.g. by an embedding program
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_span_events(FmtSpan::CLOSE)
+        .try_init();
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Initialize a new git-sheets repository
+    Init {
+        /// Path to initialize the repository
+        #[arg(value_name = "PATH")]
+        path: String,
+    },
+
+    /// Create a snapshot of a table
+    Snapshot {
+        /// Table file, or CSV export URL (with the `network` feature), to
+        /// snapshot; several files with the same headers are snapshotted as
+        /// one table, their rows in the order given
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+
+        #[command(flatten)]
+        args: SnapshotArgs,
+    },
+
+    /// Snapshot every CSV file in a directory
+    SnapshotDir {
+        /// Directory whose CSV files to snapshot (subdirectories are skipped)
+        #[arg(value_name = "DIR")]
+        dir: String,
+
+        /// Skip files whose table is unchanged since their latest snapshot
+        #[arg(long)]
+        incremental: bool,
+
+        /// With --incremental, compare logical hashes, so a file that was
+        /// only reformatted or reordered also counts as unchanged
+        #[arg(long, requires = "incremental")]
+        logical: bool,
+
+        #[command(flatten)]
+        args: SnapshotArgs,
+    },
+
+    /// Show a diff between two snapshots
+    Diff {
+        /// First snapshot file
+        #[arg(value_name = "FROM")]
+        from: String,
+
+        /// Second snapshot file
+        #[arg(value_name = "TO", required_unless_present_any = ["vs_head", "git_rev"])]
+        to: Option<String>,
+
+        #[command(flatten)]
+        args: DiffArgs,
+    },
+
+    /// Show each hop and the net change across a sequence of snapshots
+    DiffChain {
+        /// Snapshot files, oldest first
+        #[arg(value_name = "FILES", num_args = 2.., required = true)]
+        files: Vec<String>,
+
+        /// Disable colored output
+        #[arg(long)]
+        no_color: bool,
+    },
+
+    /// Export every change to one column across all snapshot history as CSV
+    Changelog {
+        /// Column name
+        #[arg(long)]
+        col: String,
+
+        /// CSV file to write (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Write one CSV row per snapshot: id, timestamp, message, author,
+    /// source, size and hash
+    Catalog {
+        /// CSV file to write (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Verify integrity of a snapshot
+    Verify {
+        /// Snapshot file to verify
+        #[arg(
+            value_name = "FILE",
+            required_unless_present = "all",
+            conflicts_with = "all"
+        )]
+        file: Option<String>,
+
+        /// Verify every snapshot in snapshots/ and print a tally
+        #[arg(long)]
+        all: bool,
+
+        /// Only re-hash N randomly chosen rows against the snapshot's row
+        /// hashes (see `snapshot --row-hashes`) of FILE; fast but
+        /// probabilistic
+        #[arg(long, value_name = "N", requires = "file", conflicts_with = "all")]
+        spot_check: Option<usize>,
+
+        /// Seed choosing the spot-checked rows (random by default)
+        #[arg(long, requires = "spot_check")]
+        seed: Option<u64>,
+
+        /// Check each column's data by name, so a snapshot whose columns
+        /// were only rearranged still passes (a file that fails its checksum
+        /// still fails)
+        #[arg(long, conflicts_with = "spot_check")]
+        ignore_column_order: bool,
+
+        /// Check the snapshot's logical hash, so a table that was only
+        /// reformatted (case, whitespace, number and date layout) or had its
+        /// rows and columns reordered still passes (a file that fails its
+        /// checksum still fails)
+        #[arg(long, conflicts_with_all = ["spot_check", "ignore_column_order"])]
+        logical: bool,
+
+        /// Verify at most N snapshots at once with --all (one per CPU core
+        /// by default)
+        #[arg(short, long, value_name = "N", conflicts_with = "file")]
+        jobs: Option<usize>,
+    },
+
+    /// Show current status
+    Status,
+
+    /// Check every snapshot, diff and parent link in the repository
+    Fsck,
+
+    /// List all snapshots
+    Log {
+        /// Limit number of snapshots shown
+        #[arg(short, long)]
+        limit: Option<usize>,
+
+        /// Only show snapshots taken on or after this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+
+        /// Only show snapshots taken on or before this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long, value_parser = parse_until)]
+        until: Option<DateTime<Utc>>,
+
+        /// Only show snapshots whose message contains this text
+        #[arg(long)]
+        grep: Option<String>,
+    },
+
+    /// List a snapshot's columns: index, name, type, how many cells have a
+    /// value, and which form the primary key
+    Columns {
+        /// Snapshot file to describe
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Print the list as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Rewrite a CSV file in canonical form, as a snapshot would load it
+    Normalize {
+        /// CSV file, or CSV export URL (with the `network` feature), to normalize
+        #[arg(value_name = "FILE")]
+        input: String,
+
+        /// CSV file to write (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[command(flatten)]
+        load: LoadArgs,
+
+        #[command(flatten)]
+        format: CsvFormatArgs,
+    },
+
+    /// Write a snapshot's table back to the file it was taken from, to edit
+    /// it and snapshot again
+    Checkout {
+        /// Snapshot file to check out
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Write to this path instead of the snapshot's source file
+        #[arg(long = "as", value_name = "PATH")]
+        as_path: Option<String>,
+
+        /// Overwrite the file even if it has changes not committed to git
+        #[arg(short, long)]
+        force: bool,
+
+        #[command(flatten)]
+        format: CsvFormatArgs,
+    },
+
+    /// Change a snapshot's message, or re-read its source, in place
+    Amend {
+        /// Snapshot file to amend
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// New message
+        #[arg(short, long, required_unless_present = "refresh")]
+        message: Option<String>,
+
+        /// Re-read the source file, keeping the primary key, and recompute
+        /// the hashes and id (the parent link is kept)
+        #[arg(long)]
+        refresh: bool,
+
+        /// Amend even if other snapshots name this one as their parent,
+        /// which a new id leaves pointing at nothing
+        #[arg(short, long)]
+        force: bool,
+
+        #[command(flatten)]
+        load: LoadArgs,
+    },
+
+    /// Set or change a snapshot's primary key in place, so diffs against it
+    /// match rows by key; the table, hashes and id stay as they are
+    Rekey {
+        /// Snapshot file to re-key
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Column(s) forming the key, as comma-separated 0-based indices or
+        /// column names; every row must have a different key
+        #[arg(short = 'k', long, value_name = "COLUMNS")]
+        key: PrimaryKeySpec,
+    },
+
+    /// Write a snapshot's table back out as CSV
+    Restore {
+        /// Snapshot file to restore
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Only write the rows with these primary keys, e.g. to paste deleted
+        /// records back (comma-separated, composite key values joined with '|')
+        #[arg(long)]
+        keys: Option<String>,
+
+        #[command(flatten)]
+        target: RestoreTarget,
+    },
+
+    /// Write a snapshot's table as a Parquet file, with typed columns
+    /// (needs the `parquet` feature)
+    Export {
+        /// Snapshot file to export
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Parquet file to write
+        #[arg(short, long)]
+        output: String,
+    },
+
+    /// Restore the snapshot before this one, undoing its changes
+    Undo {
+        /// Snapshot whose parent to restore
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        #[command(flatten)]
+        target: RestoreTarget,
+    },
+
+    /// Show how a cell or column evolved along a snapshot's parent chain
+    History {
+        /// Most recent snapshot of the chain
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Primary key of the row to follow (composite values joined with '|')
+        #[arg(
+            long,
+            required_unless_present = "aggregate",
+            conflicts_with = "aggregate"
+        )]
+        row: Option<String>,
+
+        /// Column name
+        #[arg(long)]
+        col: String,
+
+        /// Aggregate the whole column in each snapshot instead of one cell
+        #[arg(long, value_enum)]
+        aggregate: Option<Aggregate>,
+    },
+
+    /// Summarize the net change to one source file over a time window
+    Report {
+        /// Source file, by any path that names it (e.g. `data.csv` or `./data.csv`)
+        #[arg(long)]
+        source: String,
+
+        /// Start of the window (YYYY-MM-DD or RFC 3339)
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+
+        /// End of the window (YYYY-MM-DD or RFC 3339)
+        #[arg(long, value_parser = parse_until)]
+        until: Option<DateTime<Utc>>,
+
+        /// How many columns and rows to list
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        top: usize,
+    },
+}
+
+/// Options for the diff command
+#[derive(Args)]
+struct DiffArgs {
+    /// Output format (json, ndjson, git or side-by-side)
+    #[arg(short, long)]
+    format: Option<String>,
+
+    /// Only print the change counts (JSON with `-f json`)
+    #[arg(long)]
+    summary_only: bool,
+
+    /// Only list the primary keys that were added or removed (JSON with
+    /// `-f json`), skipping all cell comparison
+    #[arg(long, conflicts_with = "summary_only")]
+    keys_only: bool,
+
+    /// List each changed column with its count of touched cells and a
+    /// +/~/- bar (added, changed, removed), like `git diff --stat` (JSON
+    /// with `-f json`)
+    #[arg(long, conflicts_with_all = ["summary_only", "keys_only"])]
+    stat: bool,
+
+    /// Report "logically identical" when only row or column order differs
+    #[arg(long)]
+    unordered: bool,
+
+    /// Page through the changes one at a time, accepting or rejecting each,
+    /// then write the accepted ones as a JSON diff
+    #[arg(
+        long,
+        conflicts_with_all = ["summary_only", "keys_only", "stat", "unordered", "append_only"]
+    )]
+    interactive: bool,
+
+    /// Treat the table as an append-only log: list only the rows whose key
+    /// is new, and fail if any earlier row is missing (JSON with `-f json`)
+    #[arg(long, conflicts_with_all = ["keys_only", "stat", "unordered"])]
+    append_only: bool,
+
+    /// Diff the version of FROM's source file committed at git HEAD against
+    /// FROM itself (read with default load options and the configured null_tokens)
+    #[arg(long, conflicts_with = "to")]
+    vs_head: bool,
+
+    /// Diff the snapshot file FROM as committed at this git revision (e.g.
+    /// HEAD~3) against its current version, to see how the file itself changed
+    #[arg(long, value_name = "REV", conflicts_with_all = ["to", "vs_head"])]
+    git_rev: Option<String>,
+
+    /// Disable colored output
+    #[arg(long)]
+    no_color: bool,
+
+    /// Write the diff to this file instead of stdout, creating parent
+    /// directories as needed
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Leave whitespace-only cell changes out of text and git output
+    /// (they stay in JSON, marked as WhitespaceOnly)
+    #[arg(long)]
+    hide_whitespace_changes: bool,
+
+    /// Only list text cells that were edited a little (minor, e.g. a typo
+    /// fix) or substantially (major) (text and git formats)
+    #[arg(long, value_enum, value_name = "SIZE")]
+    only: Option<EditSize>,
+
+    /// List the changed rows worst-first (text and git formats), weighted by
+    /// the [severity] table of .gitsheets/config.toml
+    #[arg(long)]
+    sort_by_severity: bool,
+
+    /// Show N unchanged rows around each changed row (text format)
+    #[arg(short = 'U', long, default_value_t = 0)]
+    context: usize,
+
+    /// Treat a removed and an added column as a rename when at least this
+    /// fraction of their values match (e.g. 0.9)
+    #[arg(long, value_name = "FRACTION", value_parser = parse_fraction)]
+    rename_threshold: Option<f64>,
+
+    /// Stop listing individual changes after N (text and git formats);
+    /// summary counts stay exact
+    #[arg(long, value_name = "N")]
+    limit_changes: Option<usize>,
+
+    /// Comma-separated column names to match rows by, overriding the
+    /// snapshots' own primary keys
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    force_key: Option<Vec<String>>,
+
+    /// First column to show in the side-by-side format, counting from 0
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    start_col: usize,
+
+    /// Most columns to show at once in the side-by-side format (as many as
+    /// fit the terminal by default)
+    #[arg(long, value_name = "N")]
+    max_cols: Option<usize>,
+
+    /// Show each side-by-side row as a vertical column: value list, for
+    /// inspecting records of wide tables
+    #[arg(long)]
+    transpose: bool,
+
+    /// Fail (exit code 1) if more than N rows were added, removed or changed
+    #[arg(long, value_name = "N")]
+    threshold_rows: Option<usize>,
+
+    /// Fail (exit code 1) if more than P percent of FROM's rows were added,
+    /// removed or changed
+    #[arg(long, value_name = "P", value_parser = parse_percent)]
+    threshold_percent: Option<f64>,
+
+    /// Write JSON output minified instead of pretty-printed
+    #[arg(long)]
+    compact: bool,
+
+    /// Show where each changed row sits in both snapshots, e.g.
+    /// (old #5 -> new #8), when rows are matched by key
+    #[arg(long)]
+    row_indices: bool,
+
+    /// Report a removed row whose numbers were divided among several added
+    /// rows as a split, and several removed rows added up into one as a
+    /// merge (heuristic; rows must be matched by key)
+    #[arg(long)]
+    detect_splits: bool,
+
+    /// Columns holding delimited lists, as NAME:DELIMITER (e.g. Tags:;),
+    /// compared as unordered sets so reordered items aren't a change;
+    /// defaults to set_columns in .gitsheets/config.toml
+    #[arg(long, value_name = "NAME:DELIMITER", value_parser = parse_set_column)]
+    set_columns: Option<Vec<(String, String)>>,
+
+    /// Compare cells by column name, and across renamed columns, instead of
+    /// by position, for snapshots whose columns changed in between (renames
+    /// are detected at 0.9 unless --rename-threshold is given)
+    #[arg(long)]
+    align_columns: bool,
+
+    /// Comma-separated primary keys of rows to leave out of the diff, e.g. a
+    /// totals row (composite key values joined with '|')
+    #[arg(long, value_name = "KEYS")]
+    ignore_keys: Option<String>,
+}
+
+impl DiffArgs {
+    fn format(&self) -> &str {
+        self.format.as_deref().unwrap_or("text")
+    }
+
+    /// Color only output headed for a terminal, never a file
+    fn color(&self) -> bool {
+        self.output.is_none() && use_color(self.no_color)
+    }
+
+    fn options(&self) -> Result<DiffOptions> {
+        let set_columns = match &self.set_columns {
+            Some(columns) => columns.iter().cloned().collect(),
+            None => Config::load(Path::new("."))?.set_columns,
+        };
+        let opts = DiffOptions {
+            rename_threshold: self.rename_threshold,
+            force_key: self.force_key.clone(),
+            // Context needs to know where each modified row sits in `to`
+            row_indices: self.row_indices || self.context > 0,
+            detect_splits: self.detect_splits,
+            set_columns,
+            ignore_row_keys: self
+                .ignore_keys
+                .as_deref()
+                .map_or_else(Vec::new, parse_key_list),
+            align_columns: false,
+        };
+        Ok(if self.align_columns {
+            opts.with_schema_reconciliation()
+        } else {
+            opts
+        })
+    }
+
+    /// Serialize JSON output, pretty unless `--compact` was given
+    fn json(&self, value: &impl Serialize) -> Result<String> {
+        Ok(if self.compact {
+            serde_json::to_string(value)?
+        } else {
+            serde_json::to_string_pretty(value)?
+        })
+    }
+}
+
+/// Split comma-separated primary keys, composite key values joined with '|'
+fn parse_key_list(keys: &str) -> Vec<Vec<String>> {
+    keys.split(',')
+        .map(|key| key.split('|').map(|v| v.trim().to_string()).collect())
+        .collect()
+}
+
+/// Parse a fraction in `0.0..=1.0`
+fn parse_fraction(s: &str) -> std::result::Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        _ => Err(format!("expected a number between 0 and 1, got '{s}'")),
+    }
+}
+
+/// Parse a set-valued column as `NAME:DELIMITER`
+fn parse_set_column(s: &str) -> std::result::Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((name, delimiter)) if !name.is_empty() && !delimiter.is_empty() => {
+            Ok((name.to_string(), delimiter.to_string()))
+        }
+        _ => Err(format!("expected NAME:DELIMITER, e.g. Tags:;, got '{s}'")),
+    }
+}
+
+/// Parse a non-negative percentage, e.g. `5` or `2.5`
+fn parse_percent(s: &str) -> std::result::Result<f64, String> {
+    match s.trim_end_matches('%').parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(format!("expected a non-negative percentage, got '{s}'")),
+    }
+}
+
+/// Options for the snapshot command
+#[derive(Args)]
+struct SnapshotArgs {
+    /// Commit message for the snapshot
+    #[arg(short, long)]
+    message: Option<String>,
+
+    /// Who is taking the snapshot (defaults to git's user.name and user.email)
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Set which column(s) form the primary key, as comma-separated
+    /// 0-based indices or column names (e.g. "0,2" or "Region,SKU")
+    #[arg(short = 'k', long, value_name = "COLUMNS")]
+    primary_key: Option<PrimaryKeySpec>,
+
+    /// Auto-commit to git after creating snapshot
+    #[arg(long)]
+    auto_commit: bool,
+
+    /// Store only the changes from the previous snapshot of the same file
+    #[arg(long)]
+    delta: bool,
+
+    /// Store the snapshot as JSON instead of TOML (faster and smaller for big tables)
+    #[arg(long)]
+    json: bool,
+
+    /// Store a hash of every row and their Merkle root, so `verify
+    /// --spot-check` can sample rows and single rows can be proven
+    #[arg(long)]
+    row_hashes: bool,
+
+    /// Comma-separated column names to leave out of the snapshot
+    #[arg(long)]
+    ignore_columns: Option<String>,
+
+    /// Only snapshot rows with these primary keys (comma-separated,
+    /// composite key values joined with '|')
+    #[arg(long, requires = "primary_key")]
+    keys: Option<String>,
+
+    #[command(flatten)]
+    load: LoadArgs,
+
+    /// Load, hash and validate the file, but don't write or commit anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Don't print the data-quality report (it is still stored in the snapshot)
+    #[arg(long)]
+    no_warnings: bool,
+
+    /// Refuse to snapshot a table that breaks the validation rules in
+    /// .gitsheets/config.toml or the --schema types (they are only warnings
+    /// otherwise)
+    #[arg(long)]
+    strict: bool,
+
+    /// Schema file declaring column types (int, float, date, bool, text or
+    /// code) to check the cells against and to compare them by in diffs
+    #[arg(long, value_name = "PATH")]
+    schema: Option<String>,
+
+    /// Number of table-hash characters used in the snapshot id
+    #[arg(long, default_value_t = DEFAULT_ID_HASH_LEN)]
+    id_length: usize,
+}
+
+/// How a CSV file is read, shared by snapshot and normalize
+#[derive(Args, Clone)]
+struct LoadArgs {
+    /// Keep line endings as-is instead of normalizing them to LF
+    #[arg(long)]
+    preserve_eol: bool,
+
+    /// Rewrite dates in date columns as YYYY-MM-DD before hashing
+    #[arg(long)]
+    normalize_dates: bool,
+
+    /// Keep leading and trailing whitespace in cells instead of trimming it
+    #[arg(long)]
+    preserve_whitespace: bool,
+
+    /// Comma-separated cell values that mean "missing" (e.g. "NULL,N/A,-"),
+    /// read as empty; defaults to null_tokens in .gitsheets/config.toml
+    #[arg(long, value_name = "TOKENS", value_delimiter = ',')]
+    null_tokens: Option<Vec<String>>,
+
+    /// Read a fixed-width text file with these column widths
+    /// (comma-separated character counts) instead of CSV
+    #[arg(long, value_delimiter = ',')]
+    fixed_width: Option<Vec<usize>>,
+
+    /// The file has no header line: read the first line as data and name
+    /// columns column_1, column_2, ...
+    #[arg(long)]
+    no_header: bool,
+
+    /// Replace invalid UTF-8 bytes with U+FFFD instead of failing, and
+    /// report how many cells were repaired
+    #[arg(long, conflicts_with = "fixed_width")]
+    lossy: bool,
+}
+
+impl LoadArgs {
+    fn options(&self) -> Result<LoadOptions> {
+        let null_tokens = match &self.null_tokens {
+            Some(tokens) => tokens.clone(),
+            None => Config::load(Path::new("."))?.null_tokens,
+        };
+        Ok(LoadOptions {
+            preserve_eol: self.preserve_eol,
+            normalize_dates: self.normalize_dates,
+            preserve_whitespace: self.preserve_whitespace,
+            null_tokens,
+            lossy_utf8: self.lossy,
+            no_header: self.no_header,
+        })
+    }
+
+    /// Load a table from a path or URL, returning the normalizations applied
+    fn load(&self, source: &str) -> Result<(Table, Vec<String>)> {
+        let options = self.options()?;
+        let Some(widths) = &self.fixed_width else {
+            return load_table(source, &options).map_err(|e| match e {
+                GitSheetsError::InvalidEncoding(msg) => GitSheetsError::InvalidEncoding(format!(
+                    "{msg}\n(use --lossy to replace invalid bytes with U+FFFD)"
+                )),
+                e => e,
+            });
+        };
+        let mut table = Table::from_fixed_width(Path::new(source), widths, !self.no_header)?;
+        let mut normalizations = table.normalize_null_tokens(&options.null_tokens);
+        if self.normalize_dates {
+            normalizations.extend(table.normalize_dates());
+        }
+        Ok((table, normalizations))
+    }
+}
+
+/// Where restore and undo write a table
+#[derive(Args)]
+struct RestoreTarget {
+    /// CSV file to write (defaults to stdout)
+    #[arg(short, long, conflicts_with = "output_dir")]
+    output: Option<String>,
+
+    /// Directory to write into, one CSV per table named after its source
+    #[arg(long)]
+    output_dir: Option<String>,
+
+    #[command(flatten)]
+    format: CsvFormatArgs,
+}
+
+/// How CSV output is formatted, for downstream tools with strict parsers
+#[derive(Args)]
+struct CsvFormatArgs {
+    /// Field separator (a single ASCII character)
+    #[arg(long, default_value_t = Delimiter(b','))]
+    delimiter: Delimiter,
+
+    /// Which fields to quote
+    #[arg(long, value_enum, default_value_t = QuoteStyle::Necessary)]
+    quote_style: QuoteStyle,
+
+    /// Line ending after each record (Excel expects crlf)
+    #[arg(long, value_enum, default_value_t = LineEnding::Lf)]
+    line_ending: LineEnding,
+}
+
+impl CsvFormatArgs {
+    fn options(&self, header: bool) -> WriteOptions {
+        WriteOptions {
+            delimiter: self.delimiter.0,
+            quote_style: self.quote_style,
+            line_ending: self.line_ending,
+            header,
+        }
+    }
+
+    /// Write `table` to `path` atomically, or stream it to stdout, with a
+    /// header line if `header` is set
+    fn write(&self, table: &Table, header: bool, path: Option<&Path>) -> Result<()> {
+        let options = self.options(header);
+        match path {
+            Some(path) => {
+                let mut bytes = Vec::new();
+                table.write_csv_with(&mut bytes, &options)?;
+                write_atomic(path, &bytes)
+            }
+            None => table.write_csv_with(std::io::stdout().lock(), &options),
+        }
+    }
+}
+
+/// A CSV delimiter: one ASCII character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Delimiter(u8);
+
+impl std::str::FromStr for Delimiter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s.as_bytes() {
+            [byte] if byte.is_ascii() => Ok(Delimiter(*byte)),
+            _ => Err(format!("'{s}' is not a single ASCII character")),
+        }
+    }
+}
+
+impl std::fmt::Display for Delimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0 as char)
+    }
+}
+
+/// One primary key column, by 0-based index or by name
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KeyColumn {
+    Index(usize),
+    Name(String),
+}
+
+/// Primary key columns as given to `--primary-key`
+///
+/// Entries that are whole numbers are indices, anything else a column name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PrimaryKeySpec(Vec<KeyColumn>);
+
+impl std::str::FromStr for PrimaryKeySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let mut columns = Vec::new();
+        for entry in s.split(',').map(str::trim) {
+            let column = match entry.parse::<usize>() {
+                Ok(index) => KeyColumn::Index(index),
+                Err(_) if entry.is_empty() => {
+                    return Err(format!("empty column in primary key '{s}'"));
+                }
+                Err(_) if entry.starts_with(['-', '+']) && entry[1..].parse::<u64>().is_ok() => {
+                    return Err(format!(
+                        "'{entry}' is not a column index (they count from 0)"
+                    ));
+                }
+                Err(_) => KeyColumn::Name(entry.to_string()),
+            };
+            if columns.contains(&column) {
+                return Err(format!("column '{entry}' is in the primary key twice"));
+            }
+            columns.push(column);
+        }
+        Ok(PrimaryKeySpec(columns))
+    }
+}
+
+impl PrimaryKeySpec {
+    /// Column indices of the key in `table`
+    fn resolve(&self, table: &Table) -> Result<Vec<usize>> {
+        let columns = || table.headers.join(", ");
+        self.0
+            .iter()
+            .map(|column| match column {
+                KeyColumn::Index(index) if *index < table.headers.len() => Ok(*index),
+                KeyColumn::Index(index) => Err(GitSheetsError::InvalidColumn(format!(
+                    "primary key column {index} is out of range; the table has {} columns ({})",
+                    table.headers.len(),
+                    columns()
+                ))),
+                KeyColumn::Name(name) => {
+                    table.headers.iter().position(|h| h == name).ok_or_else(|| {
+                        GitSheetsError::InvalidColumn(format!(
+                            "no column named '{name}' for the primary key; columns are {}",
+                            columns()
+                        ))
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+/// Which text edits `diff --only` lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EditSize {
+    /// Small corrections, e.g. fixed typos
+    Minor,
+    /// Substantially different text
+    Major,
+}
+
+impl EditSize {
+    fn kind(self) -> CellChangeKind {
+        match self {
+            EditSize::Minor => CellChangeKind::MinorEdit,
+            EditSize::Major => CellChangeKind::MajorEdit,
+        }
+    }
+}
+
+/// Column aggregate for the history command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Aggregate {
+    /// Sum of numeric values
+    Sum,
+    /// Mean of numeric values
+    Mean,
+}
+
+/// Parse a lower date bound; a bare date means the start of that day
+fn parse_since(s: &str) -> std::result::Result<DateTime<Utc>, String> {
+    parse_date_bound(s, false)
+}
+
+/// Parse an upper date bound; a bare date means the end of that day
+fn parse_until(s: &str) -> std::result::Result<DateTime<Utc>, String> {
+    parse_date_bound(s, true)
+}
+
+fn parse_date_bound(s: &str, end_of_day: bool) -> std::result::Result<DateTime<Utc>, String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date '{s}' (expected YYYY-MM-DD or RFC 3339)"))?;
+    let time = if end_of_day {
+        NaiveTime::from_hms_nano_opt(23, 59, 59, 999_999_999)
+    } else {
+        NaiveTime::from_hms_opt(0, 0, 0)
+    };
+    Ok(date.and_time(time.expect("valid time")).and_utc())
+}
+
+/// Diff output format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiffFormat {
+    /// JSON format
+    Json,
+    /// Git-style unified diff
+    Git,
+}
+
+// ============================================================================
+// COMMAND IMPLEMENTATIONS
+// ============================================================================
+
+fn init_repository(path: &Path) -> Result<()> {
+    println!("Initializing git-sheets repository at {}", path.display());
+
+    // Create necessary directories
+    std::fs::create_dir_all(path.join("snapshots"))?;
+    std::fs::create_dir_all(path.join("diffs"))?;
+
+    // Create .gitignore if needed
+    let gitignore_path = path.join(".gitignore");
+    if !NO_GIT.load(Ordering::Relaxed) && !gitignore_path.exists() {
+        let mut gitignore = std::fs::File::create(gitignore_path)?;
+        writeln!(gitignore, "snapshots/")?;
+        writeln!(gitignore, "diffs/")?;
+        writeln!(gitignore, "*.toml")?;
+        writeln!(gitignore, "*.json")?;
+        writeln!(gitignore, ".gitsheets/lock")?;
+    }
+
+    Ok(())
+}
+
+fn create_snapshot(files: &[PathBuf], args: &SnapshotArgs) -> Result<()> {
+    println!("Creating snapshot of {}", files_label(files));
+    let prepared = prepare_table(files, args)?;
+    snapshot_table(files, args, prepared)
+}
+
+/// Snapshot each CSV file directly in `dir`, in name order
+///
+/// With `incremental`, a file is skipped when its table hashes the same as
+/// the latest snapshot of it, so nothing is built or written for it; with
+/// `logical` as well, when its logical hash is the same (falling back to the
+/// table hash for snapshots that have none). A file that fails is reported
+/// and the rest are still snapshotted.
+fn snapshot_dir(dir: &Path, incremental: bool, logical: bool, args: &SnapshotArgs) -> Result<()> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+        })
+        .collect();
+    files.sort();
+
+    // Latest table hash of every source, read once for the whole directory
+    let mut latest: HashMap<String, (DateTime<Utc>, TableHashes)> = HashMap::new();
+    if incremental {
+        let store = SnapshotStore::new("snapshots");
+        for path in snapshot_files(store.dir())? {
+            let Ok(snapshot) = store.load(&path) else {
+                continue;
+            };
+            let Some(source) = snapshot.source.as_deref().map(normalize_source) else {
+                continue;
+            };
+            if latest
+                .get(&source)
+                .is_none_or(|(timestamp, _)| snapshot.timestamp > *timestamp)
+            {
+                latest.insert(source, (snapshot.timestamp, snapshot.hashes.clone()));
+            }
+        }
+    }
+
+    let (mut unchanged, mut snapshotted, mut failed) = (0, 0, Vec::new());
+    for file in &files {
+        let result = prepare_table(std::slice::from_ref(file), args).and_then(|prepared| {
+            let unchanged_since = |hashes: &TableHashes| match &hashes.logical_hash {
+                Some(stored) if logical => *stored == logical_hash(&prepared.table),
+                _ => {
+                    TableHashes::compute_with_version(&prepared.table, hashes.version).table_hash
+                        == hashes.table_hash
+                }
+            };
+            if let Some((_, hashes)) = latest.get(&normalize_source(&file.display().to_string()))
+                && unchanged_since(hashes)
+            {
+                println!("Unchanged: {}", file.display());
+                unchanged += 1;
+                return Ok(());
+            }
+            println!("Creating snapshot of {}", file.display());
+            snapshot_table(std::slice::from_ref(file), args, prepared)?;
+            snapshotted += 1;
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("Error: {}: {e}", file.display());
+            failed.push(file);
+        }
+    }
+
+    print!("{unchanged} unchanged, {snapshotted} snapshotted");
+    if !failed.is_empty() {
+        print!(", {} failed", failed.len());
+    }
+    println!();
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(GitSheetsError::FileSystemError(format!(
+            "{} file(s) in {} could not be snapshotted",
+            failed.len(),
+            dir.display()
+        )))
+    }
+}
+
+/// A table loaded and shaped by the snapshot options, ready to snapshot
+struct PreparedTable {
+    table: Table,
+    normalizations: Vec<String>,
+    key_subset: Option<Vec<String>>,
+}
+
+/// Load `files`, one after another into a single table, and apply the
+/// primary key, ignored columns and key subset
+fn prepare_table(files: &[PathBuf], args: &SnapshotArgs) -> Result<PreparedTable> {
+    let label = files_label(files);
+    if files.len() > 1
+        && let Some(url) = files
+            .iter()
+            .find(|file| is_url(&file.display().to_string()))
+    {
+        return Err(GitSheetsError::FileSystemError(format!(
+            "{} can only be snapshotted on its own",
+            url.display()
+        )));
+    }
+
+    let mut loaded: Option<Table> = None;
+    let mut normalizations = Vec::new();
+    for file in files {
+        let (next, notes) = args.load.load(&file.display().to_string())?;
+        for note in notes {
+            let note = if files.len() > 1 {
+                format!("{}: {note}", file.display())
+            } else {
+                note
+            };
+            println!("Load: {note}");
+            normalizations.push(note);
+        }
+        match &mut loaded {
+            None => loaded = Some(next),
+            Some(table) => table.append_rows(next).map_err(|e| match e {
+                GitSheetsError::InvalidColumn(msg) => GitSheetsError::InvalidColumn(format!(
+                    "{} doesn't have the headers of {}: {msg}",
+                    file.display(),
+                    files[0].display()
+                )),
+                e => e,
+            })?,
+        }
+    }
+    let mut table = loaded.expect("clap requires at least one file");
+
+    // Set primary key if specified
+    if let Some(spec) = &args.primary_key {
+        table.set_primary_key(spec.resolve(&table)?);
+    }
+
+    // Drop ignored columns before hashing
+    let ignored: Vec<String> = args
+        .ignore_columns
+        .as_ref()
+        .map(|cols| cols.split(',').map(|s| s.trim().to_string()).collect())
+        .unwrap_or_default();
+    if !ignored.is_empty() {
+        table = table.drop_columns(&ignored)?;
+    }
+
+    // Restrict to the requested keyed rows
+    let mut key_subset = None;
+    if let Some(keys_str) = &args.keys {
+        let keys = parse_key_list(keys_str);
+        let (indices, missing) = table.rows_with_keys(&keys)?;
+        for key in &missing {
+            eprintln!("Warning: key '{}' not found in {label}", key.join("|"));
+        }
+        let found = keys
+            .iter()
+            .filter(|key| !missing.contains(&key.as_slice()))
+            .map(|key| key.join("|"))
+            .collect();
+        table = table.select_rows(&indices);
+        key_subset = Some(found);
+    }
+
+    Ok(PreparedTable {
+        table,
+        normalizations,
+        key_subset,
+    })
+}
+
+/// The files being snapshotted, for messages
+fn files_label(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .map(|file| file.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Validate, snapshot and save a prepared table of `files`
+///
+/// A table of one file records it as its source. A table concatenated from
+/// several has no single source to check out or refresh from, so each file
+/// is recorded as a dependency instead.
+fn snapshot_table(files: &[PathBuf], args: &SnapshotArgs, prepared: PreparedTable) -> Result<()> {
+    let PreparedTable {
+        table,
+        normalizations,
+        key_subset,
+    } = prepared;
+    let source = match files {
+        [file] => Some(normalize_source(&file.display().to_string())),
+        _ => None,
+    };
+    let fetched_at = source.as_deref().is_some_and(is_url).then(Utc::now);
+
+    // Advisory only, reported once the snapshot is taken
+    let quality_warnings = table.quality_warnings();
+
+    // Check the configured validation rules and the declared column types
+    let validators = Config::load(Path::new("."))?.validators_for(&table)?;
+    let mut violations = table.validate(&validators);
+    let schema = args
+        .schema
+        .as_deref()
+        .map(|path| Schema::load(Path::new(path)))
+        .transpose()?;
+    if let Some(schema) = &schema {
+        violations.extend(table.apply_schema(schema)?);
+    }
+    for violation in &violations {
+        eprintln!("Warning: {violation}");
+    }
+    if args.strict && !violations.is_empty() {
+        return Err(GitSheetsError::ValidationFailed(format!(
+            "{} cell(s) broke validation rules",
+            violations.len()
+        )));
+    }
+
+    // Create snapshot; the parent and id depend on what's already stored,
+    // so nothing else may store a snapshot until this one is written and
+    // committed. A dry run writes nothing and takes no lock.
+    let _lock = if args.dry_run {
+        None
+    } else {
+        Some(lock_repo()?)
+    };
+    let parent = match (&source, files) {
+        (Some(source), [file]) => {
+            // Older snapshots recorded the source as it was typed
+            let typed = file.display().to_string();
+            match Snapshot::latest_for_source(Path::new("snapshots"), source)? {
+                None if typed != *source => {
+                    Snapshot::latest_for_source(Path::new("snapshots"), &typed)?
+                }
+                latest => latest,
+            }
+        }
+        _ => None,
+    };
+
+    let mut snapshot = Snapshot::with_id_length(table, args.message.clone(), args.id_length);
+
+    // Extend the id if it collides with one already in the store
+    let mut storage = FsStorage::new("snapshots");
+    snapshot.disambiguate_id(&storage.list()?);
+    snapshot.author = match &args.author {
+        Some(author) => Some(author.clone()),
+        None if git_enabled()? => git_author(),
+        None => None,
+    };
+    snapshot.source = source;
+    if files.len() > 1 {
+        for file in files {
+            snapshot.add_file_dependency(file)?;
+        }
+        // Relative to the repository root, so any directory or checkout finds them
+        for dep in &mut snapshot.dependencies {
+            if let Some(path) = dep.path.take() {
+                dep.path = Some(relative_to_repo_root(&path).unwrap_or(path));
+            }
+        }
+    }
+    snapshot.fetched_at = fetched_at;
+    snapshot.parent = parent.as_ref().map(|p| p.id.clone());
+    snapshot.key_subset = key_subset;
+    snapshot.normalizations = normalizations;
+    snapshot.quality_warnings = quality_warnings;
+    snapshot.synthetic_headers = args.load.no_header;
+    snapshot.column_types = schema.map(|schema| schema.columns).unwrap_or_default();
+    if args.row_hashes {
+        snapshot.store_row_hashes();
+    }
+
+    if args.dry_run {
+        print_dry_run(&snapshot, Config::load(Path::new("."))?.hash_display_len());
+        if !args.no_warnings {
+            print_quality_report(&snapshot.quality_warnings);
+        }
+        return Ok(());
+    }
+
+    if args.delta {
+        match &parent {
+            Some(parent) => {
+                let snapshots_dir = Path::new("snapshots");
+                let resolver = |id: &str| Snapshot::load(&snapshot_path(snapshots_dir, id));
+                let mut base = parent.clone();
+                base.table = parent.materialize(&resolver)?;
+                match snapshot.make_delta(&base) {
+                    Ok(()) => println!("Storing changes from {}", base.id),
+                    Err(e) => eprintln!("Warning: storing full snapshot: {e}"),
+                }
+            }
+            None => eprintln!(
+                "Warning: no earlier snapshot of {} to store changes from; storing full snapshot",
+                files_label(files)
+            ),
+        }
+    }
+
+    // Save snapshot
+    snapshot.seal()?;
+    let bytes = if args.json {
+        snapshot.to_json()?
+    } else {
+        snapshot.to_toml()?
+    };
+    storage.put(&snapshot.id, &bytes)?;
+    let saved_path = snapshot_path(storage.dir(), &snapshot.id);
+
+    println!("Snapshot created: {}", snapshot.id);
+    if !args.no_warnings {
+        print_quality_report(&snapshot.quality_warnings);
+    }
+
+    if args.auto_commit && !git_enabled()? {
+        eprintln!("Warning: git integration disabled, auto-commit skipped");
+    } else if args.auto_commit {
+        // The snapshot is already written, so a failed commit only warns
+        match commit_snapshot(&saved_path, &snapshot.id) {
+            Ok(Some(commit_id)) => println!("Auto-commit performed: {}", commit_id),
+            Ok(None) => eprintln!("Warning: Git repository not found, auto-commit skipped"),
+            Err(e) => eprintln!(
+                "Warning: auto-commit failed, {} was written but not committed: {e}",
+                saved_path.display()
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Commit a saved snapshot file, or `None` if there is no repository here
+fn commit_snapshot(saved_path: &Path, id: &str) -> Result<Option<git2::Oid>> {
+    let Ok(repo) = git2::Repository::open(".") else {
+        return Ok(None);
+    };
+    let mut index = repo.index()?;
+
+    // Add the snapshot file to the index
+    index.add_path(saved_path)?;
+    index.write()?;
+
+    // Create commit on top of HEAD, if the repository has one yet
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(_) => None,
+    };
+    let author = git2::Signature::now("git-sheets", "git-sheets@localhost")?;
+    let committer = author.clone();
+    let commit_id = repo.commit(
+        Some("HEAD"),
+        &author,
+        &committer,
+        &format!("Snapshot: {id}"),
+        &tree,
+        &parent.iter().collect::<Vec<_>>(),
+    )?;
+    Ok(Some(commit_id))
+}
+
+/// Whether a snapshot source is a URL rather than a local path
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// A snapshot source as it is stored and looked up
+///
+/// A file inside the working directory, the repository root, is named
+/// relative to it, so `data.csv`, `./data.csv` and its absolute path are one
+/// source; other files by their absolute path. URLs are kept as given.
+fn normalize_source(source: &str) -> String {
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) if !is_url(source) => cwd,
+        _ => return source.to_string(),
+    };
+    let path = Path::new(source);
+    let absolute = path
+        .canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| cwd.join(path));
+    let root = cwd.canonicalize().unwrap_or(cwd);
+    match absolute.strip_prefix(&root) {
+        Ok(relative) => relative.display().to_string(),
+        Err(_) => absolute.display().to_string(),
+    }
+}
+
+/// Load a table from a local CSV file or a CSV export URL
+fn load_table(source: &str, options: &LoadOptions) -> Result<(Table, Vec<String>)> {
+    if !is_url(source) {
+        return Table::from_csv_with(Path::new(source), options);
+    }
+
+    #[cfg(feature = "network")]
+    {
+        Table::from_url_with(source, options)
+    }
+    #[cfg(not(feature = "network"))]
+    {
+        Err(GitSheetsError::NetworkError(format!(
+            "cannot fetch {source}: git-sheets was built without the `network` feature"
+        )))
+    }
+}
+
+/// The user's identity from git config, as "Name <email>"
+///
+/// Uses whichever of `user.name` and `user.email` are set, or `None` if
+/// neither is.
+fn git_author() -> Option<String> {
+    let config = git2::Config::open_default().ok()?;
+    let name = config.get_string("user.name").ok();
+    let email = config.get_string("user.email").ok();
+    match (name, email) {
+        (Some(name), Some(email)) => Some(format!("{name} <{email}>")),
+        (Some(name), None) => Some(name),
+        (None, Some(email)) => Some(format!("<{email}>")),
+        (None, None) => None,
+    }
+}
+
+/// Print the data-quality problems found in a snapshot's table, if any
+fn print_quality_report(warnings: &[String]) {
+    if warnings.is_empty() {
+        return;
+    }
+    println!("\nData quality ({} warning(s)):", warnings.len());
+    for warning in warnings {
+        println!("  - {warning}");
+    }
+}
+
+fn print_dry_run(snapshot: &Snapshot, hash_len: usize) {
+    let table = &snapshot.table;
+    println!("Dry run: nothing written");
+    println!("  Would create: {}", snapshot.id);
+    println!("  Columns: {}", table.headers.len());
+    println!("  Rows: {}", table.rows.len());
+    println!("  Table hash: {}", snapshot.hashes.short(hash_len));
+    if let Some(author) = &snapshot.author {
+        println!("  Author: {author}");
+    }
+    if let Some(parent) = &snapshot.parent {
+        println!("  Parent: {parent}");
+    }
+    if let Some(fetched_at) = snapshot.fetched_at {
+        println!("  Fetched: {}", fetched_at.to_rfc3339());
+    }
+
+    if let Some(pk) = &table.primary_key {
+        let out_of_range: Vec<usize> = pk
+            .iter()
+            .copied()
+            .filter(|&idx| idx >= table.headers.len())
+            .collect();
+        if !out_of_range.is_empty() {
+            println!("  Primary key: column index {out_of_range:?} out of range");
+        } else {
+            let names: Vec<&str> = pk.iter().map(|&idx| table.headers[idx].as_str()).collect();
+            match table.duplicate_keys() {
+                Ok(dups) if dups.is_empty() => {
+                    println!("  Primary key: {} (unique)", names.join(", "))
+                }
+                Ok(dups) => println!(
+                    "  Primary key: {} ({} duplicate keys, e.g. {})",
+                    names.join(", "),
+                    dups.len(),
+                    dups[0].join("|")
+                ),
+                Err(e) => println!("  Primary key: {e}"),
+            }
+        }
+    }
+}
+
+/// Run the diff command, writing to `--output` or stdout
+fn run_diff(from: &Path, to: Option<&Path>, args: &DiffArgs) -> Result<()> {
+    let (snapshot1, snapshot2) = match to {
+        Some(to) => (
+            Snapshot::load_materialized(from)?,
+            Snapshot::load_materialized(to)?,
+        ),
+        None => {
+            let snapshot = Snapshot::load_materialized(from)?;
+            match &args.git_rev {
+                Some(rev) => (snapshot_at_revision(from, rev)?, snapshot),
+                None => (head_snapshot(&snapshot)?, snapshot),
+            }
+        }
+    };
+
+    // Render --output in memory, so a failed diff leaves an existing file alone
+    let mut rendered = Vec::new();
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(_) => Box::new(&mut rendered),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    if args.unordered && snapshot1.table.equals_ignoring_order(&snapshot2.table) {
+        writeln!(
+            out,
+            "Snapshots are logically identical (ignoring row and column order)"
+        )?;
+    } else if args.interactive {
+        review_changes(&mut out, &snapshot1, &snapshot2, args)?;
+    } else if args.append_only {
+        show_appended_rows(&mut out, &snapshot1, &snapshot2, args)?;
+    } else if args.summary_only {
+        show_diff_summary(&mut out, &snapshot1, &snapshot2, args)?;
+    } else if args.keys_only {
+        show_key_changes(&mut out, &snapshot1, &snapshot2, args)?;
+    } else if args.stat {
+        show_diff_stat(&mut out, &snapshot1, &snapshot2, args)?;
+    } else {
+        show_diff(&mut out, &snapshot1, &snapshot2, args)?;
+    }
+    out.flush()?;
+    drop(out);
+
+    if let Some(path) = &args.output {
+        let file = Path::new(path);
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        write_atomic(file, &rendered)?;
+        println!("Diff written to {path}");
+    }
+    check_thresholds(&snapshot1, &snapshot2, args)
+}
+
+/// Fail if the diff changed more rows than `--threshold-rows` or
+/// `--threshold-percent` allow, and report the verdict on stderr
+///
+/// The percentage is taken of FROM's row count, so growing an empty table
+/// exceeds any percentage threshold.
+fn check_thresholds(from: &Snapshot, to: &Snapshot, args: &DiffArgs) -> Result<()> {
+    if args.threshold_rows.is_none() && args.threshold_percent.is_none() {
+        return Ok(());
+    }
+
+    let changed = SnapshotDiff::changed_row_count(from, to, &args.options()?)?;
+    let percent = if from.table.rows.is_empty() {
+        if changed == 0 { 0.0 } else { f64::INFINITY }
+    } else {
+        changed as f64 * 100.0 / from.table.rows.len() as f64
+    };
+    let measured = if percent.is_finite() {
+        format!(
+            "{changed} rows changed ({percent:.2}% of {})",
+            from.table.rows.len()
+        )
+    } else {
+        format!("{changed} rows changed (FROM has no rows)")
+    };
+
+    let mut exceeded = Vec::new();
+    if let Some(limit) = args.threshold_rows
+        && changed > limit
+    {
+        exceeded.push(format!("more than {limit} rows"));
+    }
+    if let Some(limit) = args.threshold_percent
+        && percent > limit
+    {
+        exceeded.push(format!("more than {limit}%"));
+    }
+
+    if exceeded.is_empty() {
+        eprintln!("Within threshold: {measured}");
+        Ok(())
+    } else {
+        Err(GitSheetsError::ThresholdExceeded(format!(
+            "{measured}, {}",
+            exceeded.join(" and ")
+        )))
+    }
+}
+
+/// A snapshot file as committed at git revision `rev`
+///
+/// Delta parents are read at the same revision, so the table is the one the
+/// file described then.
+fn snapshot_at_revision(path: &Path, rev: &str) -> Result<Snapshot> {
+    require_git("--git-rev")?;
+    let at_revision =
+        |path: &Path| -> Result<Snapshot> { Snapshot::from_bytes(&read_at_revision(path, rev)?) };
+
+    let mut snapshot = at_revision(path)?;
+    if snapshot.delta.is_some() {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        snapshot.table = snapshot.materialize(&|id| at_revision(&snapshot_path(dir, id)))?;
+    }
+    snapshot.id = format!("{}@{rev}", snapshot.id);
+    Ok(snapshot)
+}
+
+/// The committed version of a snapshot's source file, as an unsaved snapshot
+///
+/// The table takes the snapshot's primary key, matched by column name.
+fn head_snapshot(snapshot: &Snapshot) -> Result<Snapshot> {
+    let source = match snapshot.source.as_deref() {
+        Some(source) if !is_url(source) => source,
+        Some(source) => {
+            return Err(GitSheetsError::FileSystemError(format!(
+                "Snapshot {} was taken from {source}, which is not a file in git",
+                snapshot.id
+            )));
+        }
+        None => {
+            return Err(GitSheetsError::FileSystemError(format!(
+                "Snapshot {} does not record its source file",
+                snapshot.id
+            )));
+        }
+    };
+
+    require_git("--vs-head")?;
+    let bytes = read_committed(Path::new(source))?;
+    let options = LoadOptions {
+        null_tokens: Config::load(Path::new("."))?.null_tokens,
+        no_header: snapshot.synthetic_headers,
+        ..LoadOptions::default()
+    };
+    let (mut table, _) = Table::from_csv_bytes(bytes, &options)?;
+    let key: Option<Vec<usize>> = snapshot.table.primary_key_names().and_then(|names| {
+        names
+            .iter()
+            .map(|name| table.headers.iter().position(|h| h == name))
+            .collect()
+    });
+    if let Some(key) = key {
+        table.set_primary_key(key);
+    }
+
+    let mut head = Snapshot::new(table, None);
+    head.id = format!("HEAD:{source}");
+    head.source = Some(source.to_string());
+    Ok(head)
+}
+
+fn show_diff(
+    out: &mut dyn Write,
+    snapshot1: &Snapshot,
+    snapshot2: &Snapshot,
+    args: &DiffArgs,
+) -> Result<()> {
+    // Keep stdout parseable for machine-readable formats
+    if !matches!(args.format(), "json" | "ndjson") {
+        println!("Computing diff...");
+    }
+
+    let opts = args.options()?;
+    warn_on_key_mismatch(snapshot1, snapshot2, &opts)?;
+
+    let color = args.color();
+    let weights = Config::load(Path::new("."))?.severity;
+    let output = ChangeOutput {
+        color,
+        limit: args.limit_changes,
+        hide_whitespace: args.hide_whitespace_changes,
+        only: args.only.map(EditSize::kind),
+        severity: args.sort_by_severity.then_some(&weights),
+    };
+    match args.format() {
+        "json" => {
+            let diff = SnapshotDiff::compute_with(snapshot1, snapshot2, &opts)?;
+            let json_string = args.json(&diff)?;
+            writeln!(out, "{json_string}")?;
+        }
+        "ndjson" => print_diff_ndjson(out, snapshot1, snapshot2, &opts)?,
+        "git" => print_diff_git(out, snapshot1, snapshot2, &opts, &output)?,
+        "side-by-side" => {
+            let matching = RowMatching::resolve(&snapshot1.table, &snapshot2.table, &opts)?;
+            let columns = SideColumns::new(&snapshot1.table, &snapshot2.table);
+            let window = (args.start_col, args.max_cols);
+            if args.transpose {
+                print_transposed(
+                    out,
+                    &snapshot1.table,
+                    &snapshot2.table,
+                    &matching,
+                    &columns,
+                    window,
+                    color,
+                )?;
+            } else {
+                print_side_by_side(
+                    out,
+                    &snapshot1.table,
+                    &snapshot2.table,
+                    &matching,
+                    &columns,
+                    window,
+                    color,
+                )?;
+            }
+        }
+        _ => {
+            // Default to text format
+            let context = (args.context > 0).then_some(DiffContext {
+                from: &snapshot1.table,
+                to: &snapshot2.table,
+                rows: args.context,
+            });
+            print_diff_text(out, snapshot1, snapshot2, &opts, &output, context)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Warn when differing primary keys force rows to be compared by position,
+/// and about ignored keys that match no row
+fn warn_on_key_mismatch(from: &Snapshot, to: &Snapshot, opts: &DiffOptions) -> Result<()> {
+    for key in SnapshotDiff::unmatched_ignored_keys(from, to, opts)? {
+        eprintln!(
+            "Warning: ignored key '{}' is in neither snapshot",
+            key.join("|")
+        );
+    }
+    if RowMatching::resolve(&from.table, &to.table, opts)? == RowMatching::Position
+        && from.table.primary_key_names() != to.table.primary_key_names()
+    {
+        let describe = |table: &Table| {
+            table
+                .primary_key_names()
+                .map_or("none".to_string(), |names| names.join(", "))
+        };
+        eprintln!(
+            "Warning: primary keys differ ({}: {}; {}: {}); comparing rows by position (use --force-key to pick one)",
+            from.id,
+            describe(&from.table),
+            to.id,
+            describe(&to.table)
+        );
+    }
+    Ok(())
+}
+
+fn show_diff_chain(files: &[String], color: bool) -> Result<()> {
+    let store = SnapshotStore::default();
+    let snapshots = files
+        .iter()
+        .map(|file| store.load_materialized(Path::new(file)))
+        .collect::<Result<Vec<_>>>()?;
+
+    println!("Timeline:");
+    for pair in snapshots.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        warn_on_key_mismatch(from, to, &DiffOptions::default())?;
+        let diff = SnapshotDiff::compute(from, to)?;
+
+        // Replaying the hop must land on the exact target table
+        let replayed = diff
+            .apply(&from.table)
+            .map(|table| {
+                TableHashes::compute_with_version(&table, to.hashes.version).table_hash
+                    == to.hashes.table_hash
+            })
+            .unwrap_or(false);
+
+        let s = &diff.summary;
+        println!(
+            "  {} -> {}: rows +{} -{} ~{}, columns +{} -{}{}",
+            from.id,
+            to.id,
+            s.rows_added,
+            s.rows_removed,
+            s.rows_modified,
+            s.columns_added,
+            s.columns_removed,
+            if replayed {
+                ""
+            } else {
+                " (not fully replayable)"
+            }
+        );
+    }
+
+    let (first, last) = (&snapshots[0], &snapshots[snapshots.len() - 1]);
+    println!();
+    println!("Net change:");
+    // Columns may have come and gone along the way, so line them up by name
+    let opts = DiffOptions::default().with_schema_reconciliation();
+    warn_on_key_mismatch(first, last, &opts)?;
+    let output = ChangeOutput {
+        color,
+        ..ChangeOutput::default()
+    };
+    print_diff_text(
+        &mut std::io::stdout().lock(),
+        first,
+        last,
+        &opts,
+        &output,
+        None,
+    )
+}
+
+fn normalize_csv(
+    input: &str,
+    output: Option<&Path>,
+    load: &LoadArgs,
+    format: &CsvFormatArgs,
+) -> Result<()> {
+    let (table, normalizations) = load.load(input)?;
+
+    // Notes go to stderr so they never mix with CSV written to stdout
+    for note in &normalizations {
+        eprintln!("Load: {note}");
+    }
+    format.write(&table, !load.no_header, output)?;
+    if let Some(path) = output {
+        eprintln!("Normalized {input} to {}", path.display());
+    }
+    Ok(())
+}
+
+fn restore_snapshot(snapshot: &Snapshot, target: &RestoreTarget) -> Result<()> {
+    let path = match (&target.output, &target.output_dir) {
+        (Some(output), _) => Path::new(output).to_path_buf(),
+        (None, Some(dir)) => {
+            std::fs::create_dir_all(dir)?;
+            Path::new(dir).join(format!("{}.csv", restore_file_stem(snapshot)))
+        }
+        (None, None) => {
+            return target
+                .format
+                .write(&snapshot.table, !snapshot.synthetic_headers, None);
+        }
+    };
+
+    target
+        .format
+        .write(&snapshot.table, !snapshot.synthetic_headers, Some(&path))?;
+    println!(
+        "Restored {} ({} rows) to {}",
+        snapshot.id,
+        snapshot.table.rows.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Write a snapshot's table to its source file (or `as_path`)
+///
+/// Refuses to replace a file whose contents differ from what git has at
+/// HEAD, or that git doesn't track, unless `force` is set.
+fn checkout_snapshot(
+    path: &Path,
+    as_path: Option<&str>,
+    force: bool,
+    format: &CsvFormatArgs,
+) -> Result<()> {
+    let snapshot = Snapshot::load_materialized(path)?;
+    let target = match (as_path, snapshot.source.as_deref()) {
+        (Some(target), _) => target,
+        (None, Some(source)) if !is_url(source) => source,
+        (None, Some(source)) => {
+            return Err(GitSheetsError::FileSystemError(format!(
+                "Snapshot {} was taken from {source}; choose a file with --as",
+                snapshot.id
+            )));
+        }
+        (None, None) => {
+            return Err(GitSheetsError::FileSystemError(format!(
+                "Snapshot {} does not record its source file; choose one with --as",
+                snapshot.id
+            )));
+        }
+    };
+    let target = Path::new(target);
+
+    if !force && target.exists() && !git_enabled()? {
+        return Err(GitSheetsError::GitDisabled(format!(
+            "{} already exists and git integration is disabled, so its changes can't be checked; use --force",
+            target.display()
+        )));
+    }
+    if !force && target.exists() {
+        let committed = read_committed(target).ok();
+        if committed.as_deref() != Some(std::fs::read(target)?.as_slice()) {
+            return Err(GitSheetsError::FileSystemError(format!(
+                "{} has changes not committed to git; commit them or use --force",
+                target.display()
+            )));
+        }
+    }
+
+    if let Some(dir) = target.parent()
+        && !dir.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(dir)?;
+    }
+    format.write(&snapshot.table, !snapshot.synthetic_headers, Some(target))?;
+    println!(
+        "Checked out {} ({} rows) to {}",
+        snapshot.id,
+        snapshot.table.rows.len(),
+        target.display()
+    );
+    Ok(())
+}
+
+/// Rewrite the snapshot at `path` with a new message and, with `refresh`,
+/// the current contents of its source
+fn amend_snapshot(
+    path: &Path,
+    message: Option<String>,
+    refresh: bool,
+    force: bool,
+    load: &LoadArgs,
+) -> Result<()> {
+    let _lock = lock_repo()?;
+    let snapshot = Snapshot::load(path)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut children = Vec::new();
+    for file in snapshot_files(dir)? {
+        if let Ok(other) = Snapshot::load_unverified(&file)
+            && other.parent.as_deref() == Some(snapshot.id.as_str())
+        {
+            children.push(other.id);
+        }
+    }
+    if !children.is_empty() && !force {
+        return Err(GitSheetsError::FileSystemError(format!(
+            "{} is the parent of {}; amending it may break their chain (use --force)",
+            snapshot.id,
+            children.join(", ")
+        )));
+    }
+
+    let mut amended = if refresh {
+        refreshed_snapshot(&snapshot, dir, load)?
+    } else {
+        snapshot.clone()
+    };
+    if message.is_some() {
+        amended.message = message;
+    }
+    amended.seal()?;
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("toml");
+    let amended_path = dir.join(format!("{}.{extension}", amended.id));
+    amended.save(&amended_path)?;
+    if amended_path != path {
+        std::fs::remove_file(path)?;
+        println!("Amended {} (now {})", snapshot.id, amended.id);
+        for child in &children {
+            eprintln!("Warning: {child} still names {} as its parent", snapshot.id);
+        }
+    } else {
+        println!("Amended {}", snapshot.id);
+    }
+    Ok(())
+}
+
+/// Set the primary key of the snapshot at `path` and rewrite it
+///
+/// The key only decides how rows are matched, so the table hash and id are
+/// unchanged and snapshots naming this one as their parent stay linked.
+/// Uniqueness is checked on the full table, also for delta snapshots.
+fn rekey_snapshot(path: &Path, key: &PrimaryKeySpec) -> Result<()> {
+    let _lock = lock_repo()?;
+    let mut snapshot = Snapshot::load(path)?;
+    let mut table = Snapshot::load_materialized(path)?.table;
+    // Named from the full table: a delta snapshot stores no headers
+    let old = table.primary_key_names().map(|names| names.join(", "));
+    let columns = key.resolve(&table)?;
+    table.set_primary_key(columns.clone());
+
+    let duplicates = table.duplicate_keys()?;
+    if let Some(first) = duplicates.first() {
+        return Err(GitSheetsError::ValidationFailed(format!(
+            "{} is not a unique key of {}: {} key(s) appear on more than one row, e.g. '{}'",
+            table.primary_key_names().unwrap_or_default().join(", "),
+            snapshot.id,
+            duplicates.len(),
+            first.join("|")
+        )));
+    }
+
+    snapshot.table.set_primary_key(columns);
+    snapshot.seal()?;
+    snapshot.save(path)?;
+
+    let new = table.primary_key_names().unwrap_or_default().join(", ");
+    match old {
+        Some(old) => println!("Re-keyed {} from {old} to {new}", snapshot.id),
+        None => println!("Keyed {} by {new}", snapshot.id),
+    }
+    Ok(())
+}
+
+/// A new full snapshot of `snapshot`'s source as it is now, with the same
+/// primary key, parent, author and dependencies
+fn refreshed_snapshot(snapshot: &Snapshot, dir: &Path, load: &LoadArgs) -> Result<Snapshot> {
+    let Some(source) = snapshot.source.as_deref() else {
+        return Err(GitSheetsError::FileSystemError(format!(
+            "Snapshot {} does not record its source file",
+            snapshot.id
+        )));
+    };
+    if snapshot.key_subset.is_some() {
+        return Err(GitSheetsError::FileSystemError(format!(
+            "Snapshot {} covers only some keys; take a new snapshot instead",
+            snapshot.id
+        )));
+    }
+
+    // A headerless source stays headerless, with or without --no-header
+    let load = if snapshot.synthetic_headers {
+        LoadArgs {
+            no_header: true,
+            ..load.clone()
+        }
+    } else {
+        load.clone()
+    };
+    let (mut table, normalizations) = load.load(source)?;
+    for note in &normalizations {
+        println!("Load: {note}");
+    }
+    if let Some(names) = snapshot.table.primary_key_names() {
+        let key = names
+            .iter()
+            .map(|name| {
+                table.headers.iter().position(|h| h == name).ok_or_else(|| {
+                    GitSheetsError::InvalidColumn(format!(
+                        "primary key column '{name}' is no longer in {source}"
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        table.set_primary_key(key);
+    }
+
+    // `<timestamp>-<hash>`, possibly followed by a `-<counter>`
+    let id_length = snapshot
+        .id
+        .split('-')
+        .nth(1)
+        .map_or(DEFAULT_ID_HASH_LEN, str::len);
+    let mut refreshed = Snapshot::with_id_length(table, snapshot.message.clone(), id_length);
+    let mut existing = FsStorage::new(dir).list()?;
+    existing.retain(|id| *id != snapshot.id);
+    refreshed.disambiguate_id(&existing);
+    refreshed.author = snapshot.author.clone();
+    refreshed.source = snapshot.source.clone();
+    refreshed.fetched_at = is_url(source).then(Utc::now);
+    refreshed.parent = snapshot.parent.clone();
+    refreshed.dependencies = snapshot.dependencies.clone();
+    refreshed.normalizations = normalizations;
+    refreshed.quality_warnings = refreshed.table.quality_warnings();
+    refreshed.synthetic_headers = load.no_header;
+    refreshed.column_types = snapshot.column_types.clone();
+    if snapshot.hashes.row_hashes.is_some() {
+        refreshed.store_row_hashes();
+    }
+    Ok(refreshed)
+}
+
+/// Export the table of the snapshot at `path` to a Parquet file
+fn export_snapshot(path: &Path, output: &Path) -> Result<()> {
+    #[cfg(feature = "parquet")]
+    {
+        let snapshot = Snapshot::load_materialized(path)?;
+        for warning in snapshot.table.write_parquet(output)? {
+            eprintln!("Warning: {warning}");
+        }
+        println!(
+            "Exported {} ({} rows) to {}",
+            snapshot.id,
+            snapshot.table.rows.len(),
+            output.display()
+        );
+        Ok(())
+    }
+    #[cfg(not(feature = "parquet"))]
+    {
+        Err(GitSheetsError::ExportError(format!(
+            "cannot export {} to {}: git-sheets was built without the `parquet` feature",
+            path.display(),
+            output.display()
+        )))
+    }
+}
+
+/// Restore the parent of the snapshot at `path`
+fn undo_snapshot(path: &Path, target: &RestoreTarget) -> Result<()> {
+    let snapshot = Snapshot::load(path)?;
+    let Some(parent_id) = &snapshot.parent else {
+        return Err(GitSheetsError::NoParent(format!(
+            "Snapshot {} has no parent to undo to",
+            snapshot.id
+        )));
+    };
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let parent_path = snapshot_path(dir, parent_id);
+    if !parent_path.exists() {
+        return Err(GitSheetsError::FileSystemError(format!(
+            "Parent snapshot '{parent_id}' not found"
+        )));
+    }
+    let parent = Snapshot::load_materialized(&parent_path)?;
+    restore_snapshot(&parent, target)
+}
+
+/// File name (without extension) for a restored table
+///
+/// Named after the file the snapshot was taken from, falling back to the
+/// snapshot id. Characters that aren't safe in file names become `_`.
+fn restore_file_stem(snapshot: &Snapshot) -> String {
+    let name = snapshot
+        .source
+        .as_deref()
+        .and_then(|source| Path::new(source).file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| snapshot.id.clone());
+    sanitize_file_name(&name)
+}
+
+/// Replace characters that are reserved in file names on common platforms
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if cleaned.is_empty() {
+        "table".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+fn export_changelog(col: &str, output: Option<&Path>) -> Result<()> {
+    let snapshots_dir = Path::new("snapshots");
+    let store = SnapshotStore::new(snapshots_dir);
+    let mut snapshots = Vec::new();
+    for path in snapshot_files(snapshots_dir)? {
+        match store.load_materialized(&path) {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(e) => eprintln!("Warning: Could not load snapshot from {:?}: {}", path, e),
+        }
+    }
+    snapshots.sort_by_key(|s| s.timestamp);
+
+    // Render a file in memory, so a failed export leaves the old one alone
+    let mut rendered = Vec::new();
+    let sink: Box<dyn Write> = match output {
+        Some(_) => Box::new(&mut rendered),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut writer = csv::Writer::from_writer(sink);
+    writer.write_record(["snapshot_id", "timestamp", "row_key", "old", "new"])?;
+
+    let mut rows = 0;
+    for snapshot in &snapshots {
+        // Each snapshot is compared with its parent; the first of a chain has
+        // nothing to compare against
+        let Some(parent) = snapshot
+            .parent
+            .as_ref()
+            .and_then(|id| snapshots.iter().find(|s| &s.id == id))
+        else {
+            continue;
+        };
+
+        match SnapshotDiff::column_changes(parent, snapshot, col) {
+            Ok(changes) => {
+                for change in changes {
+                    writer.write_record([
+                        snapshot.id.as_str(),
+                        &snapshot.timestamp.to_rfc3339(),
+                        &change.key.join("|"),
+                        change.old.as_deref().unwrap_or(""),
+                        change.new.as_deref().unwrap_or(""),
+                    ])?;
+                    rows += 1;
+                }
+            }
+            Err(e) => eprintln!("Warning: skipping {}: {e}", snapshot.id),
+        }
+    }
+    writer.flush()?;
+    drop(writer);
+
+    if let Some(path) = output {
+        write_atomic(path, &rendered)?;
+        println!("Wrote {rows} changes to {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// List every snapshot in snapshots/, oldest first, as a CSV catalog
+fn export_catalog(output: Option<&Path>) -> Result<()> {
+    let snapshots_dir = Path::new("snapshots");
+    let store = SnapshotStore::new(snapshots_dir);
+    let mut snapshots = Vec::new();
+    for path in snapshot_files(snapshots_dir)? {
+        match store.load_materialized(&path) {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(e) => eprintln!("Warning: Could not load snapshot from {:?}: {}", path, e),
+        }
+    }
+    snapshots.sort_by_key(|s| s.timestamp);
+
+    // Render a file in memory, so a failed export leaves the old one alone
+    let mut rendered = Vec::new();
+    let sink: Box<dyn Write> = match output {
+        Some(_) => Box::new(&mut rendered),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut writer = csv::Writer::from_writer(sink);
+    writer.write_record([
+        "id",
+        "timestamp",
+        "message",
+        "author",
+        "source",
+        "rows",
+        "columns",
+        "table_hash",
+    ])?;
+    for snapshot in &snapshots {
+        writer.write_record([
+            snapshot.id.as_str(),
+            &snapshot.timestamp.to_rfc3339(),
+            snapshot.message.as_deref().unwrap_or(""),
+            snapshot.author.as_deref().unwrap_or(""),
+            snapshot.source.as_deref().unwrap_or(""),
+            &snapshot.table.rows.len().to_string(),
+            &snapshot.table.headers.len().to_string(),
+            &snapshot.hashes.table_hash,
+        ])?;
+    }
+    writer.flush()?;
+    drop(writer);
+
+    if let Some(path) = output {
+        write_atomic(path, &rendered)?;
+        println!("Wrote {} snapshots to {}", snapshots.len(), path.display());
+    }
+
+    Ok(())
+}
+
+fn show_diff_summary(
+    out: &mut dyn Write,
+    snapshot1: &Snapshot,
+    snapshot2: &Snapshot,
+    args: &DiffArgs,
+) -> Result<()> {
+    let opts = args.options()?;
+    warn_on_key_mismatch(snapshot1, snapshot2, &opts)?;
+    let summary = SnapshotDiff::summarize_with(snapshot1, snapshot2, &opts)?;
+
+    if args.format() == "json" {
+        writeln!(out, "{}", args.json(&summary)?)?;
+    } else {
+        writeln!(out, "Diff from {} to {}", snapshot1.id, snapshot2.id)?;
+        writeln!(out, "{summary}")?;
+    }
+
+    Ok(())
+}
+
+/// Widest `diff --stat` bar; larger counts are scaled down to fit
+const STAT_BAR_WIDTH: usize = 40;
+
+/// Bar lengths for (added, changed, removed) counts: the counts themselves,
+/// or scaled so `max_total` fills `STAT_BAR_WIDTH`, non-zero counts keeping
+/// at least one character
+fn stat_bar(counts: [usize; 3], max_total: usize) -> [usize; 3] {
+    if max_total <= STAT_BAR_WIDTH {
+        return counts;
+    }
+    counts.map(|count| match count {
+        0 => 0,
+        _ => (count * STAT_BAR_WIDTH / max_total).max(1),
+    })
+}
+
+fn show_diff_stat(
+    out: &mut dyn Write,
+    snapshot1: &Snapshot,
+    snapshot2: &Snapshot,
+    args: &DiffArgs,
+) -> Result<()> {
+    let opts = args.options()?;
+    warn_on_key_mismatch(snapshot1, snapshot2, &opts)?;
+    let stat = SnapshotDiff::stat(snapshot1, snapshot2, &opts)?;
+
+    if args.format() == "json" {
+        writeln!(out, "{}", args.json(&stat)?)?;
+        return Ok(());
+    }
+
+    let color = args.color();
+    let name_width = stat
+        .columns
+        .iter()
+        .map(|column| column.column.chars().count())
+        .max()
+        .unwrap_or_default();
+    let max_total = stat
+        .columns
+        .iter()
+        .map(|c| c.total())
+        .max()
+        .unwrap_or_default();
+    let count_width = max_total.to_string().len();
+    let segment = |mark: &str, len: usize, code: &str| match len {
+        0 => String::new(),
+        _ => paint(&mark.repeat(len), code, color),
+    };
+    for column in &stat.columns {
+        let [added, modified, removed] =
+            stat_bar([column.added, column.modified, column.removed], max_total);
+        writeln!(
+            out,
+            " {:name_width$} | {:>count_width$} {}{}{}",
+            column.column,
+            column.total(),
+            segment("+", added, GREEN),
+            segment("~", modified, YELLOW),
+            segment("-", removed, RED),
+        )?;
+    }
+    writeln!(
+        out,
+        " {} row(s) changed, {} cell(s) added(+), {} changed(~), {} removed(-)",
+        stat.rows_changed,
+        stat.cells_added(),
+        stat.cells_modified(),
+        stat.cells_removed()
+    )?;
+    Ok(())
+}
+
+fn show_key_changes(
+    out: &mut dyn Write,
+    snapshot1: &Snapshot,
+    snapshot2: &Snapshot,
+    args: &DiffArgs,
+) -> Result<()> {
+    let keys = SnapshotDiff::key_changes(snapshot1, snapshot2, &args.options()?)?;
+
+    if args.format() == "json" {
+        writeln!(out, "{}", args.json(&keys)?)?;
+        return Ok(());
+    }
+
+    let color = args.color();
+    writeln!(out, "Keys added ({}):", keys.added.len())?;
+    for key in &keys.added {
+        writeln!(out, "  {}", paint(&key.join("|"), GREEN, color))?;
+    }
+    writeln!(out, "Keys removed ({}):", keys.removed.len())?;
+    for key in &keys.removed {
+        writeln!(out, "  {}", paint(&key.join("|"), RED, color))?;
+    }
+    Ok(())
+}
+
+fn show_appended_rows(
+    out: &mut dyn Write,
+    snapshot1: &Snapshot,
+    snapshot2: &Snapshot,
+    args: &DiffArgs,
+) -> Result<()> {
+    let diff = SnapshotDiff::compute_append_only(snapshot1, snapshot2, &args.options()?)?;
+
+    if args.format() == "json" {
+        writeln!(out, "{}", args.json(&diff)?)?;
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "Diff from {} to {} (append-only)",
+        diff.from_id, diff.to_id
+    )?;
+    writeln!(out, "Rows appended: {}", diff.summary.rows_added)?;
+    if args.summary_only {
+        return Ok(());
+    }
+
+    let color = args.color();
+    let limit = args.limit_changes.unwrap_or(usize::MAX);
+    let mut changes = diff.changes.into_iter();
+    for change in changes.by_ref().take(limit) {
+        writeln!(out, "{}", paint(&change.to_string(), GREEN, color))?;
+    }
+    print_truncated(out, changes)
+}
+
+/// Leaves terminal raw mode when dropped, however the review ends
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<Self> {
+        crossterm::terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}
+
+/// What the reviewer decided for the change on screen
+enum Review {
+    Accept,
+    Reject,
+    AcceptRest,
+    RejectRest,
+}
+
+/// Wait for one of the review keys; Ctrl-C and Esc abort the review
+fn read_review_key() -> Result<Review> {
+    use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers, read};
+
+    loop {
+        let Event::Key(key) = read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Err(GitSheetsError::Cancelled("Review aborted".to_string()));
+            }
+            KeyCode::Esc => {
+                return Err(GitSheetsError::Cancelled("Review aborted".to_string()));
+            }
+            KeyCode::Char('y') => return Ok(Review::Accept),
+            KeyCode::Char('n') => return Ok(Review::Reject),
+            KeyCode::Char('a') => return Ok(Review::AcceptRest),
+            KeyCode::Char('d') | KeyCode::Char('q') => return Ok(Review::RejectRest),
+            _ => {}
+        }
+    }
+}
+
+/// Walk the reviewer through each change, then write the accepted ones
+///
+/// Prompts go to stderr so the resulting diff can be redirected on its own.
+fn review_changes(
+    out: &mut dyn Write,
+    from: &Snapshot,
+    to: &Snapshot,
+    args: &DiffArgs,
+) -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        return Err(GitSheetsError::TerminalRequired(
+            "--interactive needs a terminal".to_string(),
+        ));
+    }
+    let opts = args.options()?;
+    warn_on_key_mismatch(from, to, &opts)?;
+    let diff = SnapshotDiff::compute_with(from, to, &opts)?;
+    let total = diff.changes.len();
+    let color = std::io::stderr().is_terminal() && !args.no_color;
+
+    let mut accepted = Vec::new();
+    {
+        let _raw = RawMode::enable()?;
+        let mut changes = diff.changes.into_iter().enumerate();
+        while let Some((idx, change)) = changes.next() {
+            // Raw mode doesn't translate \n, so lines end in \r\n
+            let code = match &change {
+                Change::RowAdded { .. } | Change::ColumnAdded { .. } => GREEN,
+                Change::RowRemoved { .. } | Change::ColumnRemoved { .. } => RED,
+                _ => YELLOW,
+            };
+            eprint!(
+                "[{}/{total}] {}\r\n  accept? [y]es [n]o [a]ccept rest [d]rop rest  ",
+                idx + 1,
+                paint(&change.to_string(), code, color)
+            );
+            let review = read_review_key()?;
+            eprint!("\r\n");
+            match review {
+                Review::Accept => accepted.push(change),
+                Review::Reject => {}
+                Review::AcceptRest => {
+                    accepted.push(change);
+                    accepted.extend(changes.by_ref().map(|(_, change)| change));
+                }
+                Review::RejectRest => break,
+            }
+        }
+    }
+    eprintln!("Accepted {} of {total} changes", accepted.len());
+
+    let mut summary = DiffSummary::default();
+    for change in &accepted {
+        summary.record(change);
+    }
+    let reviewed = SnapshotDiff {
+        from_id: diff.from_id,
+        to_id: diff.to_id,
+        summary,
+        changes: accepted,
+    };
+    writeln!(out, "{}", args.json(&reviewed)?)?;
+    Ok(())
+}
+
+// ANSI colors used for diff output
+const GREEN: &str = "32";
+const RED: &str = "31";
+const YELLOW: &str = "33";
+const CYAN: &str = "36";
+
+/// Wrap text in an ANSI color escape when color is enabled
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Decide whether diff output should be colored
+///
+/// Color is used only when stdout is a terminal, `--no-color` was not given
+/// and `NO_COLOR` is not set.
+fn use_color(no_color: bool) -> bool {
+    !no_color && std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+fn change_color(change: &Change) -> &'static str {
+    match change {
+        Change::RowAdded { .. } | Change::ColumnAdded { .. } => GREEN,
+        Change::RowRemoved { .. } | Change::ColumnRemoved { .. } => RED,
+        Change::CellChanged { .. }
+        | Change::RowModified { .. }
+        | Change::ColumnRenamed { .. }
+        | Change::RowSplit { .. }
+        | Change::RowMerged { .. } => YELLOW,
+    }
+}
+
+/// How the text and git formats list changes
+#[derive(Default)]
+struct ChangeOutput<'a> {
+    color: bool,
+    /// Stop listing after this many changes
+    limit: Option<usize>,
+    /// Leave out whitespace-only cell changes
+    hide_whitespace: bool,
+    /// List only cell changes of this kind
+    only: Option<CellChangeKind>,
+    /// Group changes by row, worst row first
+    severity: Option<&'a SeverityWeights>,
+}
+
+impl ChangeOutput<'_> {
+    /// The changes to list, in order
+    ///
+    /// When sorting by severity, the first change of each row carries the
+    /// row's score. Otherwise changes stream as they are computed.
+    fn changes<'t>(
+        &self,
+        from: &'t Snapshot,
+        to: &'t Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<Box<dyn Iterator<Item = (Option<f64>, Change)> + 't>> {
+        let (hide_whitespace, only) = (self.hide_whitespace, self.only);
+        let changes = SnapshotDiff::iter_changes(from, to, opts)?
+            .filter(move |change| !(hide_whitespace && change.is_whitespace_only()))
+            .filter(move |change| only.is_none_or(|kind| change.cell_kind() == Some(kind)));
+        let Some(weights) = self.severity else {
+            return Ok(Box::new(changes.map(|change| (None, change))));
+        };
+        let ranked = rank_by_severity(changes, weights);
+        Ok(Box::new(ranked.into_iter().flat_map(|row| {
+            let score = row.score;
+            row.changes
+                .into_iter()
+                .enumerate()
+                .map(move |(i, change)| ((i == 0).then_some(score), change))
+        })))
+    }
+}
+
+/// Print changes as they are computed, in a unified-diff-like layout
+fn print_diff_git(
+    out: &mut dyn Write,
+    from: &Snapshot,
+    to: &Snapshot,
+    opts: &DiffOptions,
+    output: &ChangeOutput,
+) -> Result<()> {
+    let color = output.color;
+    writeln!(out, "--- {}", from.id)?;
+    writeln!(out, "+++ {}", to.id)?;
+    let mut changes = output.changes(from, to, opts)?.map(|(_, change)| change);
+    for change in changes.by_ref().take(output.limit.unwrap_or(usize::MAX)) {
+        let (hunk, removed, added) = match &change {
+            Change::RowAdded { index, data, .. } => (
+                format!("@@ -0 +{} @@", index + 1),
+                vec![],
+                vec![data.join("\t")],
+            ),
+            Change::RowRemoved { index, data, .. } => (
+                format!("@@ -{} +0 @@", index + 1),
+                vec![data.join("\t")],
+                vec![],
+            ),
+            Change::CellChanged {
+                row, col, old, new, ..
+            } => (
+                format!("@@ -{} +{} @@", row + 1, col + 1),
+                vec![old.clone()],
+                vec![new.clone()],
+            ),
+            Change::RowModified {
+                index,
+                old_data,
+                new_data,
+                new_index,
+                ..
+            } => (
+                format!("@@ -{} +{} @@", index + 1, new_index.unwrap_or(*index) + 1),
+                vec![old_data.join("\t")],
+                vec![new_data.join("\t")],
+            ),
+            Change::ColumnAdded { name, index } => (
+                format!("@@ -0 +{} @@", index + 1),
+                vec![],
+                vec![name.clone()],
+            ),
+            Change::ColumnRemoved { name, index } => (
+                format!("@@ -{} +0 @@", index + 1),
+                vec![name.clone()],
+                vec![],
+            ),
+            Change::ColumnRenamed {
+                from,
+                to,
+                from_index,
+                to_index,
+                ..
+            } => (
+                format!("@@ -{} +{} @@", from_index + 1, to_index + 1),
+                vec![from.clone()],
+                vec![to.clone()],
+            ),
+            Change::RowSplit { from, into } => (
+                format!(
+                    "@@ -{} +{},{} @@",
+                    from.index + 1,
+                    into[0].index + 1,
+                    into.len()
+                ),
+                vec![from.data.join("\t")],
+                into.iter().map(|row| row.data.join("\t")).collect(),
+            ),
+            Change::RowMerged { from, into } => (
+                format!(
+                    "@@ -{},{} +{} @@",
+                    from[0].index + 1,
+                    from.len(),
+                    into.index + 1
+                ),
+                from.iter().map(|row| row.data.join("\t")).collect(),
+                vec![into.data.join("\t")],
+            ),
+        };
+        writeln!(out, "{}", paint(&hunk, CYAN, color))?;
+        for line in removed {
+            writeln!(out, "{}", paint(&format!("-{line}"), RED, color))?;
+        }
+        for line in added {
+            writeln!(out, "{}", paint(&format!("+{line}"), GREEN, color))?;
+        }
+    }
+    print_truncated(out, changes)
+}
+
+/// Terminal width assumed when it can't be queried (e.g. output is piped)
+const DEFAULT_TERMINAL_WIDTH: usize = 120;
+
+/// Narrowest a side-by-side cell gets before columns are paged instead
+const MIN_CELL_WIDTH: usize = 8;
+
+fn terminal_width() -> usize {
+    terminal_size::terminal_size().map_or(DEFAULT_TERMINAL_WIDTH, |(terminal_size::Width(w), _)| {
+        w as usize
+    })
+}
+
+/// Columns of both tables lined up by name: every `from` column, then the
+/// columns only `to` has
+struct SideColumns<'a> {
+    names: Vec<&'a str>,
+    from: Vec<Option<usize>>,
+    to: Vec<Option<usize>>,
+}
+
+impl<'a> SideColumns<'a> {
+    fn new(from: &'a Table, to: &'a Table) -> Self {
+        let mut names: Vec<&str> = from.headers.iter().map(String::as_str).collect();
+        for header in &to.headers {
+            if !names.contains(&header.as_str()) {
+                names.push(header);
+            }
+        }
+        let index = |table: &Table| -> Vec<Option<usize>> {
+            names
+                .iter()
+                .map(|name| table.headers.iter().position(|h| h == name))
+                .collect()
+        };
+        let (from, to) = (index(from), index(to));
+        Self { names, from, to }
+    }
+
+    /// Indices of the columns in view, from `start`, at most `max` of them
+    ///
+    /// Says which slice of a wide table is shown, so the rest can be paged to.
+    fn window(
+        &self,
+        out: &mut dyn Write,
+        start: usize,
+        max: usize,
+    ) -> Result<std::ops::Range<usize>> {
+        let total = self.names.len();
+        if start >= total && total > 0 {
+            return Err(GitSheetsError::InvalidColumn(format!(
+                "--start-col {start} is past the last column ({})",
+                total - 1
+            )));
+        }
+        let end = start.saturating_add(max.max(1)).min(total);
+        if start > 0 || end < total {
+            write!(
+                out,
+                "Showing columns {start}-{} of {total} (counting from 0)",
+                end.saturating_sub(1)
+            )?;
+            if end < total {
+                write!(out, "; next page: --start-col {end}")?;
+            }
+            writeln!(out)?;
+        }
+        Ok(start..end)
+    }
+
+    /// Whether the cell in `col` differs between the paired rows
+    fn changed(
+        &self,
+        from: &Table,
+        to: &Table,
+        (old, new): (Option<usize>, Option<usize>),
+        col: usize,
+    ) -> bool {
+        side_cell(from, &self.from, old, col) != side_cell(to, &self.to, new, col)
+    }
+}
+
+/// Print both tables next to each other, rows aligned, changes marked
+///
+/// The gutter between the tables shows `-` for removed rows, `+` for added
+/// rows and `~` for rows with changed cells, in or out of view. Columns are
+/// lined up by name. When they don't all fit the terminal, a window of them
+/// is shown (`window` is the first column and the most to show); cells are
+/// cut to fit the width.
+fn print_side_by_side(
+    out: &mut dyn Write,
+    from: &Table,
+    to: &Table,
+    matching: &RowMatching,
+    all: &SideColumns,
+    (start, max_cols): (usize, Option<usize>),
+    color: bool,
+) -> Result<()> {
+    // Each side gets half the width left after the " ~ " gutter, and each
+    // column a share of that minus its separating space
+    let side = terminal_width().saturating_sub(3) / 2;
+    let fitting = (side / (MIN_CELL_WIDTH + 1)).max(1);
+    let visible = all.window(out, start, max_cols.unwrap_or(fitting))?;
+    let columns = &all.names[visible.clone()];
+    let (from_cols, to_cols) = (&all.from[visible.clone()], &all.to[visible.clone()]);
+    let cell_width = (side / columns.len().max(1)).saturating_sub(1).max(1);
+
+    let render = |cells: &[String]| {
+        let mut line = cells.join(" ");
+        let visible = (cells.len() * (cell_width + 1)).saturating_sub(1);
+        line.push_str(&" ".repeat(side.saturating_sub(visible)));
+        line
+    };
+    let fit = |text: &str, code: Option<&str>| {
+        let text = truncate(text, cell_width);
+        let padded = format!("{text:<cell_width$}");
+        match code {
+            Some(code) => paint(&padded, code, color),
+            None => padded,
+        }
+    };
+
+    let header = |cols: &[Option<usize>]| -> Vec<String> {
+        (0..columns.len())
+            .map(|col| match cols[col] {
+                Some(_) => fit(columns[col], None),
+                None => fit("", None),
+            })
+            .collect()
+    };
+    let line = format!(
+        "{}   {}",
+        render(&header(from_cols)),
+        render(&header(to_cols))
+    );
+    writeln!(out, "{}", line.trim_end())?;
+    writeln!(out, "{}   {}", "-".repeat(side), "-".repeat(side))?;
+
+    for (old, new) in matching.pair_rows(from, to) {
+        let changed: Vec<bool> = visible
+            .clone()
+            .map(|col| all.changed(from, to, (old, new), col))
+            .collect();
+        let (marker, code) = match (old, new) {
+            (Some(_), None) => ('-', Some(RED)),
+            (None, Some(_)) => ('+', Some(GREEN)),
+            _ if (0..all.names.len()).any(|col| all.changed(from, to, (old, new), col)) => {
+                ('~', None)
+            }
+            _ => (' ', None),
+        };
+        let side_cells = |table: &Table, cols: &[Option<usize>], row: Option<usize>| {
+            (0..columns.len())
+                .map(|col| {
+                    let code = code.or((marker == '~' && changed[col]).then_some(YELLOW));
+                    fit(side_cell(table, cols, row, col), code)
+                })
+                .collect::<Vec<_>>()
+        };
+        let line = format!(
+            "{} {} {}",
+            render(&side_cells(from, from_cols, old)),
+            marker,
+            render(&side_cells(to, to_cols, new))
+        );
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Print each pair of rows as a `column: old | new` list, one line per column
+///
+/// For records too wide to read across. Rows are marked as in
+/// [`print_side_by_side`], and `window` picks the columns listed (all of
+/// them by default).
+fn print_transposed(
+    out: &mut dyn Write,
+    from: &Table,
+    to: &Table,
+    matching: &RowMatching,
+    all: &SideColumns,
+    (start, max_cols): (usize, Option<usize>),
+    color: bool,
+) -> Result<()> {
+    let visible = all.window(out, start, max_cols.unwrap_or(usize::MAX))?;
+    let name_width = all.names[visible.clone()]
+        .iter()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let value_width = terminal_width().saturating_sub(name_width + 5) / 2;
+
+    for (old, new) in matching.pair_rows(from, to) {
+        let (marker, code) = match (old, new) {
+            (Some(_), None) => ('-', Some(RED)),
+            (None, Some(_)) => ('+', Some(GREEN)),
+            _ if (0..all.names.len()).any(|col| all.changed(from, to, (old, new), col)) => {
+                ('~', None)
+            }
+            _ => (' ', None),
+        };
+        let position = |row: Option<usize>| row.map_or("-".to_string(), |idx| idx.to_string());
+        writeln!(out, "{marker} row {} -> {}", position(old), position(new))?;
+
+        for col in visible.clone() {
+            let changed = marker == '~' && all.changed(from, to, (old, new), col);
+            let cell = |table: &Table, cols: &[Option<usize>], row: Option<usize>| {
+                let text = truncate(side_cell(table, cols, row, col), value_width);
+                let text = format!("{text:<value_width$}");
+                match code.or(changed.then_some(YELLOW)) {
+                    Some(code) => paint(&text, code, color),
+                    None => text,
+                }
+            };
+            let line = format!(
+                "  {:<name_width$}  {} | {}",
+                all.names[col],
+                cell(from, &all.from, old),
+                cell(to, &all.to, new)
+            );
+            writeln!(out, "{}", line.trim_end())?;
         }
     }
+    Ok(())
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    /// Initialize a new git-sheets repository
-    Init {
-        /// Path to initialize the repository
-        #[arg(value_name = "PATH")]
-        path: String,
-    },
+/// One cell of a side-by-side row, blank where the row or column is missing
+fn side_cell<'a>(
+    table: &'a Table,
+    cols: &[Option<usize>],
+    row: Option<usize>,
+    col: usize,
+) -> &'a str {
+    match (row, cols[col]) {
+        (Some(row), Some(c)) => table.rows[row].get(c).map_or("", String::as_str),
+        _ => "",
+    }
+}
 
-    /// Create a snapshot of a table
-    Snapshot {
-        /// Table file to snapshot
-        #[arg(value_name = "FILE")]
-        file: String,
+/// Cut text to at most `width` characters, marking the cut with `…`
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    }
+}
 
-        /// Commit message for the snapshot
-        #[arg(short, long)]
-        message: Option<String>,
+/// Report how many changes a `--limit-changes` cap left unprinted
+fn print_truncated(out: &mut dyn Write, rest: impl Iterator<Item = Change>) -> Result<()> {
+    let more = rest.count();
+    if more > 0 {
+        writeln!(out, "… and {more} more changes.")?;
+    }
+    Ok(())
+}
 
-        /// Set which column(s) form the primary key
-        #[arg(long)]
-        primary_key: Option<String>,
+/// Print one JSON object per line: the snapshot ids, then each change as it
+/// is computed, then the summary
+fn print_diff_ndjson(
+    out: &mut dyn Write,
+    from: &Snapshot,
+    to: &Snapshot,
+    opts: &DiffOptions,
+) -> Result<()> {
+    writeln!(
+        out,
+        "{}",
+        serde_json::json!({ "from_id": from.id, "to_id": to.id })
+    )?;
+    let mut summary = DiffSummary::default();
+    for change in SnapshotDiff::iter_changes(from, to, opts)? {
+        summary.record(&change);
+        writeln!(out, "{}", serde_json::to_string(&change)?)?;
+    }
+    writeln!(out, "{}", serde_json::json!({ "summary": summary }))?;
+    Ok(())
+}
 
-        /// Auto-commit to git after creating snapshot
-        #[arg(long)]
-        auto_commit: bool,
-    },
+/// Both tables of a diff, for rendering unchanged rows around changes
+struct DiffContext<'a> {
+    from: &'a Table,
+    to: &'a Table,
+    rows: usize,
+}
 
-    /// Show a diff between two snapshots
-    Diff {
-        /// First snapshot file
-        #[arg(value_name = "FROM")]
-        from: String,
+/// Print a diff summary followed by each change
+///
+/// Changes are streamed as they are computed. Only context rendering buffers
+/// them, since it needs every changed row before printing neighbours.
+fn print_diff_text(
+    out: &mut dyn Write,
+    from: &Snapshot,
+    to: &Snapshot,
+    opts: &DiffOptions,
+    output: &ChangeOutput,
+    context: Option<DiffContext>,
+) -> Result<()> {
+    writeln!(out, "Diff from {} to {}", from.id, to.id)?;
+    writeln!(out, "Summary:")?;
+    for line in SnapshotDiff::summarize_with(from, to, opts)?
+        .to_string()
+        .lines()
+    {
+        writeln!(out, "  {line}")?;
+    }
 
-        /// Second snapshot file
-        #[arg(value_name = "TO")]
-        to: String,
+    let numeric: Vec<_> = SnapshotDiff::numeric_summary(from, to)
+        .into_iter()
+        .filter(|delta| delta.is_changed())
+        .collect();
+    if !numeric.is_empty() {
+        writeln!(out, "Numeric changes:")?;
+        for delta in &numeric {
+            writeln!(out, "  {delta}")?;
+        }
+    }
 
-        /// Output format (json or git)
-        #[arg(short, long)]
-        format: Option<String>,
-    },
+    let changes = output.changes(from, to, opts)?;
+    let (changes, changed): (Box<dyn Iterator<Item = _>>, HashSet<(bool, usize)>) =
+        if context.is_some() {
+            let all: Vec<(Option<f64>, Change)> = changes.collect();
+            let changed = all
+                .iter()
+                .flat_map(|(_, change)| change.row_positions())
+                .collect();
+            (Box::new(all.into_iter()), changed)
+        } else {
+            (changes, HashSet::new())
+        };
+    let mut changes = changes.peekable();
+    if changes.peek().is_none() {
+        return Ok(());
+    }
 
-    /// Verify integrity of a snapshot
-    Verify {
-        /// Snapshot file to verify
-        #[arg(value_name = "FILE")]
-        file: String,
-    },
+    writeln!(out, "Changes:")?;
+    let mut shown = HashSet::new();
 
-    /// Show current status
-    Status,
+    // Print unchanged rows once each, with a neutral prefix naming their table
+    let mut print_context =
+        |out: &mut dyn Write, in_to: bool, range: std::ops::Range<usize>| -> Result<()> {
+            let Some(ctx) = &context else { return Ok(()) };
+            let table = if in_to { ctx.to } else { ctx.from };
+            for idx in range {
+                if let Some(row) = table.rows.get(idx)
+                    && !changed.contains(&(in_to, idx))
+                    && shown.insert((in_to, idx))
+                {
+                    let side = if in_to { "new" } else { "old" };
+                    writeln!(out, "  {side} {idx}: {row:?}")?;
+                }
+            }
+            Ok(())
+        };
 
-    /// List all snapshots
-    Log {
-        /// Limit number of snapshots shown
-        #[arg(short, long)]
-        limit: Option<usize>,
-    },
+    let rows = context.as_ref().map_or(0, |ctx| ctx.rows);
+    for (score, change) in changes.by_ref().take(output.limit.unwrap_or(usize::MAX)) {
+        if let Some(score) = score
+            && change.row_position().is_some()
+        {
+            writeln!(out, "Severity {score}:")?;
+        }
+        let position = change.row_position();
+        if let Some((in_to, idx)) = position {
+            print_context(out, in_to, idx.saturating_sub(rows)..idx)?;
+        }
+        writeln!(
+            out,
+            "{}",
+            paint(&change.to_string(), change_color(&change), output.color)
+        )?;
+        if let Some((in_to, idx)) = position {
+            print_context(out, in_to, idx + 1..idx + 1 + rows)?;
+        }
+    }
+    print_truncated(out, changes.map(|(_, change)| change))
 }
 
-/// Diff output format
-#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
-pub enum DiffFormat {
-    /// JSON format
-    Json,
-    /// Git-style unified diff
-    Git,
+/// How far a snapshot's table may have drifted and still pass `verify`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tolerance {
+    /// Every cell exactly as hashed, in the same place
+    Exact,
+    /// Each column's data by name, wherever the column now sits
+    ColumnOrder,
+    /// The logical hash: same meaning, whatever the formatting or order
+    Logical,
 }
 
-// ============================================================================
-// COMMAND IMPLEMENTATIONS
-// ============================================================================
-
-fn init_repository(path: &Path) -> Result<()> {
-    println!("Initializing git-sheets repository at {}", path.display());
+impl Tolerance {
+    fn new(ignore_column_order: bool, logical: bool) -> Self {
+        if logical {
+            Self::Logical
+        } else if ignore_column_order {
+            Self::ColumnOrder
+        } else {
+            Self::Exact
+        }
+    }
 
-    // Create necessary directories
-    std::fs::create_dir_all(path.join("snapshots"))?;
-    std::fs::create_dir_all(path.join("diffs"))?;
+    /// Whether a snapshot that failed the exact check still passes
+    fn accepts(self, snapshot: &Snapshot) -> bool {
+        match self {
+            Self::Exact => false,
+            Self::ColumnOrder => snapshot.verify_content_ignoring_column_order(),
+            Self::Logical => snapshot.verify_logical(),
+        }
+    }
 
-    // Create .gitignore if needed
-    let gitignore_path = path.join(".gitignore");
-    if !gitignore_path.exists() {
-        let mut gitignore = std::fs::File::create(gitignore_path)?;
-        writeln!(gitignore, "snapshots/")?;
-        writeln!(gitignore, "diffs/")?;
-        writeln!(gitignore, "*.toml")?;
-        writeln!(gitignore, "*.json")?;
+    /// What of the table an accepted snapshot still holds, for messages
+    fn intact(self) -> &'static str {
+        match self {
+            Self::Exact => "content",
+            Self::ColumnOrder => "column data",
+            Self::Logical => "logical content",
+        }
     }
 
-    Ok(())
+    /// How an accepted table differs from the one hashed, for messages
+    fn drift(self) -> &'static str {
+        match self {
+            Self::Exact => "unchanged",
+            Self::ColumnOrder => "its columns have been rearranged",
+            Self::Logical => "it has been reformatted or reordered",
+        }
+    }
 }
 
-fn create_snapshot(
-    file: &Path,
-    message: Option<String>,
-    primary_key: Option<String>,
-    auto_commit: bool,
-) -> Result<()> {
-    println!("Creating snapshot of {}", file.display());
+fn verify_snapshot(path: &Path, tolerance: Tolerance) -> Result<()> {
+    println!("Verifying snapshot: {}", path.display());
 
-    // Load the table
-    let mut table = Table::from_csv(file)?;
+    // A checksum mismatch is fatal whatever the tolerance: the message,
+    // parent, key and dependencies it protects have no other check
+    let snapshot = Snapshot::load_materialized(path)?;
 
-    // Set primary key if specified
-    if let Some(pk_str) = primary_key {
-        let pk_indices: Vec<usize> = pk_str
-            .split(',')
-            .map(|s| s.trim().parse::<usize>().unwrap_or(0))
-            .collect();
-        table.set_primary_key(pk_indices);
+    if snapshot.verify() {
+        println!("Snapshot integrity verified");
+    } else if tolerance.accepts(&snapshot) {
+        println!(
+            "Snapshot {} verified; {}",
+            tolerance.intact(),
+            tolerance.drift()
+        );
+    } else {
+        println!("Snapshot integrity check failed");
+        if tolerance == Tolerance::Logical && snapshot.hashes.logical_hash.is_none() {
+            println!("  (the snapshot predates logical hashes; retake it to record one)");
+        }
+        return Err(GitSheetsError::FileSystemError(
+            "Snapshot verification failed".to_string(),
+        ));
     }
 
-    // Create snapshot
-    let snapshot = Snapshot::new(table, message);
-
-    // Save snapshot
-    let snapshot_path = Path::new("snapshots").join(format!("{}.toml", snapshot.id));
-    snapshot.save(&snapshot_path)?;
+    Ok(())
+}
 
-    println!("Snapshot created: {}", snapshot.id);
+/// Re-hash a sample of rows; a pass says "probably intact", not "verified"
+///
+/// Skips the envelope checksum, which would hash the whole file. A delta
+/// still has to be rebuilt, which checks every step's table hash, so it
+/// gets no faster.
+fn spot_check_snapshot(path: &Path, sample: usize, seed: Option<u64>) -> Result<()> {
+    let mut snapshot = Snapshot::load_unverified(path)?;
+    if snapshot.delta.is_some() {
+        snapshot = Snapshot::load_materialized(path)?;
+    }
+    let Some(row_hashes) = &snapshot.hashes.row_hashes else {
+        return Err(GitSheetsError::FileSystemError(format!(
+            "Snapshot {} has no row hashes to spot-check (take it with --row-hashes)",
+            snapshot.id
+        )));
+    };
 
-    if auto_commit {
-        // Perform actual git commit
-        let repo_path = Path::new(".");
-        match git2::Repository::open(repo_path) {
-            Ok(repo) => {
-                let mut index = repo.index()?;
-
-                // Add the snapshot file to the index
-                index.add_path(&snapshot_path)?;
-                index.write_tree()?;
-
-                // Create commit
-                let tree_id = index.write_tree()?;
-                let tree = repo.find_tree(tree_id)?;
-                let author = git2::Signature::now("git-sheets", "git-sheets@localhost")?;
-                let committer = author.clone();
-                let commit_id = repo.commit(
-                    Some("HEAD"),
-                    &author,
-                    &committer,
-                    &format!("Snapshot: {}", snapshot.id),
-                    &tree,
-                    &[],
-                )?;
+    // Record the seed so a failing sample can be reproduced
+    let seed = seed.unwrap_or_else(|| Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64);
+    let checked = sample.min(row_hashes.len());
+    println!(
+        "Spot-checking {checked} of {} rows of {} (seed {seed})",
+        row_hashes.len(),
+        path.display()
+    );
 
-                println!("Auto-commit performed: {}", commit_id.to_string());
-            }
-            Err(_) => {
-                eprintln!("Warning: Git repository not found, auto-commit skipped");
-            }
-        }
+    if snapshot.spot_check(sample, seed) {
+        println!(
+            "Spot check passed (probabilistic; run verify without --spot-check for a full check)"
+        );
+        Ok(())
+    } else {
+        println!("Spot check failed");
+        Err(GitSheetsError::SpotCheckFailed(format!(
+            "A sampled row of {} doesn't match its hash (seed {seed})",
+            snapshot.id
+        )))
     }
+}
 
-    Ok(())
+/// What `verify --all` found for one snapshot file
+enum VerifyOutcome {
+    Passed,
+    Tolerated,
+    Failed(String),
 }
 
-fn show_diff(from: &Path, to: &Path, format: &str) -> Result<()> {
-    println!("Computing diff...");
+fn verify_file(store: &SnapshotStore, path: &Path, tolerance: Tolerance) -> VerifyOutcome {
+    match store.load_materialized(path) {
+        Ok(snapshot) if snapshot.verify() => VerifyOutcome::Passed,
+        Ok(snapshot) if tolerance.accepts(&snapshot) => VerifyOutcome::Tolerated,
+        Ok(_) => VerifyOutcome::Failed("hash mismatch".to_string()),
+        Err(e) => VerifyOutcome::Failed(e.to_string()),
+    }
+}
 
-    let snapshot1 = Snapshot::load(from)?;
-    let snapshot2 = Snapshot::load(to)?;
-    let diff = SnapshotDiff::compute(&snapshot1, &snapshot2)?;
+/// Check the hash of every snapshot, without fsck's cross-reference checks,
+/// `jobs` at a time
+///
+/// Each worker thread keeps its own `SnapshotStore`, so delta parents may be
+/// loaded once per thread. Results are reported in file order.
+fn verify_all(tolerance: Tolerance, jobs: Option<usize>) -> Result<()> {
+    let dir = Path::new("snapshots");
+    let paths = snapshot_files(dir)?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()
+        .map_err(|e| {
+            GitSheetsError::FileSystemError(format!("cannot start verify threads: {e}"))
+        })?;
+    let outcomes: Vec<VerifyOutcome> = pool.install(|| {
+        paths
+            .par_iter()
+            .map_init(
+                || SnapshotStore::new(dir),
+                |store, path| verify_file(store, path, tolerance),
+            )
+            .collect()
+    });
 
-    match format {
-        "json" => {
-            let json_string = serde_json::to_string_pretty(&diff)?;
-            println!("{json_string}");
-        }
-        "git" => {
-            // Print git-style diff
-            println!("--- {}", snapshot1.id);
-            println!("+++ {}", snapshot2.id);
-            for change in &diff.changes {
-                match change {
-                    Change::RowAdded { index, data } => {
-                        println!("@@ -0 +{} @@", index + 1);
-                        println!("+{}", data.join("\t"));
-                    }
-                    Change::RowRemoved { index, data } => {
-                        println!("@@ -{} +0 @@", index + 1);
-                        println!("-{}", data.join("\t"));
-                    }
-                    Change::CellChanged { row, col, old, new } => {
-                        println!("@@ -{} +{} @@", row + 1, col + 1);
-                        println!("-{}", old);
-                        println!("+{}", new);
-                    }
-                    Change::RowModified {
-                        index,
-                        old_data,
-                        new_data,
-                    } => {
-                        println!("@@ -{} +{} @@", index + 1, index + 1);
-                        println!("-{}", old_data.join("\t"));
-                        println!("+{}", new_data.join("\t"));
-                    }
-                    Change::ColumnAdded { name, index } => {
-                        println!("@@ -0 +{} @@", index + 1);
-                        println!("+{}", name);
-                    }
-                    Change::ColumnRemoved { name, index } => {
-                        println!("@@ -{} +0 @@", index + 1);
-                        println!("-{}", name);
+    let mut passed = 0;
+    let mut failed = Vec::new();
+    for (path, outcome) in paths.iter().zip(outcomes) {
+        match outcome {
+            VerifyOutcome::Passed => passed += 1,
+            VerifyOutcome::Tolerated => {
+                match tolerance {
+                    Tolerance::Logical => {
+                        println!("  reformatted: {} (same logical content)", path.display())
                     }
+                    _ => println!("  rearranged: {} (columns moved)", path.display()),
                 }
+                passed += 1;
             }
-        }
-        _ => {
-            // Default to text format
-            print_diff_text(&diff);
+            VerifyOutcome::Failed(reason) => failed.push(format!("{} ({reason})", path.display())),
         }
     }
 
-    Ok(())
+    println!("{passed} passed, {} failed", failed.len());
+    for failure in &failed {
+        println!("  failed: {failure}");
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(GitSheetsError::FileSystemError(format!(
+            "{} snapshot(s) failed verification",
+            failed.len()
+        )))
+    }
 }
 
-fn print_diff_text(diff: &SnapshotDiff) {
-    println!("Diff from {} to {}", diff.from_id, diff.to_id);
-    println!("Summary:");
-    println!("  Rows added: {}", diff.summary.rows_added);
-    println!("  Rows removed: {}", diff.summary.rows_removed);
-    println!("  Rows modified: {}", diff.summary.rows_modified);
+fn check_repository() -> Result<()> {
+    let snapshots_dir = Path::new("snapshots");
+    let diffs_dir = Path::new("diffs");
 
-    if !diff.changes.is_empty() {
-        println!("Changes:");
-        for change in &diff.changes {
-            match change {
-                Change::RowAdded { index, data } => {
-                    println!("Row added at {}: {:?}", index, data);
-                }
-                Change::RowRemoved { index, data } => {
-                    println!("Row removed at {}: {:?}", index, data);
-                }
-                Change::CellChanged { row, col, old, new } => {
-                    println!("Cell changed at ({}, {}): {} -> {}", row, col, old, new);
-                }
-                Change::RowModified {
-                    index,
-                    old_data,
-                    new_data,
-                } => {
-                    println!(
-                        "Row modified at {}: {:?} -> {:?}",
-                        index, old_data, new_data
-                    );
-                }
-                Change::ColumnAdded { name, index } => {
-                    println!("Column added at {}: {}", index, name);
+    if !snapshots_dir.exists() {
+        return Err(GitSheetsError::FileSystemError(
+            "Not a git-sheets repository".to_string(),
+        ));
+    }
+
+    let mut healthy = 0;
+    let mut corrupted = 0;
+    let mut orphaned = 0;
+
+    // Integrity of each snapshot
+    let store = SnapshotStore::new(snapshots_dir);
+    let mut snapshots = Vec::new();
+    for path in snapshot_files(snapshots_dir)? {
+        match store.load_materialized(&path) {
+            Ok(snapshot) if snapshot.verify() => snapshots.push((path, snapshot)),
+            Ok(_) => {
+                println!("corrupted: {} (hash mismatch)", path.display());
+                corrupted += 1;
+            }
+            Err(e) => {
+                println!("corrupted: {} ({e})", path.display());
+                corrupted += 1;
+            }
+        }
+    }
+
+    // Parent links must point at snapshots that exist
+    let known_ids: Vec<&str> = snapshots.iter().map(|(_, s)| s.id.as_str()).collect();
+    for (path, snapshot) in &snapshots {
+        match &snapshot.parent {
+            Some(parent) if !known_ids.contains(&parent.as_str()) => {
+                println!("orphaned: {} (parent '{parent}' not found)", path.display());
+                orphaned += 1;
+            }
+            _ => healthy += 1,
+        }
+    }
+
+    // Saved diffs must reference snapshots that exist
+    if diffs_dir.exists() {
+        for path in snapshot_files(diffs_dir)? {
+            match SnapshotDiff::load(&path) {
+                Ok(diff) => {
+                    let missing: Vec<&str> = [diff.from_id.as_str(), diff.to_id.as_str()]
+                        .into_iter()
+                        .filter(|id| !known_ids.contains(id))
+                        .collect();
+                    if missing.is_empty() {
+                        healthy += 1;
+                    } else {
+                        println!(
+                            "orphaned: {} (references missing snapshot {})",
+                            path.display(),
+                            missing.join(", ")
+                        );
+                        orphaned += 1;
+                    }
                 }
-                Change::ColumnRemoved { name, index } => {
-                    println!("Column removed at {}: {}", index, name);
+                Err(e) => {
+                    println!("corrupted: {} ({e})", path.display());
+                    corrupted += 1;
                 }
             }
         }
     }
-}
 
-fn verify_snapshot(path: &Path) -> Result<()> {
-    println!("Verifying snapshot: {}", path.display());
-
-    let snapshot = Snapshot::load(path)?;
+    println!("{healthy} healthy, {corrupted} corrupted, {orphaned} orphaned");
 
-    if snapshot.verify() {
-        println!("Snapshot integrity verified");
-    } else {
-        println!("Snapshot integrity check failed");
+    if corrupted + orphaned > 0 {
         return Err(GitSheetsError::FileSystemError(
-            "Snapshot verification failed".to_string(),
+            "Repository check failed".to_string(),
         ));
     }
 
     Ok(())
 }
 
+/// List the `.toml` and `.json` files in a directory, sorted by name
+fn snapshot_files(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_snapshot_file(path))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
 fn show_status() -> Result<()> {
     println!("Git-sheets status\n");
 
@@ -332,30 +3496,57 @@ fn show_status() -> Result<()> {
     println!("Diffs directory: diffs/");
 
     // Check if git repository exists
-    // Check if git repository exists
-    match git2::Repository::open(repo_path) {
-        Ok(repo) => match repo.head() {
-            Ok(head) => {
-                println!(
-                    "Git HEAD: {}",
-                    head.target()
-                        .map(|oid| oid.to_string())
-                        .unwrap_or_else(|| "None".to_string())
-                );
-            }
+    if !git_enabled()? {
+        println!("git integration disabled.");
+    } else {
+        match git2::Repository::open(repo_path) {
+            Ok(repo) => match repo.head() {
+                Ok(head) => {
+                    println!(
+                        "Git HEAD: {}",
+                        head.target()
+                            .map(|oid| oid.to_string())
+                            .unwrap_or_else(|| "None".to_string())
+                    );
+                }
+                Err(_) => {
+                    println!("No Git HEAD");
+                }
+            },
             Err(_) => {
-                println!("No Git HEAD");
+                println!("No Git repository found");
+            }
+        }
+    }
+
+    // Current size of each tracked file, without loading its rows
+    let mut sources: Vec<String> = snapshot_files(Path::new("snapshots"))?
+        .iter()
+        .filter_map(|path| Snapshot::load(path).ok()?.source)
+        .filter(|source| !is_url(source))
+        .map(|source| normalize_source(&source))
+        .collect();
+    sources.sort();
+    sources.dedup();
+    if !sources.is_empty() {
+        println!("\nTracked files:");
+        for source in sources {
+            match Table::quick_dimensions(Path::new(&source)) {
+                Ok((cols, rows)) => println!("  {source}: {cols} columns, {rows} rows"),
+                Err(e) => println!("  {source}: {e}"),
             }
-        },
-        Err(_) => {
-            println!("No Git repository found");
         }
     }
 
     Ok(())
 }
 
-fn show_log(limit: Option<usize>) -> Result<()> {
+fn show_log(
+    limit: Option<usize>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    grep: Option<&str>,
+) -> Result<()> {
     let snapshots_dir = Path::new("snapshots");
 
     if !snapshots_dir.exists() {
@@ -369,12 +3560,32 @@ fn show_log(limit: Option<usize>) -> Result<()> {
     let mut snapshot_files: Vec<_> = std::fs::read_dir(snapshots_dir)?
         .filter_map(|entry| entry.ok())
         .map(|entry| entry.path())
-        .filter(|path| path.extension().map_or(false, |ext| ext == "toml"))
+        .filter(|path| is_snapshot_file(path))
         .collect();
 
     // Sort by name (which should be timestamp-based)
     snapshot_files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
 
+    // Date and message filters need the snapshot contents
+    if since.is_some() || until.is_some() || grep.is_some() {
+        snapshot_files.retain(|path| match Snapshot::load(path) {
+            Ok(snapshot) => {
+                since.is_none_or(|since| snapshot.timestamp >= since)
+                    && until.is_none_or(|until| snapshot.timestamp <= until)
+                    && grep.is_none_or(|pattern| {
+                        snapshot
+                            .message
+                            .as_deref()
+                            .is_some_and(|message| message.contains(pattern))
+                    })
+            }
+            Err(e) => {
+                eprintln!("Warning: Could not load snapshot from {:?}: {}", path, e);
+                false
+            }
+        });
+    }
+
     let limit = limit.unwrap_or(snapshot_files.len());
     let total = snapshot_files.len();
     let start = total.saturating_sub(limit);
@@ -383,7 +3594,250 @@ fn show_log(limit: Option<usize>) -> Result<()> {
     println!("Recent snapshots:");
     for path in snapshots_to_show {
         let filename = path.file_name().unwrap().to_string_lossy();
-        println!("  {}", filename);
+        let snapshot = Snapshot::load(path).ok();
+        let mut line = format!("  {filename}");
+        if let Some(author) = snapshot.as_ref().and_then(|s| s.author.as_ref()) {
+            line.push_str(&format!("  ({author})"));
+        }
+        if let Some(count) = snapshot
+            .map(|s| s.quality_warnings.len())
+            .filter(|&count| count > 0)
+        {
+            line.push_str(&format!("  [{count} quality warning(s)]"));
+        }
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+fn show_columns(path: &Path, json: bool) -> Result<()> {
+    let snapshot = Snapshot::load_materialized(path)?;
+    let columns = snapshot.columns();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&columns)?);
+        return Ok(());
+    }
+
+    let rows = snapshot.table.rows.len();
+    let types: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            if column.declared {
+                format!("{} (declared)", column.column_type)
+            } else {
+                column.column_type.to_string()
+            }
+        })
+        .collect();
+    let index_width = columns.len().saturating_sub(1).to_string().len().max(1);
+    let name_width = columns
+        .iter()
+        .map(|column| column.name.chars().count())
+        .chain(["Column".len()])
+        .max()
+        .unwrap_or_default();
+    let type_width = types
+        .iter()
+        .map(String::len)
+        .chain(["Type".len()])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{:>index_width$}  {:name_width$}  {:type_width$}  Non-empty  Key",
+        "#", "Column", "Type"
+    );
+    for (column, ty) in columns.iter().zip(&types) {
+        let filled = format!("{}/{rows}", column.non_empty);
+        let line = format!(
+            "{:>index_width$}  {:name_width$}  {ty:type_width$}  {filled:9}  {}",
+            column.index,
+            column.name,
+            if column.primary_key { "yes" } else { "" }
+        );
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+fn show_history(
+    path: &Path,
+    row: Option<&str>,
+    col: &str,
+    aggregate: Option<Aggregate>,
+) -> Result<()> {
+    let mut chain = Snapshot::load_chain(path)?;
+    chain.reverse();
+
+    match (row, aggregate) {
+        (_, Some(aggregate)) => println!("History of {aggregate:?} of {col}:"),
+        (Some(key), None) => println!("History of {col} for key {key}:"),
+        (None, None) => unreachable!("clap requires --row or --aggregate"),
+    }
+
+    for snapshot in &chain {
+        let table = &snapshot.table;
+        let value = match table.headers.iter().position(|h| h == col) {
+            None => "(no such column)".to_string(),
+            Some(col_idx) => match (row, aggregate) {
+                (_, Some(aggregate)) => {
+                    let values: Vec<f64> = table
+                        .rows
+                        .iter()
+                        .filter_map(|r| r.get(col_idx).and_then(|cell| parse_number(cell)))
+                        .collect();
+                    let sum: f64 = values.iter().sum();
+                    match aggregate {
+                        Aggregate::Sum => sum.to_string(),
+                        Aggregate::Mean if values.is_empty() => "(no numeric values)".to_string(),
+                        Aggregate::Mean => (sum / values.len() as f64).to_string(),
+                    }
+                }
+                (Some(key_str), None) => {
+                    let key: Vec<String> =
+                        key_str.split('|').map(|s| s.trim().to_string()).collect();
+                    match table.find_row_by_key(&key) {
+                        Ok(Some(row_idx)) => table.rows[row_idx]
+                            .get(col_idx)
+                            .cloned()
+                            .unwrap_or_default(),
+                        Ok(None) => "(row absent)".to_string(),
+                        Err(_) => "(no primary key)".to_string(),
+                    }
+                }
+                (None, None) => unreachable!("clap requires --row or --aggregate"),
+            },
+        };
+
+        println!(
+            "  {}  {}  {}",
+            snapshot.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            snapshot.id,
+            value
+        );
+    }
+
+    Ok(())
+}
+
+/// Net change to `source` from the first to the last snapshot in a window
+///
+/// The window's snapshots are found by following parent links back from the
+/// newest snapshot of `source` inside it.
+fn show_report(
+    source: &str,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    top: usize,
+) -> Result<()> {
+    let store = SnapshotStore::new("snapshots");
+    let in_window = |snapshot: &Snapshot| {
+        since.is_none_or(|since| snapshot.timestamp >= since)
+            && until.is_none_or(|until| snapshot.timestamp <= until)
+    };
+
+    let wanted = normalize_source(source);
+    let mut latest: Option<(PathBuf, Rc<Snapshot>)> = None;
+    for path in snapshot_files(store.dir())? {
+        let snapshot = match store.load(&path) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                eprintln!("Warning: Could not load snapshot from {:?}: {}", path, e);
+                continue;
+            }
+        };
+        if snapshot.source.as_deref().map(normalize_source).as_ref() == Some(&wanted)
+            && in_window(&snapshot)
+            && latest
+                .as_ref()
+                .is_none_or(|(_, l)| snapshot.timestamp > l.timestamp)
+        {
+            latest = Some((path, snapshot));
+        }
+    }
+    let Some((latest_path, _)) = latest else {
+        return Err(GitSheetsError::FileSystemError(format!(
+            "No snapshots of {source} in the given window"
+        )));
+    };
+
+    let chain: Vec<_> = store
+        .chain(&latest_path)?
+        .into_iter()
+        .take_while(|snapshot| in_window(snapshot))
+        .collect();
+    let (first, last) = (&chain[chain.len() - 1], &chain[0]);
+
+    let config = Config::load(Path::new("."))?;
+    println!("Report for {source}");
+    for (label, snapshot) in [("From", first), ("To", last)] {
+        println!(
+            "  {label:<4} {}  {}",
+            snapshot.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            snapshot.short_id(config.hash_display_len())
+        );
+    }
+    println!("  {} snapshot(s) in the window", chain.len());
+    if chain.len() == 1 {
+        println!("Only one snapshot in the window; nothing to compare");
+        return Ok(());
+    }
+
+    warn_on_key_mismatch(first, last, &DiffOptions::default())?;
+    let diff = SnapshotDiff::compute(first, last)?;
+    println!();
+    println!("Net change:");
+    println!("{}", diff.summary);
+
+    // Columns by number of changed cells, most first
+    let mut columns: Vec<(&str, usize)> = Vec::new();
+    for change in &diff.changes {
+        let Change::CellChanged { col, .. } = change else {
+            continue;
+        };
+        let name = first.table.headers.get(*col).map_or("?", String::as_str);
+        match columns.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, count)) => *count += 1,
+            None => columns.push((name, 1)),
+        }
+    }
+    columns.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    println!();
+    println!("Top changed columns:");
+    if columns.is_empty() {
+        println!("  (none)");
+    }
+    for (name, count) in columns.iter().take(top) {
+        println!("  {name}: {count} cell(s)");
+    }
+
+    // Rows ranked as by diff --sort-by-severity
+    let ranked = rank_by_severity(diff.changes, &config.severity);
+    let rows: Vec<_> = ranked
+        .iter()
+        .filter(|row| row.changes[0].row_position().is_some())
+        .collect();
+    println!();
+    println!("Top changed rows:");
+    if rows.is_empty() {
+        println!("  (none)");
+    }
+    for row in rows.iter().take(top) {
+        let change = &row.changes[0];
+        let label = match (change.row_key(), change.row_position()) {
+            (Some(key), _) => format!("key {}", key.join("|")),
+            (None, Some((_, index))) => format!("row {index}"),
+            (None, None) => unreachable!("filtered to row changes"),
+        };
+        let what = match change {
+            Change::RowAdded { .. } => "added".to_string(),
+            Change::RowRemoved { .. } => "removed".to_string(),
+            Change::RowSplit { into, .. } => format!("split into {} rows", into.len()),
+            Change::RowMerged { from, .. } => format!("merged from {} rows", from.len()),
+            _ => format!("{} cell(s) changed", row.changes.len()),
+        };
+        println!("  {label}: {what} (severity {})", row.score);
     }
 
     Ok(())
@@ -0,0 +1,128 @@
+// git-sheets: Repository configuration, read from .gitsheets/config.toml
+
+use super::validate::{
+    NonEmptyValidator, OneOfValidator, RangeValidator, RegexValidator, Validator,
+};
+use super::{DEFAULT_ID_HASH_LEN, Result, Table};
+use crate::diff::SeverityWeights;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[cfg(feature = "native")]
+use std::{fs, path::Path};
+
+/// Location of the config file, relative to the repository root
+pub const CONFIG_PATH: &str = ".gitsheets/config.toml";
+
+/// Repository-wide settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Data-quality rules checked when taking a snapshot
+    #[serde(default)]
+    pub validate: Vec<ValidationRule>,
+    /// Weights for `diff --sort-by-severity`
+    #[serde(default)]
+    pub severity: SeverityWeights,
+    /// Cell values read as empty when loading, unless `--null-tokens` is given
+    #[serde(default)]
+    pub null_tokens: Vec<String>,
+    /// Table-hash characters shown when printing ids and hashes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_display_length: Option<usize>,
+    /// Columns holding delimited lists, by name, with their delimiter;
+    /// diffs compare them as unordered sets unless `--set-columns` is given
+    ///
+    /// ```toml
+    /// [set_columns]
+    /// Tags = ";"
+    /// ```
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set_columns: BTreeMap<String, String>,
+    /// Never touch git, as if every command were given `--no-git`
+    #[serde(default)]
+    pub no_git: bool,
+}
+
+/// Checks for one column, applied to any table that has that column
+///
+/// ```toml
+/// [[validate]]
+/// column = "Amount"
+/// min = 0
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationRule {
+    /// Column name the rule applies to
+    pub column: String,
+    /// Cells must match this regular expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// Cells must be numbers no smaller than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Cells must be numbers no larger than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Cells must not be empty
+    #[serde(default)]
+    pub non_empty: bool,
+    /// Cells must be one of these values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_of: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Load the config of the repository at `root`, or defaults if it has none
+    #[cfg(feature = "native")]
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(CONFIG_PATH);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Table-hash characters to show, `DEFAULT_ID_HASH_LEN` unless configured
+    pub fn hash_display_len(&self) -> usize {
+        self.hash_display_length.unwrap_or(DEFAULT_ID_HASH_LEN)
+    }
+
+    /// Build the validators that apply to `table`, keyed by column index
+    ///
+    /// Rules for columns the table doesn't have are skipped.
+    pub fn validators_for(&self, table: &Table) -> Result<Vec<(usize, Box<dyn Validator>)>> {
+        let mut validators = Vec::new();
+        for rule in &self.validate {
+            let Some(col) = table.headers.iter().position(|h| *h == rule.column) else {
+                continue;
+            };
+            for validator in rule.validators()? {
+                validators.push((col, validator));
+            }
+        }
+        Ok(validators)
+    }
+}
+
+impl ValidationRule {
+    /// The built-in validators this rule describes
+    pub fn validators(&self) -> Result<Vec<Box<dyn Validator>>> {
+        let mut validators: Vec<Box<dyn Validator>> = Vec::new();
+        if let Some(pattern) = &self.regex {
+            validators.push(Box::new(RegexValidator::new(pattern)?));
+        }
+        if self.min.is_some() || self.max.is_some() {
+            validators.push(Box::new(RangeValidator {
+                min: self.min,
+                max: self.max,
+            }));
+        }
+        if self.non_empty {
+            validators.push(Box::new(NonEmptyValidator));
+        }
+        if let Some(allowed) = &self.one_of {
+            validators.push(Box::new(OneOfValidator(allowed.clone())));
+        }
+        Ok(validators)
+    }
+}
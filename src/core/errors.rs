@@ -18,18 +18,64 @@ pub enum GitSheetsError {
     JsonError(JsonError),
     /// CSV error
     CsvError(CsvError),
+    /// CSV error tied to a line of the source file
+    Csv {
+        /// 1-based line number
+        line: usize,
+        /// The offending line, truncated
+        snippet: String,
+        /// Underlying CSV error
+        source: CsvError,
+    },
     /// Git error
+    #[cfg(feature = "native")]
     GitError(git2::Error),
+    /// A command needs git, but git integration is turned off
+    GitDisabled(String),
     /// Dependency hash mismatch
     DependencyHashMismatch(String),
+    /// A snapshot file's contents don't match its envelope checksum
+    ChecksumMismatch(String),
+    /// A snapshot file was written by a newer git-sheets than this one
+    UnsupportedSchema(String),
+    /// An append-only diff found rows that were changed or removed
+    AppendOnlyViolation(String),
+    /// A diff changed more rows than its threshold allows
+    ThresholdExceeded(String),
+    /// A source file isn't valid UTF-8
+    InvalidEncoding(String),
+    /// Another process holds the repository lock
+    RepositoryLocked(String),
     /// Empty table encountered
     EmptyTable,
     /// No primary key defined
     NoPrimaryKey,
+    /// Primary key values that match no row
+    KeyNotFound(String),
     /// Invalid row index provided
     InvalidRowIndex(String),
+    /// Unknown, missing or duplicated column
+    InvalidColumn(String),
     /// File system error
     FileSystemError(String),
+    /// Fetching a remote table failed
+    NetworkError(String),
+    /// Writing a table in another file format failed
+    ExportError(String),
+    /// A delta snapshot can't be created or rebuilt
+    InvalidDelta(String),
+    /// A validation rule can't be built
+    InvalidRule(String),
+    /// The table broke validation rules
+    ValidationFailed(String),
+    /// A snapshot's sampled rows don't match their stored row hashes
+    SpotCheckFailed(String),
+    /// A snapshot has no parent where one is needed
+    NoParent(String),
+    /// An interactive command was run without a terminal
+    TerminalRequired(String),
+    /// The user stopped an interactive command
+    Cancelled(String),
 }
 
 impl fmt::Display for GitSheetsError {
@@ -40,14 +86,41 @@ impl fmt::Display for GitSheetsError {
             GitSheetsError::TomlSerError(e) => write!(f, "TOML Serialization Error: {e}"),
             GitSheetsError::JsonError(e) => write!(f, "JSON Error: {e}"),
             GitSheetsError::CsvError(e) => write!(f, "CSV Error: {e}"),
+            GitSheetsError::Csv {
+                line,
+                snippet,
+                source,
+            } => write!(
+                f,
+                "CSV Error on line {line}: {source}\n  {line} | {snippet}"
+            ),
+            #[cfg(feature = "native")]
             GitSheetsError::GitError(e) => write!(f, "Git Error: {e}"),
+            GitSheetsError::GitDisabled(msg) => write!(f, "Git Disabled: {msg}"),
             GitSheetsError::DependencyHashMismatch(msg) => {
                 write!(f, "Dependency Hash Mismatch: {msg}")
             }
+            GitSheetsError::ChecksumMismatch(msg) => write!(f, "Checksum Mismatch: {msg}"),
+            GitSheetsError::UnsupportedSchema(msg) => write!(f, "Unsupported Schema: {msg}"),
+            GitSheetsError::AppendOnlyViolation(msg) => write!(f, "Append Only Violation: {msg}"),
+            GitSheetsError::ThresholdExceeded(msg) => write!(f, "Threshold Exceeded: {msg}"),
+            GitSheetsError::InvalidEncoding(msg) => write!(f, "Invalid Encoding: {msg}"),
+            GitSheetsError::RepositoryLocked(msg) => write!(f, "Repository Locked: {msg}"),
             GitSheetsError::EmptyTable => write!(f, "Empty Table"),
             GitSheetsError::NoPrimaryKey => write!(f, "No Primary Key"),
+            GitSheetsError::KeyNotFound(msg) => write!(f, "Key Not Found: {msg}"),
             GitSheetsError::InvalidRowIndex(msg) => write!(f, "Invalid Row Index: {msg}"),
+            GitSheetsError::InvalidColumn(msg) => write!(f, "Invalid Column: {msg}"),
             GitSheetsError::FileSystemError(msg) => write!(f, "File System Error: {msg}"),
+            GitSheetsError::NetworkError(msg) => write!(f, "Network Error: {msg}"),
+            GitSheetsError::ExportError(msg) => write!(f, "Export Error: {msg}"),
+            GitSheetsError::InvalidDelta(msg) => write!(f, "Invalid Delta: {msg}"),
+            GitSheetsError::InvalidRule(msg) => write!(f, "Invalid Rule: {msg}"),
+            GitSheetsError::ValidationFailed(msg) => write!(f, "Validation Failed: {msg}"),
+            GitSheetsError::SpotCheckFailed(msg) => write!(f, "Spot Check Failed: {msg}"),
+            GitSheetsError::NoParent(msg) => write!(f, "No Parent: {msg}"),
+            GitSheetsError::TerminalRequired(msg) => write!(f, "Terminal Required: {msg}"),
+            GitSheetsError::Cancelled(msg) => write!(f, "Cancelled: {msg}"),
         }
     }
 }
@@ -59,13 +132,33 @@ impl std::error::Error for GitSheetsError {
             GitSheetsError::TomlError(e) => Some(e),
             GitSheetsError::TomlSerError(e) => Some(e),
             GitSheetsError::CsvError(e) => Some(e),
+            GitSheetsError::Csv { source, .. } => Some(source),
+            #[cfg(feature = "native")]
             GitSheetsError::GitError(e) => Some(e),
             GitSheetsError::JsonError(e) => Some(e),
-            GitSheetsError::DependencyHashMismatch(_)
+            GitSheetsError::GitDisabled(_)
+            | GitSheetsError::DependencyHashMismatch(_)
+            | GitSheetsError::ChecksumMismatch(_)
+            | GitSheetsError::UnsupportedSchema(_)
+            | GitSheetsError::AppendOnlyViolation(_)
+            | GitSheetsError::ThresholdExceeded(_)
+            | GitSheetsError::InvalidEncoding(_)
+            | GitSheetsError::RepositoryLocked(_)
             | GitSheetsError::EmptyTable
             | GitSheetsError::NoPrimaryKey
+            | GitSheetsError::KeyNotFound(_)
             | GitSheetsError::InvalidRowIndex(_)
-            | GitSheetsError::FileSystemError(_) => None,
+            | GitSheetsError::InvalidColumn(_)
+            | GitSheetsError::FileSystemError(_)
+            | GitSheetsError::NetworkError(_)
+            | GitSheetsError::ExportError(_)
+            | GitSheetsError::InvalidDelta(_)
+            | GitSheetsError::InvalidRule(_)
+            | GitSheetsError::ValidationFailed(_)
+            | GitSheetsError::SpotCheckFailed(_)
+            | GitSheetsError::NoParent(_)
+            | GitSheetsError::TerminalRequired(_)
+            | GitSheetsError::Cancelled(_) => None,
         }
     }
 }
@@ -94,6 +187,7 @@ impl From<CsvError> for GitSheetsError {
     }
 }
 
+#[cfg(feature = "native")]
 impl From<git2::Error> for GitSheetsError {
     fn from(error: git2::Error) -> Self {
         GitSheetsError::GitError(error)
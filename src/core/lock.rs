@@ -0,0 +1,76 @@
+// git-sheets: Repository lock - one process at a time changes the snapshot store
+
+use super::{GitSheetsError, Result};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Location of the lock file, relative to the repository root
+pub const LOCK_PATH: &str = ".gitsheets/lock";
+
+/// How long to wait for another process to release the lock
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause between attempts to take a held lock
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Exclusive hold on a repository's snapshot store, released when dropped
+///
+/// Held while a command picks a snapshot's parent and id and writes it, so
+/// two snapshots taken at once can't both claim the same id or parent.
+/// The lock is a file created with `create_new`, holding the owner's
+/// process id; dropping the lock removes it, also when the command fails.
+#[derive(Debug)]
+pub struct RepoLock {
+    path: PathBuf,
+}
+
+impl RepoLock {
+    /// Take the lock of the repository at `root`, waiting up to `timeout`
+    /// for another process to release it
+    pub fn acquire(root: &Path, timeout: Duration) -> Result<Self> {
+        let path = root.join(LOCK_PATH);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let start = Instant::now();
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    let lock = Self { path };
+                    write!(file, "{}", std::process::id())?;
+                    tracing::debug!(path = %lock.path.display(), "repository locked");
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    if start.elapsed() >= timeout {
+                        let owner = fs::read_to_string(&path).unwrap_or_default();
+                        let owner = match owner.trim() {
+                            "" => String::new(),
+                            pid => format!(" (pid {pid})"),
+                        };
+                        return Err(GitSheetsError::RepositoryLocked(format!(
+                            "held by another process{owner}; if no git-sheets command is running, delete {}",
+                            path.display()
+                        )));
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,94 @@
+// git-sheets: Merkle tree over row hashes, for proving a single row belongs
+// to a snapshot without handing over the whole table
+
+use super::{GitSheetsError, Result, hash_row};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Which side of the running hash a proof step's sibling sits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    /// The sibling is hashed before the running hash
+    Left,
+    /// The sibling is hashed after the running hash
+    Right,
+}
+
+/// Hash two child nodes into their parent
+///
+/// The `0x01` prefix keeps parent hashes from ever equalling a row hash.
+fn parent(left: &str, right: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// The next level up: pairs are hashed together, a trailing odd node is
+/// carried up unchanged
+fn next_level(level: &[String]) -> Vec<String> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => parent(left, right),
+            [lone] => lone.clone(),
+            _ => unreachable!("chunks of two"),
+        })
+        .collect()
+}
+
+/// Merkle root of a list of row hashes
+///
+/// An empty table has the hash of no input as its root.
+pub fn merkle_root(leaves: &[String]) -> String {
+    if leaves.is_empty() {
+        return format!("{:x}", Sha256::digest([]));
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.remove(0)
+}
+
+/// Sibling hashes from leaf `idx` up to the root
+pub fn merkle_proof(leaves: &[String], idx: usize) -> Result<Vec<(Side, String)>> {
+    if idx >= leaves.len() {
+        return Err(GitSheetsError::InvalidRowIndex(format!(
+            "Row index {idx} exceeds row count {}",
+            leaves.len()
+        )));
+    }
+
+    let mut proof = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut idx = idx;
+    while level.len() > 1 {
+        let sibling = idx ^ 1;
+        if sibling < level.len() {
+            let side = if sibling < idx {
+                Side::Left
+            } else {
+                Side::Right
+            };
+            proof.push((side, level[sibling].clone()));
+        }
+        level = next_level(&level);
+        idx /= 2;
+    }
+    Ok(proof)
+}
+
+/// Whether `row` is included under `root`, given its proof
+///
+/// Needs only the row, the proof and the published root.
+pub fn verify_merkle_proof(root: &str, row: &[String], proof: &[(Side, String)]) -> bool {
+    let computed = proof
+        .iter()
+        .fold(hash_row(row), |hash, (side, sibling)| match side {
+            Side::Left => parent(sibling, &hash),
+            Side::Right => parent(&hash, sibling),
+        });
+    computed == root
+}
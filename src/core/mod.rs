@@ -1,19 +1,43 @@
 // git-sheets: Core module - fundamental data structures and operations
 // A tool for Excel sufferers who deserve better
 
+use crate::diff::SnapshotDiff;
 use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::fs;
-use std::io::Write;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
-// Git integration
-use git2;
+// Filesystem and git access, unavailable in wasm builds
+#[cfg(feature = "native")]
+use std::{fs, io::Write};
 
+pub mod config;
 pub mod errors;
+#[cfg(feature = "native")]
+pub mod lock;
+pub mod merkle;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod quality;
+pub mod schema;
+pub mod storage;
+#[cfg(feature = "native")]
+pub mod store;
+pub mod validate;
+pub use config::Config;
 pub use errors::{GitSheetsError, Result};
+#[cfg(feature = "native")]
+pub use lock::RepoLock;
+pub use merkle::Side;
+pub use schema::{ColumnInfo, ColumnType, Schema};
+#[cfg(feature = "native")]
+pub use storage::FsStorage;
+pub use storage::{InMemoryStorage, SnapshotStorage};
+#[cfg(feature = "native")]
+pub use store::SnapshotStore;
+pub use validate::{ValidationError, Validator};
 
 // ============================================================================
 // CORE PRIMITIVES
@@ -22,22 +46,73 @@ pub use errors::{GitSheetsError, Result};
 /// A snapshot represents the complete state of a table at a point in time
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Snapshot {
+    /// Layout of the snapshot file itself
+    ///
+    /// Files written before the field existed are the legacy layout; it is
+    /// left out when saving those so their checksums still match.
+    #[serde(
+        default = "Snapshot::legacy_schema_version",
+        skip_serializing_if = "Snapshot::is_legacy_schema"
+    )]
+    pub schema_version: u32,
     /// Unique identifier for this snapshot
     pub id: String,
     /// When this snapshot was taken
     pub timestamp: DateTime<Utc>,
     /// User-provided message explaining the snapshot
     pub message: Option<String>,
+    /// Who took the snapshot, e.g. "Jane Doe <jane@example.com>"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
     /// The table data
     pub table: Table,
     /// Hashes for integrity verification
     pub hashes: TableHashes,
     /// Dependencies on other tables/files
     pub dependencies: Vec<Dependency>,
+    /// Primary keys the table was filtered to, if this is a key-subset snapshot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_subset: Option<Vec<String>>,
+    /// Cosmetic normalizations applied while loading the source file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub normalizations: Vec<String>,
+    /// Data-quality problems found in the table when the snapshot was taken,
+    /// see [`Table::quality_warnings`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quality_warnings: Vec<String>,
+    /// Whether the source had no header line, so the headers are the
+    /// generated `column_1`, `column_2`, ... and are left out on restore
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub synthetic_headers: bool,
+    /// Column types declared by a schema when the snapshot was taken;
+    /// diffs compare these columns by typed value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub column_types: BTreeMap<String, ColumnType>,
+    /// Where the table was loaded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// ID of the previous snapshot of the same source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// When the source was downloaded, for tables fetched from a URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// Changes from `parent`, for delta snapshots
+    ///
+    /// A delta snapshot stores an empty `table` (keeping only its primary
+    /// key); use `materialize` to rebuild the full table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<SnapshotDiff>,
+    /// SHA-256 over every other field, so corrupted metadata is caught too
+    ///
+    /// Set by `seal` (and `save`), checked by `load`. Snapshots written
+    /// before the field existed have none and load unchecked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
 }
 
 /// A table is just headers + rows, nothing fancy
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Table {
     /// Column names (the primary key lives here)
     pub headers: Vec<String>,
@@ -48,19 +123,142 @@ pub struct Table {
 }
 
 /// Hashes for verifying table integrity
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TableHashes {
     /// Hash of the entire table (quick integrity check)
     pub table_hash: String,
     /// Per-header hashes (column-level verification)
+    ///
+    /// From version 3, a column with no content is stored as
+    /// `EMPTY_COLUMN_HASH` instead of a hash, see `empty_columns`.
     pub header_hashes: HashMap<String, String>,
     /// Optional: per-row hashes (fine-grained verification)
     pub row_hashes: Option<Vec<String>>,
+    /// Merkle root over `row_hashes`, for proving single rows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<String>,
+    /// Hashing scheme these hashes were computed with
+    ///
+    /// Snapshots written before the field existed used the legacy scheme.
+    #[serde(default = "TableHashes::legacy_version")]
+    pub version: u32,
+    /// Hash of what the table means rather than how it is written, see
+    /// [`logical_hash`]
+    ///
+    /// Recorded when a snapshot is taken; `compute` leaves it out, since
+    /// canonicalizing every cell costs far more than hashing it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logical_hash: Option<String>,
 }
 
 impl TableHashes {
+    /// Hashing scheme used for new snapshots
+    pub const CURRENT_VERSION: u32 = 3;
+
+    /// Stands in for the hash of a column with no content, so wide sparse
+    /// tables don't store the same 64 characters over and over
+    pub const EMPTY_COLUMN_HASH: &'static str = "empty";
+
+    /// First scheme to store `EMPTY_COLUMN_HASH` for columns with no content;
+    /// version 2 hashes them like any other column
+    const EMPTY_COLUMN_VERSION: u32 = 3;
+
+    /// Original scheme: plain concatenation of every field
+    ///
+    /// Ambiguous (`["ab","c"]` and `["a","bc"]` collide), kept only so old
+    /// snapshots still verify.
+    const LEGACY_VERSION: u32 = 1;
+
+    fn legacy_version() -> u32 {
+        Self::LEGACY_VERSION
+    }
+
+    /// The first `len` characters of the table hash, for display
+    ///
+    /// Never panics: `len` is raised to at least 1 and a hash shorter than
+    /// `len` is returned whole.
+    pub fn short(&self, len: usize) -> &str {
+        let end = self
+            .table_hash
+            .char_indices()
+            .nth(len.max(1))
+            .map_or(self.table_hash.len(), |(idx, _)| idx);
+        &self.table_hash[..end]
+    }
+
+    /// Columns that had no content when hashed, in name order
+    ///
+    /// Every cell was empty or missing: usually export artifacts such as
+    /// trailing separators. Unlike [`Table::empty_columns`], whitespace
+    /// counts as content, since the column then keeps a full hash. Always
+    /// empty for hashes older than version 3.
+    pub fn empty_columns(&self) -> Vec<String> {
+        let mut columns: Vec<String> = self
+            .header_hashes
+            .iter()
+            .filter(|(_, hash)| *hash == Self::EMPTY_COLUMN_HASH)
+            .map(|(header, _)| header.clone())
+            .collect();
+        columns.sort();
+        columns
+    }
+
+    /// Inclusion proof for row `row_idx` against `merkle_root`
+    ///
+    /// Check it with [`merkle::verify_merkle_proof`]. Needs row hashes (see
+    /// `Snapshot::store_row_hashes`).
+    pub fn merkle_proof(&self, row_idx: usize) -> Result<Vec<(Side, String)>> {
+        let row_hashes = self.row_hashes.as_deref().ok_or_else(|| {
+            GitSheetsError::InvalidRowIndex("snapshot has no row hashes".to_string())
+        })?;
+        merkle::merkle_proof(row_hashes, row_idx)
+    }
+
     /// Compute hashes for a table
     pub fn compute(table: &Table) -> Self {
+        Self::compute_with_version(table, Self::CURRENT_VERSION)
+    }
+
+    /// Compute hashes for a table using a specific hashing scheme
+    #[tracing::instrument(level = "trace", skip_all, fields(rows = table.rows.len(), version = version))]
+    pub fn compute_with_version(table: &Table, version: u32) -> Self {
+        if version == Self::LEGACY_VERSION {
+            return Self::compute_legacy(table);
+        }
+
+        // Stops at a column's first cell with content, usually the first row
+        let no_content = |idx: usize| {
+            !table.rows.is_empty()
+                && table
+                    .rows
+                    .iter()
+                    .all(|row| row.get(idx).is_none_or(String::is_empty))
+        };
+        let header_hashes = table
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(idx, header)| {
+                let hash = if version >= Self::EMPTY_COLUMN_VERSION && no_content(idx) {
+                    Self::EMPTY_COLUMN_HASH.to_string()
+                } else {
+                    hash_column(table, idx)
+                };
+                (header.clone(), hash)
+            })
+            .collect();
+
+        Self {
+            table_hash: hash_table(table),
+            header_hashes,
+            row_hashes: None,
+            merkle_root: None,
+            version,
+            logical_hash: None,
+        }
+    }
+
+    fn compute_legacy(table: &Table) -> Self {
         let mut hasher = Sha256::new();
 
         // Hash the entire table by concatenating all data
@@ -95,72 +293,866 @@ impl TableHashes {
             table_hash,
             header_hashes,
             row_hashes: None,
+            merkle_root: None,
+            version: Self::LEGACY_VERSION,
+            logical_hash: None,
         }
     }
 }
 
+// Every field is fed to the hasher with its length in front, and every list
+// with its element count, so no two different tables share an input stream.
+
+fn update_field(hasher: &mut Sha256, field: &str) {
+    hasher.update((field.len() as u64).to_le_bytes());
+    hasher.update(field.as_bytes());
+}
+
+fn update_fields(hasher: &mut Sha256, fields: &[String]) {
+    hasher.update((fields.len() as u64).to_le_bytes());
+    for field in fields {
+        update_field(hasher, field);
+    }
+}
+
+/// `sample` distinct indices below `len` (all of them if `sample >= len`),
+/// chosen by Floyd's algorithm from a SplitMix64 stream seeded with `seed`
+fn sample_indices(len: usize, sample: usize, seed: u64) -> Vec<usize> {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    let mut chosen = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for upper in len - sample.min(len)..len {
+        let candidate = (next() % (upper as u64 + 1)) as usize;
+        let pick = if seen.contains(&candidate) {
+            upper
+        } else {
+            candidate
+        };
+        seen.insert(pick);
+        chosen.push(pick);
+    }
+    chosen
+}
+
+/// Hash a single row
+pub fn hash_row(row: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    update_fields(&mut hasher, row);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Hash one column: its header followed by its value in every row
+///
+/// Rows too short to have the column contribute a distinct "missing" marker.
+pub fn hash_column(table: &Table, col_idx: usize) -> String {
+    let mut hasher = Sha256::new();
+    update_field(
+        &mut hasher,
+        table.headers.get(col_idx).map_or("", String::as_str),
+    );
+    hasher.update((table.rows.len() as u64).to_le_bytes());
+    for row in &table.rows {
+        match row.get(col_idx) {
+            Some(cell) => {
+                hasher.update([1u8]);
+                update_field(&mut hasher, cell);
+            }
+            None => hasher.update([0u8]),
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Hash a whole table: headers, then every row
+pub fn hash_table(table: &Table) -> String {
+    let mut hasher = Sha256::new();
+    update_fields(&mut hasher, &table.headers);
+    hasher.update((table.rows.len() as u64).to_le_bytes());
+    for row in &table.rows {
+        update_fields(&mut hasher, row);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// A cell as `logical_hash` sees it: trimmed, dates in ISO-8601, numbers in
+/// plain form and anything else lowercased
+///
+/// Numbers with leading zeros stay text, so zip code `02134` isn't `2134`.
+fn canonical_cell(cell: &str) -> String {
+    let cell = cell.trim();
+    if let Some(date) = schema::parse_date(cell) {
+        return date.format("%Y-%m-%d").to_string();
+    }
+    let number =
+        parse_number(cell).filter(|value| value.is_finite() && !schema::has_leading_zero(cell));
+    match number {
+        // Matches `-0` as well, which would otherwise print as "-0"
+        Some(0.0) => "0".to_string(),
+        Some(value) => value.to_string(),
+        None => cell.to_lowercase(),
+    }
+}
+
+/// Hash of what a table means rather than how it is written
+///
+/// Headers are trimmed and lowercased, and cells canonicalized: trimmed,
+/// dates rewritten as ISO-8601, numbers in plain form (`$1,000.50` is
+/// `1000.5`, `1.0` is `1`) and other text lowercased. Columns are then
+/// sorted by header and rows sorted, so two exports of the same data that
+/// differ only in formatting or order share a logical hash. The primary key
+/// plays no part.
+pub fn logical_hash(table: &Table) -> String {
+    let headers: Vec<String> = table
+        .headers
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let mut order: Vec<usize> = (0..headers.len()).collect();
+    order.sort_by(|&a, &b| headers[a].cmp(&headers[b]));
+
+    let mut rows: Vec<Vec<String>> = table
+        .rows
+        .iter()
+        .map(|row| {
+            order
+                .iter()
+                .map(|&col| canonical_cell(row.get(col).map_or("", String::as_str)))
+                .collect()
+        })
+        .collect();
+    rows.sort();
+
+    hash_table(&Table {
+        headers: order.iter().map(|&col| headers[col].clone()).collect(),
+        rows,
+        primary_key: None,
+    })
+}
+
+/// Options controlling how a source file is read into a table
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Keep CRLF/CR line endings instead of normalizing them to LF
+    pub preserve_eol: bool,
+    /// Rewrite cells of date columns as ISO-8601 (`YYYY-MM-DD`)
+    pub normalize_dates: bool,
+    /// Keep leading and trailing whitespace in cells instead of trimming it
+    pub preserve_whitespace: bool,
+    /// Cell values that mean "missing", e.g. `NULL` or `N/A`, read as empty
+    pub null_tokens: Vec<String>,
+    /// Replace invalid UTF-8 with U+FFFD instead of failing with `InvalidEncoding`
+    pub lossy_utf8: bool,
+    /// The first line is data, not headers; columns are named `column_1`,
+    /// `column_2`, ... as for headerless fixed-width files
+    pub no_header: bool,
+}
+
+/// How a table is written out as CSV
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Field separator
+    pub delimiter: u8,
+    /// Which fields get quoted
+    pub quote_style: QuoteStyle,
+    /// Record terminator
+    pub line_ending: LineEnding,
+    /// Whether to write the header line first
+    pub header: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote_style: QuoteStyle::Necessary,
+            line_ending: LineEnding::Lf,
+            header: true,
+        }
+    }
+}
+
+/// Which CSV fields are quoted on write
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum QuoteStyle {
+    /// Every field
+    Always,
+    /// Only fields containing a delimiter, quote or line break
+    #[default]
+    Necessary,
+    /// No field, even if the output can't be read back unambiguously
+    Never,
+}
+
+/// Line terminator written after each CSV record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum LineEnding {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`, as Excel writes
+    Crlf,
+}
+
 /// A dependency represents a reference to another table or file
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Dependency {
     /// Name or identifier of the dependency
     pub name: String,
-    /// File path if it's external
+    /// File path if it's external, relative to the repository root when
+    /// the file is inside one
     pub path: Option<PathBuf>,
     /// Hash of the dependency at snapshot time
     pub hash: String,
 }
 
+impl Dependency {
+    /// Where the dependency lives in the checkout rooted at `repo_root`
+    ///
+    /// Absolute paths, stored for files outside any repository, are returned
+    /// as they are. A dependency without a path resolves to its name.
+    /// Snapshots from before paths were stored relative to the repository
+    /// root hold paths relative to the directory they were taken in, so a
+    /// path missing under `repo_root` that exists from the current directory
+    /// resolves there.
+    pub fn resolve(&self, repo_root: &Path) -> PathBuf {
+        let path = self
+            .path
+            .as_deref()
+            .unwrap_or_else(|| Path::new(&self.name));
+        let in_repo = repo_root.join(path);
+        if !in_repo.exists() && path.exists() {
+            return path.to_path_buf();
+        }
+        in_repo
+    }
+}
+
 // ============================================================================
 // SNAPSHOT OPERATIONS
 // ============================================================================
 
+/// Snapshots are equal when their contents are: the table, its hashes and
+/// all recorded metadata, including `parent` and `delta`
+///
+/// The `id`, `timestamp`, `fetched_at` and `checksum` are left out, since
+/// they differ between two snapshots of the same data taken at different
+/// times. A delta snapshot never equals the full snapshot it materializes
+/// to; compare materialized tables for that.
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Self) -> bool {
+        // Destructured so a new field can't be left out by accident
+        let Snapshot {
+            schema_version,
+            id: _,
+            timestamp: _,
+            message,
+            author,
+            table,
+            hashes,
+            dependencies,
+            key_subset,
+            normalizations,
+            quality_warnings,
+            synthetic_headers,
+            column_types,
+            source,
+            parent,
+            fetched_at: _,
+            delta,
+            checksum: _,
+        } = self;
+        *schema_version == other.schema_version
+            && *message == other.message
+            && *author == other.author
+            && *table == other.table
+            && *hashes == other.hashes
+            && *dependencies == other.dependencies
+            && *key_subset == other.key_subset
+            && *normalizations == other.normalizations
+            && *quality_warnings == other.quality_warnings
+            && *synthetic_headers == other.synthetic_headers
+            && *column_types == other.column_types
+            && *source == other.source
+            && *parent == other.parent
+            && *delta == other.delta
+    }
+}
+
+/// Default number of table-hash characters in a snapshot id
+pub const DEFAULT_ID_HASH_LEN: usize = 12;
+
+/// Just enough of a snapshot file to learn its schema version
+#[derive(Deserialize)]
+struct SchemaProbe {
+    #[serde(default = "Snapshot::legacy_schema_version")]
+    schema_version: u32,
+}
+
 impl Snapshot {
+    /// Snapshot file layout written by this build, and the newest it fully reads
+    pub const SCHEMA_VERSION: u32 = 2;
+
+    /// Layout of files written before `schema_version` existed
+    const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+    fn legacy_schema_version() -> u32 {
+        Self::LEGACY_SCHEMA_VERSION
+    }
+
+    fn is_legacy_schema(version: &u32) -> bool {
+        *version == Self::LEGACY_SCHEMA_VERSION
+    }
+
     /// Create a new snapshot from a table
     pub fn new(table: Table, message: Option<String>) -> Self {
-        let hashes = TableHashes::compute(&table);
+        Self::with_id_length(table, message, DEFAULT_ID_HASH_LEN)
+    }
+
+    /// Create a new snapshot whose id uses `id_length` characters of the table hash
+    pub fn with_id_length(table: Table, message: Option<String>, id_length: usize) -> Self {
+        let mut hashes = TableHashes::compute(&table);
+        hashes.logical_hash = Some(logical_hash(&table));
         let timestamp = Utc::now();
-        let id = format!("{}-{}", timestamp.timestamp(), &hashes.table_hash[..8]);
+        let id = format!("{}-{}", timestamp.timestamp(), hashes.short(id_length));
 
         Self {
+            schema_version: Self::SCHEMA_VERSION,
             id,
             timestamp,
             message,
+            author: None,
             table,
             hashes,
             dependencies: Vec::new(),
+            key_subset: None,
+            normalizations: Vec::new(),
+            quality_warnings: Vec::new(),
+            synthetic_headers: false,
+            column_types: BTreeMap::new(),
+            source: None,
+            parent: None,
+            fetched_at: None,
+            delta: None,
+            checksum: None,
+        }
+    }
+
+    /// Create a delta snapshot storing only the changes from `parent`
+    ///
+    /// `parent` must hold its full table (see `materialize`).
+    pub fn new_delta(parent: &Snapshot, new_table: &Table) -> Result<Self> {
+        let mut snapshot = Self::new(new_table.clone(), None);
+        snapshot.make_delta(parent)?;
+        Ok(snapshot)
+    }
+
+    /// Replace this snapshot's table with the changes from `parent`
+    ///
+    /// Fails, leaving the snapshot unchanged, if replaying the changes onto
+    /// `parent` does not reproduce the table exactly, e.g. when the tables
+    /// have no primary key to match rows by.
+    pub fn make_delta(&mut self, parent: &Snapshot) -> Result<()> {
+        let diff = SnapshotDiff::compute(parent, self)?;
+        let replayed = diff.apply(&parent.table)?;
+        if TableHashes::compute_with_version(&replayed, self.hashes.version).table_hash
+            != self.hashes.table_hash
+        {
+            return Err(GitSheetsError::InvalidDelta(format!(
+                "changes from '{}' don't reproduce the table (does it have a primary key?)",
+                parent.id
+            )));
+        }
+
+        self.parent = Some(parent.id.clone());
+        self.table = Table {
+            headers: Vec::new(),
+            rows: Vec::new(),
+            primary_key: self.table.primary_key.take(),
+        };
+        self.delta = Some(diff);
+        Ok(())
+    }
+
+    /// Rebuild the full table, applying the delta chain back to a full snapshot
+    ///
+    /// `resolver` loads a snapshot by id. Each step of the chain is checked
+    /// against its recorded hash. Full snapshots return their own table.
+    pub fn materialize(&self, resolver: &dyn Fn(&str) -> Result<Snapshot>) -> Result<Table> {
+        if self.delta.is_none() {
+            return Ok(self.table.clone());
+        }
+
+        // Walk back to the nearest full snapshot
+        let mut ancestors: Vec<Snapshot> = Vec::new();
+        let mut parent_id = self.parent.clone();
+        let mut table = loop {
+            let current = ancestors.last().map_or(&self.id, |s| &s.id);
+            let id = parent_id.ok_or_else(|| {
+                GitSheetsError::InvalidDelta(format!("delta snapshot '{current}' has no parent"))
+            })?;
+            if id == self.id || ancestors.iter().any(|s| s.id == id) {
+                return Err(GitSheetsError::InvalidDelta(format!(
+                    "delta chain loops at '{id}'"
+                )));
+            }
+
+            // A missing or unreadable parent is named, not just its IO error
+            let parent = resolver(&id).map_err(|e| match e {
+                GitSheetsError::InvalidDelta(_) => e,
+                e => GitSheetsError::InvalidDelta(format!(
+                    "parent '{id}' of '{current}' can't be loaded: {e}"
+                )),
+            })?;
+            if parent.delta.is_none() {
+                if !parent.verify() {
+                    return Err(GitSheetsError::InvalidDelta(format!(
+                        "base snapshot '{id}' failed verification"
+                    )));
+                }
+                break parent.table;
+            }
+            parent_id = parent.parent.clone();
+            ancestors.push(parent);
+        };
+
+        for snapshot in ancestors.iter().rev().chain(std::iter::once(self)) {
+            let delta = snapshot.delta.as_ref().expect("chain holds only deltas");
+            table = delta.apply(&table)?;
+            table.primary_key = snapshot.table.primary_key.clone();
+            if TableHashes::compute_with_version(&table, snapshot.hashes.version).table_hash
+                != snapshot.hashes.table_hash
+            {
+                return Err(GitSheetsError::InvalidDelta(format!(
+                    "snapshot '{}' does not reproduce its recorded hash",
+                    snapshot.id
+                )));
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// The id with `hash_len` characters of the table hash, for display
+    ///
+    /// The hash part is never cut shorter than the stored id's, so an id
+    /// lengthened by `disambiguate_id` stays unambiguous. Ids not of the
+    /// usual `<timestamp>-<hash>` form are returned unchanged.
+    pub fn short_id(&self, hash_len: usize) -> String {
+        let prefix = self.timestamp.timestamp().to_string();
+        match self
+            .id
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_prefix('-'))
+        {
+            Some(hash) if self.hashes.table_hash.starts_with(hash) => {
+                format!("{prefix}-{}", self.hashes.short(hash_len.max(hash.len())))
+            }
+            _ => self.id.clone(),
+        }
+    }
+
+    /// Lengthen the hash part of the id until it is not in `existing_ids`
+    ///
+    /// If even the full hash collides, the other snapshot has identical
+    /// content and was taken in the same second, e.g. two copies of one
+    /// file; a counter is appended (`-2`, `-3`, ...) so neither snapshot
+    /// replaces the other.
+    pub fn disambiguate_id(&mut self, existing_ids: &[String]) {
+        let prefix = self.timestamp.timestamp().to_string();
+        let mut len = self.id.len().saturating_sub(prefix.len() + 1);
+
+        while existing_ids.contains(&self.id) && len < self.hashes.table_hash.len() {
+            len = (len + 4).min(self.hashes.table_hash.len());
+            self.id = format!("{prefix}-{}", self.hashes.short(len));
+        }
+
+        let full = self.id.clone();
+        let mut counter = 1;
+        while existing_ids.contains(&self.id) {
+            counter += 1;
+            self.id = format!("{full}-{counter}");
         }
     }
 
     /// Add a dependency to this snapshot
+    ///
+    /// The path is stored as given; pass one relative to the repository
+    /// root (see [`relative_to_repo_root`]) so the snapshot finds it from any
+    /// directory or checkout.
     pub fn add_dependency(&mut self, name: String, path: Option<PathBuf>, hash: String) {
         self.dependencies.push(Dependency { name, path, hash });
     }
 
-    /// Save snapshot to disk as TOML
-    pub fn save(&self, path: &Path) -> Result<()> {
-        let toml_string = toml::to_string_pretty(self)?;
-        fs::write(path, toml_string)?;
+    /// Compute the envelope checksum: SHA-256 over every field but `checksum`
+    ///
+    /// Fields are hashed in a canonical JSON form (object keys sorted), so
+    /// the checksum is the same whether the snapshot is stored as TOML or
+    /// JSON.
+    pub fn envelope_checksum(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("checksum");
+        }
+        value.sort_all_objects();
+        Ok(format!("{:x}", Sha256::digest(serde_json::to_vec(&value)?)))
+    }
+
+    /// Store the envelope checksum of the snapshot's current contents
+    ///
+    /// Call after the last change and before saving.
+    pub fn seal(&mut self) -> Result<()> {
+        self.checksum = Some(self.envelope_checksum()?);
         Ok(())
     }
 
-    /// Load snapshot from disk
+    /// Whether the stored envelope checksum matches the contents
+    ///
+    /// Unsealed snapshots have nothing to check and pass.
+    pub fn verify_checksum(&self) -> Result<bool> {
+        match &self.checksum {
+            Some(checksum) => Ok(*checksum == self.envelope_checksum()?),
+            None => Ok(true),
+        }
+    }
+
+    /// Save snapshot to disk, as JSON for a `.json` path and TOML otherwise
+    ///
+    /// The file always carries an up-to-date envelope checksum; seal the
+    /// snapshot first to avoid copying it here.
+    #[cfg(feature = "native")]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if is_json(path) {
+            return write_atomic(path, &self.to_json()?);
+        }
+        write_atomic(path, &self.to_toml()?)
+    }
+
+    /// Save snapshot to disk as compact JSON
+    ///
+    /// Faster and smaller than TOML for big tables. Use a `.json` path so
+    /// `load` reads it back in the right format.
+    #[cfg(feature = "native")]
+    pub fn save_json(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &self.to_json()?)
+    }
+
+    /// Serialize as TOML, with an up-to-date envelope checksum
+    pub fn to_toml(&self) -> Result<Vec<u8>> {
+        Ok(toml::to_string_pretty(&*self.sealed()?)?.into_bytes())
+    }
+
+    /// Serialize as compact JSON, with an up-to-date envelope checksum
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&*self.sealed()?)?)
+    }
+
+    /// Parse a snapshot serialized by `to_toml` or `to_json`
+    ///
+    /// JSON is recognized by its leading `{`. Fails with `ChecksumMismatch`
+    /// like [`Snapshot::load`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot> {
+        let content = std::str::from_utf8(bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Self::parse(content, content.trim_start().starts_with('{'), "snapshot")
+    }
+
+    /// Store the snapshot in `storage` under its id, as TOML
+    pub fn save_to(&self, storage: &mut dyn SnapshotStorage) -> Result<()> {
+        storage.put(&self.id, &self.to_toml()?)
+    }
+
+    /// Load snapshot `id` from `storage`
+    pub fn load_from(storage: &dyn SnapshotStorage, id: &str) -> Result<Snapshot> {
+        let bytes = storage
+            .get(id)?
+            .ok_or_else(|| GitSheetsError::FileSystemError(format!("Snapshot '{id}' not found")))?;
+        let content = std::str::from_utf8(&bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let json = content.trim_start().starts_with('{');
+        Self::parse(content, json, format_args!("snapshot '{id}'"))
+    }
+
+    /// Load snapshot `id` from `storage`, rebuilding its table if it is a
+    /// delta
+    ///
+    /// Like `load_materialized`, with parents looked up in `storage`.
+    pub fn load_materialized_from(storage: &dyn SnapshotStorage, id: &str) -> Result<Snapshot> {
+        let mut snapshot = Self::load_from(storage, id)?;
+        if snapshot.delta.is_some() {
+            snapshot.table = snapshot.materialize(&|id| Snapshot::load_from(storage, id))?;
+        }
+        Ok(snapshot)
+    }
+
+    /// Load snapshot `id` from `storage` followed by all of its ancestors,
+    /// newest first, each with its full table
+    ///
+    /// Like `load_chain`. Every snapshot is read once and each delta is
+    /// applied to its parent's rebuilt table.
+    pub fn load_chain_from(storage: &dyn SnapshotStorage, id: &str) -> Result<Vec<Snapshot>> {
+        let mut chain = vec![Self::load_from(storage, id)?];
+        while let Some(parent_id) = chain.last().and_then(|s| s.parent.clone()) {
+            if chain.iter().any(|s| s.id == parent_id) {
+                return Err(GitSheetsError::FileSystemError(format!(
+                    "Parent chain loops at '{parent_id}'"
+                )));
+            }
+            chain.push(Self::load_from(storage, &parent_id)?);
+        }
+
+        // Oldest first, so each parent already holds its full table
+        for idx in (0..chain.len()).rev() {
+            if chain[idx].delta.is_none() {
+                continue;
+            }
+            let parent = chain.get(idx + 1).map(|parent| Snapshot {
+                delta: None,
+                ..parent.clone()
+            });
+            let resolver = |id: &str| {
+                parent.clone().ok_or_else(|| {
+                    GitSheetsError::FileSystemError(format!("Snapshot '{id}' not found"))
+                })
+            };
+            chain[idx].table = chain[idx].materialize(&resolver)?;
+        }
+        Ok(chain)
+    }
+
+    /// Find the most recent full snapshot of `source` in `storage`, the
+    /// parent of the next one
+    ///
+    /// Snapshots that fail to load are skipped, and so are key-subset
+    /// snapshots: a delta stored against one would read every row it left
+    /// out as added.
+    pub fn latest_for_source_in(
+        storage: &dyn SnapshotStorage,
+        source: &str,
+    ) -> Result<Option<Snapshot>> {
+        let mut latest: Option<Snapshot> = None;
+        for id in storage.list()? {
+            let Ok(snapshot) = Snapshot::load_from(storage, &id) else {
+                continue;
+            };
+            if snapshot.source.as_deref() == Some(source)
+                && snapshot.key_subset.is_none()
+                && latest
+                    .as_ref()
+                    .is_none_or(|l| snapshot.timestamp > l.timestamp)
+            {
+                latest = Some(snapshot);
+            }
+        }
+        Ok(latest)
+    }
+
+    /// This snapshot if its checksum is current, otherwise a sealed copy
+    fn sealed(&self) -> Result<std::borrow::Cow<'_, Snapshot>> {
+        let checksum = self.envelope_checksum()?;
+        if self.checksum.as_deref() == Some(checksum.as_str()) {
+            return Ok(std::borrow::Cow::Borrowed(self));
+        }
+        let mut sealed = self.clone();
+        sealed.checksum = Some(checksum);
+        Ok(std::borrow::Cow::Owned(sealed))
+    }
+
+    /// Load snapshot from disk, detecting JSON or TOML from the extension
+    ///
+    /// Fails with `ChecksumMismatch` if the file has an envelope checksum
+    /// that its contents no longer match.
+    #[cfg(feature = "native")]
+    #[tracing::instrument(level = "debug", fields(path = %path.display()))]
     pub fn load(path: &Path) -> Result<Snapshot> {
         let content = fs::read_to_string(path)?;
-        let snapshot: Snapshot = toml::from_str(&content)?;
+        Self::parse(&content, is_json(path), path.display())
+    }
+
+    /// Load snapshot from disk without checking its envelope checksum
+    ///
+    /// For inspecting a file that `load` rejects, e.g. to tell which parts
+    /// of an edited snapshot are still intact. Nothing loaded this way has
+    /// been verified.
+    #[cfg(feature = "native")]
+    pub fn load_unverified(path: &Path) -> Result<Snapshot> {
+        let content = fs::read_to_string(path)?;
+        if is_json(path) {
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(toml::from_str(&content)?)
+        }
+    }
+
+    /// Deserialize and check the envelope checksum; `origin` names the
+    /// snapshot in errors
+    ///
+    /// Files from a newer schema load with a warning as long as nothing in
+    /// them would be lost. Otherwise they fail with `UnsupportedSchema`
+    /// instead of a cryptic parse error or a silently truncated snapshot.
+    fn parse(content: &str, json: bool, origin: impl std::fmt::Display) -> Result<Snapshot> {
+        fn from_str<T: serde::de::DeserializeOwned>(content: &str, json: bool) -> Result<T> {
+            if json {
+                Ok(serde_json::from_str(content)?)
+            } else {
+                Ok(toml::from_str(content)?)
+            }
+        }
+
+        let version = from_str::<SchemaProbe>(content, json)?.schema_version;
+        tracing::trace!(json, version, bytes = content.len(), "parsing snapshot");
+        let newer = version > Self::SCHEMA_VERSION;
+        let unsupported = |problem: String| {
+            GitSheetsError::UnsupportedSchema(format!(
+                "{origin} uses schema version {version} but this git-sheets reads up to {}; {problem}",
+                Self::SCHEMA_VERSION
+            ))
+        };
+
+        let snapshot: Snapshot = match from_str(content, json) {
+            Ok(snapshot) => snapshot,
+            Err(e) if newer => return Err(unsupported(format!("upgrade to load it ({e})"))),
+            Err(e) => return Err(e),
+        };
+        if !snapshot.verify_checksum()? {
+            // Fields this build doesn't know were dropped while parsing
+            if newer {
+                return Err(unsupported(
+                    "it holds fields that would be lost, upgrade to load it".to_string(),
+                ));
+            }
+            return Err(GitSheetsError::ChecksumMismatch(format!(
+                "{origin} has been modified or corrupted"
+            )));
+        }
+        if newer {
+            tracing::warn!(
+                "{origin} uses schema version {version}, newer than this git-sheets ({}); loading the fields it knows",
+                Self::SCHEMA_VERSION
+            );
+        }
+        Ok(snapshot)
+    }
+
+    /// Load a snapshot from disk, rebuilding its table if it is a delta
+    ///
+    /// Parents are looked up by id in the same directory as `path`.
+    #[cfg(feature = "native")]
+    pub fn load_materialized(path: &Path) -> Result<Snapshot> {
+        let mut snapshot = Self::load(path)?;
+        if snapshot.delta.is_some() {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            snapshot.table = snapshot.materialize(&|id| Snapshot::load(&snapshot_path(dir, id)))?;
+        }
         Ok(snapshot)
     }
 
+    /// Find the most recent full snapshot of `source` in a snapshot directory
+    #[cfg(feature = "native")]
+    pub fn latest_for_source(dir: &Path, source: &str) -> Result<Option<Snapshot>> {
+        Self::latest_for_source_in(&FsStorage::new(dir), source)
+    }
+
+    /// Load a snapshot followed by all of its ancestors, newest first
+    ///
+    /// Parents are looked up by id in the same directory as `path`.
+    #[cfg(feature = "native")]
+    pub fn load_chain(path: &Path) -> Result<Vec<Snapshot>> {
+        // The temporary store is dropped here, leaving each Rc unshared
+        let chain = SnapshotStore::default().chain(path)?;
+        Ok(chain
+            .into_iter()
+            .map(std::rc::Rc::unwrap_or_clone)
+            .collect())
+    }
+
     /// Verify integrity of this snapshot
+    ///
+    /// A delta snapshot only verifies once its table has been materialized.
     pub fn verify(&self) -> bool {
-        let computed = TableHashes::compute(&self.table);
+        let computed = TableHashes::compute_with_version(&self.table, self.hashes.version);
         computed.table_hash == self.hashes.table_hash
     }
 
+    /// Verify each column's data by name, wherever the column now sits
+    ///
+    /// Checks the per-column `header_hashes` instead of the order-sensitive
+    /// table hash, so a table whose columns were only rearranged passes while
+    /// changed cells, renamed columns or reordered rows still fail.
+    pub fn verify_content_ignoring_column_order(&self) -> bool {
+        let computed = TableHashes::compute_with_version(&self.table, self.hashes.version);
+        computed.header_hashes == self.hashes.header_hashes
+    }
+
+    /// Verify the table's meaning against the stored logical hash
+    ///
+    /// Passes when the table was only reformatted or reordered since the
+    /// snapshot was taken (see [`logical_hash`]); fails when the snapshot
+    /// predates logical hashes and so has none to compare.
+    pub fn verify_logical(&self) -> bool {
+        self.hashes
+            .logical_hash
+            .as_ref()
+            .is_some_and(|stored| *stored == logical_hash(&self.table))
+    }
+
+    /// Record a hash of every row and their Merkle root, enabling
+    /// `spot_check` and `TableHashes::merkle_proof`
+    ///
+    /// Call while the snapshot still holds its full table (before
+    /// `make_delta`).
+    pub fn store_row_hashes(&mut self) {
+        let row_hashes: Vec<String> = self.table.rows.iter().map(|row| hash_row(row)).collect();
+        self.hashes.merkle_root = Some(merkle::merkle_root(&row_hashes));
+        self.hashes.row_hashes = Some(row_hashes);
+    }
+
+    /// Re-hash a random sample of rows against the stored row hashes
+    ///
+    /// A fast "probably fine" check, not a substitute for `verify`: rows
+    /// outside the sample, the headers and the row order go unchecked. The
+    /// same `seed` always picks the same rows. Fails if the snapshot has no
+    /// row hashes or their count doesn't match the table.
+    pub fn spot_check(&self, sample: usize, seed: u64) -> bool {
+        let Some(row_hashes) = &self.hashes.row_hashes else {
+            return false;
+        };
+        let rows = &self.table.rows;
+        if row_hashes.len() != rows.len() {
+            return false;
+        }
+
+        sample_indices(rows.len(), sample, seed)
+            .into_iter()
+            .all(|idx| hash_row(&rows[idx]) == row_hashes[idx])
+    }
+
     /// Verify dependencies of this snapshot
+    ///
+    /// Paths are resolved against the repository containing the current
+    /// directory, or the current directory itself outside a repository.
+    #[cfg(feature = "native")]
     pub fn verify_dependencies(&self) -> Result<()> {
+        let cwd = std::env::current_dir()?;
+        let root = find_repo_root(&cwd).unwrap_or(cwd);
+        self.verify_dependencies_in(&root)
+    }
+
+    /// Verify dependencies of this snapshot against the checkout at `repo_root`
+    #[cfg(feature = "native")]
+    pub fn verify_dependencies_in(&self, repo_root: &Path) -> Result<()> {
         for dep in &self.dependencies {
-            if let Some(dep_path) = &dep.path {
-                let content = fs::read_to_string(dep_path)?;
+            if dep.path.is_some() {
+                let content = fs::read_to_string(dep.resolve(repo_root))?;
                 let computed_hash = Self::compute_hash(&content);
                 if computed_hash != dep.hash {
                     return Err(GitSheetsError::DependencyHashMismatch(format!(
@@ -173,7 +1165,16 @@ impl Snapshot {
         Ok(())
     }
 
+    /// Record the file at `path` as a dependency, by its current contents
+    #[cfg(feature = "native")]
+    pub fn add_file_dependency(&mut self, path: &Path) -> Result<()> {
+        let hash = Self::compute_hash(&fs::read_to_string(path)?);
+        self.add_dependency(path.display().to_string(), Some(path.to_path_buf()), hash);
+        Ok(())
+    }
+
     /// Compute hash for string content
+    #[cfg(feature = "native")]
     fn compute_hash(content: &str) -> String {
         let mut hasher = Sha256::new();
         hasher.update(content.as_bytes());
@@ -187,32 +1188,378 @@ impl Snapshot {
 
 impl Table {
     /// Create a table from CSV data
+    #[cfg(feature = "native")]
     pub fn from_csv(path: &Path) -> Result<Self> {
-        let mut reader = csv::Reader::from_path(path)?;
+        Self::from_csv_with(path, &LoadOptions::default()).map(|(table, _)| table)
+    }
 
-        // Get headers
-        let headers: Vec<String> = reader
-            .headers()?
-            .iter()
-            .map(|h| h.trim().to_string())
-            .collect();
+    /// Create a table from CSV data, returning the normalizations that were applied
+    #[cfg(feature = "native")]
+    pub fn from_csv_with(path: &Path, options: &LoadOptions) -> Result<(Self, Vec<String>)> {
+        Self::from_csv_bytes(fs::read(path)?, options)
+    }
+
+    /// Create a table from a fixed-width text file, slicing each line by `widths`
+    #[cfg(feature = "native")]
+    pub fn from_fixed_width(path: &Path, widths: &[usize], has_header: bool) -> Result<Self> {
+        Self::from_fixed_width_str(&fs::read_to_string(path)?, widths, has_header)
+    }
+
+    /// Create a table from fixed-width text, slicing each line by `widths`
+    ///
+    /// Widths count characters. Cells are trimmed; a line that ends early
+    /// yields empty cells, and text past the last column is ignored. Blank
+    /// lines are skipped. Without a header row, columns are named
+    /// `column_1`, `column_2`, ...
+    pub fn from_fixed_width_str(text: &str, widths: &[usize], has_header: bool) -> Result<Self> {
+        if widths.is_empty() || widths.contains(&0) {
+            return Err(GitSheetsError::InvalidColumn(
+                "fixed-width columns need positive widths".to_string(),
+            ));
+        }
+
+        let slice = |line: &str| -> Vec<String> {
+            let mut chars = line.trim_end_matches('\r').chars();
+            widths
+                .iter()
+                .map(|&width| {
+                    let cell: String = chars.by_ref().take(width).collect();
+                    cell.trim().to_string()
+                })
+                .collect()
+        };
+
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let headers = if has_header {
+            lines.next().map(slice).unwrap_or_default()
+        } else {
+            (1..=widths.len()).map(|i| format!("column_{i}")).collect()
+        };
+        if headers.is_empty() {
+            return Err(GitSheetsError::EmptyTable);
+        }
+
+        Ok(Self {
+            headers,
+            rows: lines.map(slice).collect(),
+            primary_key: None,
+        })
+    }
+
+    /// Count the columns and data rows of a CSV file without keeping its rows
+    ///
+    /// Records are scanned one at a time through a reused buffer, so a
+    /// newline inside a quoted cell doesn't start a new row and the count
+    /// matches what `from_csv` would load.
+    #[cfg(feature = "native")]
+    pub fn quick_dimensions(path: &Path) -> Result<(usize, usize)> {
+        let file = std::io::BufReader::new(fs::File::open(path)?);
+        let mut reader = csv::Reader::from_reader(file);
+        let cols = reader.byte_headers()?.len();
+
+        let mut record = csv::ByteRecord::new();
+        let mut rows = 0;
+        while reader.read_byte_record(&mut record)? {
+            rows += 1;
+        }
+        Ok((cols, rows))
+    }
+
+    /// Fetch a CSV export URL and parse it like a local CSV file
+    #[cfg(feature = "network")]
+    pub fn from_url(url: &str) -> Result<Self> {
+        Self::from_url_with(url, &LoadOptions::default()).map(|(table, _)| table)
+    }
+
+    /// Fetch a CSV export URL, returning the normalizations that were applied
+    ///
+    /// Fails on non-success HTTP statuses and on responses that aren't CSV,
+    /// such as the HTML sign-in page served for a private Google Sheet.
+    #[cfg(feature = "network")]
+    pub fn from_url_with(url: &str, options: &LoadOptions) -> Result<(Self, Vec<String>)> {
+        let network_error = |e: reqwest::Error| GitSheetsError::NetworkError(format!("{url}: {e}"));
+
+        let response = reqwest::blocking::get(url).map_err(network_error)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(GitSheetsError::NetworkError(format!(
+                "{url} returned HTTP {status}"
+            )));
+        }
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        if !media_type.is_empty() && !CSV_MEDIA_TYPES.contains(&media_type) {
+            return Err(GitSheetsError::NetworkError(format!(
+                "{url} returned '{media_type}', not CSV (is the sheet shared and the URL a CSV export link?)"
+            )));
+        }
+
+        let bytes = response.bytes().map_err(network_error)?;
+        Self::from_csv_bytes(bytes.to_vec(), options)
+    }
+
+    /// Create a table from in-memory CSV data, returning the normalizations that were applied
+    #[tracing::instrument(level = "debug", skip_all, fields(bytes = bytes.len()))]
+    pub fn from_csv_bytes(
+        mut bytes: Vec<u8>,
+        options: &LoadOptions,
+    ) -> Result<(Self, Vec<String>)> {
+        let mut normalizations = Vec::new();
+
+        // The CSV reader's own UTF-8 error names neither the byte nor the
+        // column, so find the first bad sequence here
+        if !options.lossy_utf8
+            && let Err(e) = std::str::from_utf8(&bytes)
+        {
+            return Err(invalid_utf8(&bytes, e.valid_up_to()));
+        }
+
+        // A BOM is an encoding artifact, not content
+        if bytes.starts_with(UTF8_BOM) {
+            bytes.drain(..UTF8_BOM.len());
+            normalizations.push("stripped UTF-8 BOM".to_string());
+        }
+
+        // CRLF vs LF only matters inside quoted multi-line cells, but there it
+        // changes the hash without changing the meaning
+        if !options.preserve_eol && bytes.contains(&b'\r') {
+            bytes = normalize_line_endings(&bytes);
+            normalizations.push("normalized line endings to LF".to_string());
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(!options.no_header)
+            .from_reader(bytes.as_slice());
+
+        // Fields are decoded here rather than by the reader so invalid UTF-8
+        // can be replaced; without `lossy_utf8` there is none left by now
+        let mut repaired = 0;
+        let mut decode = |field: &[u8]| {
+            let text = String::from_utf8_lossy(field);
+            if matches!(text, std::borrow::Cow::Owned(_)) {
+                repaired += 1;
+            }
+            text.into_owned()
+        };
+
+        // Get headers; without a header line the reader still reports the
+        // first record here, and yields it again as a row below
+        let first = reader
+            .byte_headers()
+            .map_err(|e| csv_error_with_line(e, &bytes))?;
+        let headers: Vec<String> = if options.no_header {
+            (1..=first.len()).map(|i| format!("column_{i}")).collect()
+        } else {
+            first.iter().map(|h| decode(h).trim().to_string()).collect()
+        };
 
         // Get rows
         let mut rows = Vec::new();
-        for result in reader.records() {
-            let record = result?;
-            let row: Vec<String> = record.iter().map(|cell| cell.trim().to_string()).collect();
+        for result in reader.byte_records() {
+            let record = result.map_err(|e| csv_error_with_line(e, &bytes))?;
+            let row: Vec<String> = record
+                .iter()
+                .map(|cell| {
+                    let cell = decode(cell);
+                    if options.preserve_whitespace {
+                        cell
+                    } else {
+                        cell.trim().to_string()
+                    }
+                })
+                .collect();
             rows.push(row);
         }
+        if repaired > 0 {
+            normalizations.push(format!(
+                "replaced invalid UTF-8 in {repaired} cell(s) with U+FFFD"
+            ));
+        }
 
         // Allow empty tables (headers but no data rows) - this is a valid state
         // that should be tracked as a snapshot
 
-        Ok(Self {
+        let mut table = Self {
             headers,
             rows,
             primary_key: None,
-        })
+        };
+        if !options.null_tokens.is_empty() {
+            normalizations.extend(table.normalize_null_tokens(&options.null_tokens));
+        }
+        if options.normalize_dates {
+            normalizations.extend(table.normalize_dates());
+        }
+        tracing::debug!(
+            rows = table.rows.len(),
+            columns = table.headers.len(),
+            normalizations = normalizations.len(),
+            "table loaded"
+        );
+        Ok((table, normalizations))
+    }
+
+    /// Stream the table as CSV to `w`, headers first
+    pub fn write_csv_with<W: std::io::Write>(&self, w: W, options: &WriteOptions) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            // Ragged rows are written as they were loaded
+            .flexible(true)
+            .delimiter(options.delimiter)
+            .quote_style(match options.quote_style {
+                QuoteStyle::Always => csv::QuoteStyle::Always,
+                QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+                QuoteStyle::Never => csv::QuoteStyle::Never,
+            })
+            .terminator(match options.line_ending {
+                LineEnding::Lf => csv::Terminator::Any(b'\n'),
+                LineEnding::Crlf => csv::Terminator::CRLF,
+            })
+            .from_writer(w);
+        if options.header {
+            writer.write_record(&self.headers)?;
+        }
+        for row in &self.rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Render the table as CSV with the default `WriteOptions`
+    pub fn to_csv_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_csv_with(&mut bytes, &WriteOptions::default())?;
+        Ok(bytes)
+    }
+
+    /// Write the table to a CSV file, replacing it atomically
+    #[cfg(feature = "native")]
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &self.to_csv_bytes()?)
+    }
+
+    /// Infer the type of a column from its values
+    pub fn column_type(&self, col_idx: usize) -> ColumnType {
+        schema::infer_column_type(
+            self.rows
+                .iter()
+                .filter_map(|row| row.get(col_idx).map(String::as_str)),
+        )
+    }
+
+    /// Rewrite every date column in ISO-8601 so the hash reflects the date, not its display
+    ///
+    /// Returns a note for each column that changed.
+    pub fn normalize_dates(&mut self) -> Vec<String> {
+        let mut notes = Vec::new();
+        for col_idx in 0..self.headers.len() {
+            if self.column_type(col_idx) != ColumnType::Date {
+                continue;
+            }
+
+            let mut changed = false;
+            for cell in self.rows.iter_mut().filter_map(|row| row.get_mut(col_idx)) {
+                if let Some(date) = schema::parse_date(cell) {
+                    let canonical = date.format("%Y-%m-%d").to_string();
+                    if *cell != canonical {
+                        *cell = canonical;
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                notes.push(format!(
+                    "normalized dates in column '{}' to ISO-8601",
+                    self.headers[col_idx]
+                ));
+            }
+        }
+        notes
+    }
+
+    /// Empty every cell that is one of `tokens`, so `NULL` and `""` compare equal
+    ///
+    /// Matching is exact and case-sensitive, ignoring surrounding whitespace.
+    /// Returns a note naming the tokens that were found.
+    pub fn normalize_null_tokens(&mut self, tokens: &[String]) -> Vec<String> {
+        let mut found: Vec<&str> = Vec::new();
+        let mut count = 0;
+        for cell in self.rows.iter_mut().flatten() {
+            let Some(token) = tokens.iter().find(|t| cell.trim() == t.as_str()) else {
+                continue;
+            };
+            if !found.contains(&token.as_str()) {
+                found.push(token);
+            }
+            cell.clear();
+            count += 1;
+        }
+
+        if count == 0 {
+            return Vec::new();
+        }
+        vec![format!(
+            "normalized {count} null token(s) ({}) to empty",
+            found.join(", ")
+        )]
+    }
+
+    /// Check every cell of the given columns against their validators
+    ///
+    /// Returns one error per failing cell and validator, in row order.
+    pub fn validate(&self, validators: &[(usize, Box<dyn Validator>)]) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        for (row_idx, row) in self.rows.iter().enumerate() {
+            for (col, validator) in validators {
+                let value = row.get(*col).map_or("", String::as_str);
+                if let Err(message) = validator.check(value) {
+                    errors.push(ValidationError {
+                        row: row_idx,
+                        col: *col,
+                        column: self.headers.get(*col).cloned().unwrap_or_default(),
+                        value: value.to_string(),
+                        message,
+                    });
+                }
+            }
+        }
+        errors
+    }
+
+    /// Check the cells of the columns `schema` declares against their types
+    ///
+    /// Fails with `InvalidColumn` if the schema declares a column the table
+    /// doesn't have. Otherwise returns one error per non-empty cell that
+    /// isn't a value of its column's type, in row order.
+    pub fn apply_schema(&self, schema: &Schema) -> Result<Vec<ValidationError>> {
+        let mut validators: Vec<(usize, Box<dyn Validator>)> = Vec::new();
+        for (name, &ty) in &schema.columns {
+            let col = self.headers.iter().position(|h| h == name).ok_or_else(|| {
+                GitSheetsError::InvalidColumn(format!(
+                    "the schema declares column '{name}', which the table doesn't have (columns are {})",
+                    self.headers.join(", ")
+                ))
+            })?;
+            validators.push((col, Box::new(validate::TypeValidator(ty))));
+        }
+        validators.sort_by_key(|(col, _)| *col);
+        Ok(self.validate(&validators))
+    }
+
+    /// Names of the primary key columns, or `None` without a primary key
+    pub fn primary_key_names(&self) -> Option<Vec<&str>> {
+        let pk = self.primary_key.as_ref()?;
+        Some(
+            pk.iter()
+                .filter_map(|&idx| self.headers.get(idx).map(String::as_str))
+                .collect(),
+        )
     }
 
     /// Set which columns form the primary key
@@ -225,7 +1572,7 @@ impl Table {
         let pk_indices = self
             .primary_key
             .as_ref()
-            .ok_or_else(|| GitSheetsError::NoPrimaryKey)?;
+            .ok_or(GitSheetsError::NoPrimaryKey)?;
 
         let row = self.rows.get(row_idx).ok_or_else(|| {
             GitSheetsError::InvalidRowIndex(format!(
@@ -246,13 +1593,559 @@ impl Table {
 
         Ok(pk_values)
     }
+
+    /// Find the row whose primary key values equal `key`
+    pub fn find_row_by_key(&self, key: &[String]) -> Result<Option<usize>> {
+        let pk_indices = self
+            .primary_key
+            .as_ref()
+            .ok_or(GitSheetsError::NoPrimaryKey)?;
+
+        Ok(self.rows.iter().position(|row| {
+            pk_indices.len() == key.len()
+                && pk_indices
+                    .iter()
+                    .zip(key)
+                    .all(|(&idx, value)| row.get(idx) == Some(value))
+        }))
+    }
+
+    /// View a row as a header -> value map, preserving column order
+    pub fn row_as_map(&self, row_idx: usize) -> Option<IndexMap<String, String>> {
+        let row = self.rows.get(row_idx)?;
+        Some(
+            self.headers
+                .iter()
+                .enumerate()
+                .map(|(idx, header)| (header.clone(), row.get(idx).cloned().unwrap_or_default()))
+                .collect(),
+        )
+    }
+
+    /// Iterate over all rows as header -> value maps
+    pub fn iter_rows_as_map(&self) -> impl Iterator<Item = IndexMap<String, String>> + '_ {
+        (0..self.rows.len()).filter_map(|idx| self.row_as_map(idx))
+    }
+
+    /// Primary key values that appear on more than one row, in first-seen order
+    pub fn duplicate_keys(&self) -> Result<Vec<Vec<String>>> {
+        if self.primary_key.is_none() {
+            return Err(GitSheetsError::NoPrimaryKey);
+        }
+
+        let mut seen = HashMap::new();
+        let mut duplicates = Vec::new();
+        for idx in 0..self.rows.len() {
+            let key = self.get_row_key(idx)?;
+            let count = seen.entry(key.clone()).or_insert(0);
+            *count += 1;
+            if *count == 2 {
+                duplicates.push(key);
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// Produce a copy of this table containing only the given rows, in order
+    pub fn select_rows(&self, indices: &[usize]) -> Table {
+        Table {
+            headers: self.headers.clone(),
+            rows: indices
+                .iter()
+                .filter_map(|&idx| self.rows.get(idx).cloned())
+                .collect(),
+            primary_key: self.primary_key.clone(),
+        }
+    }
+
+    /// Add the rows of `other`, which must have exactly the same headers
+    ///
+    /// For tables split across several files, such as monthly exports.
+    pub fn append_rows(&mut self, other: Table) -> Result<()> {
+        if other.headers != self.headers {
+            let mismatch = self
+                .headers
+                .iter()
+                .zip(&other.headers)
+                .position(|(ours, theirs)| ours != theirs);
+            return Err(GitSheetsError::InvalidColumn(match mismatch {
+                Some(idx) => format!(
+                    "column {} is '{}', expected '{}'",
+                    idx + 1,
+                    other.headers[idx],
+                    self.headers[idx]
+                ),
+                None => format!(
+                    "{} columns, expected {}",
+                    other.headers.len(),
+                    self.headers.len()
+                ),
+            }));
+        }
+        self.rows.extend(other.rows);
+        Ok(())
+    }
+
+    /// Indices of the rows with each of the given primary keys, in key order,
+    /// and the keys that match no row
+    ///
+    /// Every row of a duplicated key is included, in table order, so none is
+    /// silently dropped. Fails if the table has no primary key.
+    pub fn rows_with_keys<'k>(
+        &self,
+        keys: &'k [Vec<String>],
+    ) -> Result<(Vec<usize>, Vec<&'k [String]>)> {
+        let pk_indices = self
+            .primary_key
+            .as_ref()
+            .ok_or(GitSheetsError::NoPrimaryKey)?;
+
+        let mut indices = Vec::new();
+        let mut missing = Vec::new();
+        for key in keys {
+            let before = indices.len();
+            indices.extend(self.rows.iter().enumerate().filter_map(|(idx, row)| {
+                (pk_indices.len() == key.len()
+                    && pk_indices
+                        .iter()
+                        .zip(key)
+                        .all(|(&col, value)| row.get(col) == Some(value)))
+                .then_some(idx)
+            }));
+            if indices.len() == before {
+                missing.push(key.as_slice());
+            }
+        }
+        Ok((indices, missing))
+    }
+
+    /// Produce a copy of this table containing only the rows with the given
+    /// primary keys, in that order
+    ///
+    /// Every row of a duplicated key is kept. Fails if the table has no
+    /// primary key, or naming every key that matches no row.
+    pub fn select_keys(&self, keys: &[Vec<String>]) -> Result<Table> {
+        let (indices, missing) = self.rows_with_keys(keys)?;
+        if !missing.is_empty() {
+            let missing: Vec<String> = missing
+                .iter()
+                .map(|key| format!("'{}'", key.join("|")))
+                .collect();
+            return Err(GitSheetsError::KeyNotFound(format!(
+                "{} not in the table",
+                missing.join(", ")
+            )));
+        }
+        Ok(self.select_rows(&indices))
+    }
+
+    /// Produce a copy of this table with columns arranged in the given header order
+    ///
+    /// `order` must name every header exactly once. Row cells and primary key
+    /// indices are remapped so they follow their column.
+    pub fn reorder_columns(&self, order: &[String]) -> Result<Table> {
+        if order.len() != self.headers.len() {
+            return Err(GitSheetsError::InvalidColumn(format!(
+                "Expected {} columns in new order, got {}",
+                self.headers.len(),
+                order.len()
+            )));
+        }
+
+        // Old column index for each position in the new order
+        let mut mapping = Vec::with_capacity(order.len());
+        for name in order {
+            let old_idx =
+                self.headers.iter().position(|h| h == name).ok_or_else(|| {
+                    GitSheetsError::InvalidColumn(format!("Unknown column '{name}'"))
+                })?;
+            if mapping.contains(&old_idx) {
+                return Err(GitSheetsError::InvalidColumn(format!(
+                    "Column '{name}' listed more than once"
+                )));
+            }
+            mapping.push(old_idx);
+        }
+
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                mapping
+                    .iter()
+                    .map(|&old_idx| row.get(old_idx).cloned().unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+
+        let primary_key = self.primary_key.as_ref().map(|pk| {
+            pk.iter()
+                .filter_map(|old_idx| mapping.iter().position(|m| m == old_idx))
+                .collect()
+        });
+
+        Ok(Table {
+            headers: order.to_vec(),
+            rows,
+            primary_key,
+        })
+    }
+
+    /// Check whether two tables hold the same data regardless of row and column order
+    ///
+    /// Columns are matched by name and rows are compared as a multiset, so a
+    /// primary key is not needed. The primary key definition itself is ignored.
+    pub fn equals_ignoring_order(&self, other: &Table) -> bool {
+        if self.headers.len() != other.headers.len() || self.rows.len() != other.rows.len() {
+            return false;
+        }
+
+        let mut order = self.headers.clone();
+        order.sort();
+        let mut other_order = other.headers.clone();
+        other_order.sort();
+        if order != other_order {
+            return false;
+        }
+
+        let (Ok(ours), Ok(theirs)) = (self.reorder_columns(&order), other.reorder_columns(&order))
+        else {
+            return false;
+        };
+
+        let mut our_rows = ours.rows;
+        let mut their_rows = theirs.rows;
+        our_rows.sort();
+        their_rows.sort();
+        our_rows == their_rows
+    }
+
+    /// Produce a copy of this table without the named columns
+    ///
+    /// Errors if a name is unknown or if it is part of the primary key.
+    pub fn drop_columns(&self, names: &[String]) -> Result<Table> {
+        for name in names {
+            let idx =
+                self.headers.iter().position(|h| h == name).ok_or_else(|| {
+                    GitSheetsError::InvalidColumn(format!("Unknown column '{name}'"))
+                })?;
+            if self
+                .primary_key
+                .as_ref()
+                .is_some_and(|pk| pk.contains(&idx))
+            {
+                return Err(GitSheetsError::InvalidColumn(format!(
+                    "Column '{name}' is part of the primary key"
+                )));
+            }
+        }
+
+        let kept: Vec<String> = self
+            .headers
+            .iter()
+            .filter(|h| !names.contains(h))
+            .cloned()
+            .collect();
+        let mut table = self.reorder_columns(&[kept.as_slice(), names].concat())?;
+        table.headers.truncate(kept.len());
+        for row in &mut table.rows {
+            row.truncate(kept.len());
+        }
+        Ok(table)
+    }
+
+    /// Find columns that probably change on every export
+    ///
+    /// A column is flagged when its header looks like a timestamp
+    /// (`updated`, `timestamp`, `exported`, `*_at`) or when every non-empty
+    /// value is a datetime from the last week.
+    pub fn volatile_columns(&self) -> Vec<String> {
+        const PATTERNS: [&str; 3] = ["updated", "timestamp", "exported"];
+        let recent = Utc::now() - chrono::Duration::days(7);
+
+        let mut volatile = Vec::new();
+        for (idx, header) in self.headers.iter().enumerate() {
+            let lower = header.to_lowercase();
+            let by_name = PATTERNS.iter().any(|p| lower.contains(p)) || lower.ends_with("_at");
+
+            let mut values = self
+                .rows
+                .iter()
+                .filter_map(|row| row.get(idx))
+                .filter(|cell| !cell.is_empty())
+                .peekable();
+            let by_values = values.peek().is_some()
+                && values.all(|cell| parse_datetime(cell).is_some_and(|dt| dt >= recent));
+
+            if by_name || by_values {
+                volatile.push(header.clone());
+            }
+        }
+        volatile
+    }
+
+    /// Find columns whose every cell is blank
+    ///
+    /// Usually export artifacts such as trailing separators. Computed on
+    /// each call; [`TableHashes::empty_columns`] gives the columns with no
+    /// content at all as recorded when hashed. Rows too short to reach a
+    /// column count as blank there. A table with no rows has no empty
+    /// columns.
+    pub fn empty_columns(&self) -> Vec<String> {
+        if self.rows.is_empty() {
+            return Vec::new();
+        }
+        self.headers
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| {
+                self.rows
+                    .iter()
+                    .all(|row| row.get(*idx).is_none_or(|cell| cell.trim().is_empty()))
+            })
+            .map(|(_, header)| header.clone())
+            .collect()
+    }
+}
+
+/// File extensions snapshots can be stored under; the first is the default
+pub const SNAPSHOT_EXTENSIONS: &[&str] = &["toml", "json"];
+
+/// Whether a path names a snapshot file in one of the supported formats
+#[cfg(feature = "native")]
+pub fn is_snapshot_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SNAPSHOT_EXTENSIONS.contains(&ext))
+}
+
+/// Path of the snapshot `id` in `dir`, in whichever format exists
+///
+/// Falls back to the default `.toml` name when there is none yet.
+#[cfg(feature = "native")]
+pub fn snapshot_path(dir: &Path, id: &str) -> PathBuf {
+    SNAPSHOT_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{id}.{ext}")))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dir.join(format!("{id}.{}", SNAPSHOT_EXTENSIONS[0])))
+}
+
+/// Whether a path has a `.json` extension
+#[cfg(feature = "native")]
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// Write a file so that readers see either the old or the new contents, never a mix
+///
+/// Data goes to `.<name>.tmp` in the same directory, is flushed to disk, and
+/// is then renamed over `path`. If anything fails the temp file is removed and
+/// an existing file at `path` is left untouched.
+#[cfg(feature = "native")]
+#[tracing::instrument(level = "debug", skip(contents), fields(path = %path.display(), bytes = contents.len()))]
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        GitSheetsError::FileSystemError(format!("Not a file path: {}", path.display()))
+    })?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    Ok(result?)
+}
+
+/// Parse a cell as a number, tolerating thousands separators and a leading `$`
+pub(crate) fn parse_number(cell: &str) -> Option<f64> {
+    let cleaned: String = cell
+        .trim()
+        .trim_start_matches('$')
+        .chars()
+        .filter(|&c| c != ',')
+        .collect();
+    if cleaned.is_empty() {
+        return None;
+    }
+    cleaned.parse().ok()
+}
+
+/// Attach the source line number and a snippet of that line to a CSV error
+fn csv_error_with_line(error: csv::Error, bytes: &[u8]) -> GitSheetsError {
+    let line = match error.kind() {
+        csv::ErrorKind::UnequalLengths { pos: Some(pos), .. }
+        | csv::ErrorKind::Utf8 { pos: Some(pos), .. }
+        | csv::ErrorKind::Deserialize { pos: Some(pos), .. } => pos.line() as usize,
+        _ => return GitSheetsError::CsvError(error),
+    };
+
+    GitSheetsError::Csv {
+        line,
+        snippet: line_snippet(bytes, line),
+        source: error,
+    }
+}
+
+/// Line `line` (1-based) of `bytes`, truncated for an error message
+fn line_snippet(bytes: &[u8], line: usize) -> String {
+    const MAX_SNIPPET: usize = 80;
+
+    let raw = bytes
+        .split(|&b| b == b'\n')
+        .nth(line - 1)
+        .unwrap_or_default();
+    let text = String::from_utf8_lossy(raw);
+    let mut snippet: String = text.trim_end().chars().take(MAX_SNIPPET).collect();
+    if text.trim_end().chars().count() > MAX_SNIPPET {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// Point at the invalid UTF-8 sequence starting at byte `offset`
+fn invalid_utf8(bytes: &[u8], offset: usize) -> GitSheetsError {
+    let line_start = bytes[..offset]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |pos| pos + 1);
+    let line = bytes[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+    GitSheetsError::InvalidEncoding(format!(
+        "invalid UTF-8 at byte offset {offset} (line {line}, byte {} of the line)\n  {line} | {}",
+        offset - line_start + 1,
+        line_snippet(bytes, line)
+    ))
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Content types accepted from a CSV export URL
+#[cfg(feature = "network")]
+const CSV_MEDIA_TYPES: &[&str] = &[
+    "text/csv",
+    "application/csv",
+    "text/plain",
+    "application/octet-stream",
+];
+
+/// Convert CRLF and lone CR line endings to LF
+fn normalize_line_endings(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().peekable();
+    while let Some(&b) = iter.next() {
+        if b == b'\r' {
+            if iter.peek() == Some(&&b'\n') {
+                iter.next();
+            }
+            out.push(b'\n');
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+/// Parse a cell as a datetime in one of the common export formats
+fn parse_datetime(cell: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(cell) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|fmt| chrono::NaiveDateTime::parse_from_str(cell, fmt).ok())
+        .map(|naive| naive.and_utc())
 }
 
 // ============================================================================
 // REPO OPERATIONS
 // ============================================================================
 
+/// The working tree root of the git repository containing `start`
+///
+/// Bare repositories have no working tree and give `None`.
+#[cfg(feature = "native")]
+pub fn find_repo_root(start: &Path) -> Option<PathBuf> {
+    let repo = git2::Repository::discover(start).ok()?;
+    repo.workdir().map(Path::to_path_buf)
+}
+
+/// `path` relative to the root of the repository containing it, if any
+#[cfg(feature = "native")]
+pub fn relative_to_repo_root(path: &Path) -> Option<PathBuf> {
+    let absolute = path
+        .canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .ok()?;
+    let root = find_repo_root(absolute.parent()?)?;
+    let root = root.canonicalize().unwrap_or(root);
+    absolute.strip_prefix(&root).ok().map(Path::to_path_buf)
+}
+
+/// Contents of `path` as committed at HEAD, like `git show HEAD:<path>`
+///
+/// The repository is found by searching upwards from the file, which need
+/// not exist in the working tree any more.
+#[cfg(feature = "native")]
+pub fn read_committed(path: &Path) -> Result<Vec<u8>> {
+    read_at_revision(path, "HEAD")
+}
+
+/// Contents of `path` at git revision `rev`, like `git show <rev>:<path>`
+///
+/// `rev` is anything git understands: a commit id, branch, tag or
+/// `HEAD~3`. Fails if the file wasn't tracked at that revision.
+#[cfg(feature = "native")]
+pub fn read_at_revision(path: &Path, rev: &str) -> Result<Vec<u8>> {
+    let not_tracked = || {
+        GitSheetsError::FileSystemError(format!(
+            "{} is not tracked in git at {rev}",
+            path.display()
+        ))
+    };
+
+    let absolute = std::path::absolute(path)?;
+    let file_name = absolute.file_name().ok_or_else(not_tracked)?;
+    let dir = absolute.parent().unwrap_or(Path::new("/")).canonicalize()?;
+    let repo = git2::Repository::discover(&dir).map_err(|_| {
+        GitSheetsError::FileSystemError(format!(
+            "{} is not inside a git repository",
+            path.display()
+        ))
+    })?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| GitSheetsError::FileSystemError("git repository is bare".to_string()))?
+        .canonicalize()?;
+    let relative = dir
+        .join(file_name)
+        .strip_prefix(&workdir)
+        .map_err(|_| not_tracked())?
+        .to_path_buf();
+
+    let tree = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_tree())
+        .map_err(|_| {
+            GitSheetsError::FileSystemError(if repo.is_empty().unwrap_or(false) {
+                "git repository has no commits yet".to_string()
+            } else {
+                format!("unknown git revision '{rev}'")
+            })
+        })?;
+    let entry = tree.get_path(&relative).map_err(|_| not_tracked())?;
+    let object = entry.to_object(&repo)?;
+    let blob = object.as_blob().ok_or_else(not_tracked)?;
+    Ok(blob.content().to_vec())
+}
+
 /// A git-sheets repository
+#[cfg(feature = "native")]
 pub struct GitSheetsRepo {
     /// Path to the repository
     pub path: PathBuf,
@@ -260,6 +2153,7 @@ pub struct GitSheetsRepo {
     pub git_repo: Option<git2::Repository>,
 }
 
+#[cfg(feature = "native")]
 impl GitSheetsRepo {
     /// Initialize a new git-sheets repository
     pub fn init(path: PathBuf) -> Result<GitSheetsRepo> {
@@ -321,9 +2215,7 @@ impl GitSheetsRepo {
                     let path = entry.path();
                     if path.is_file() {
                         let rel_path = path.strip_prefix(&self.path).map_err(|e| {
-                            GitSheetsError::FileSystemError(format!(
-                                "Failed to strip prefix: {e}"
-                            ))
+                            GitSheetsError::FileSystemError(format!("Failed to strip prefix: {e}"))
                         })?;
                         index.add_path(rel_path)?;
                     }
@@ -389,7 +2281,7 @@ impl GitSheetsRepo {
             .filter(|e| e.file_type().is_file())
         {
             let path = entry.path();
-            if path.extension().map_or(false, |ext| ext == "toml") {
+            if is_snapshot_file(path) {
                 match Snapshot::load(path) {
                     Ok(snapshot) => snapshots.push(snapshot),
                     Err(e) => {
@@ -0,0 +1,114 @@
+// git-sheets: Parquet export - snapshot tables for DuckDB, pandas and friends
+
+use super::schema::{self, ColumnType};
+use super::{GitSheetsError, Result, Table, write_atomic};
+use ::parquet::arrow::ArrowWriter;
+use arrow_array::{
+    ArrayRef, BooleanArray, Date32Array, Float64Array, Int64Array, RecordBatch, StringArray,
+};
+use arrow_schema::{DataType, Field, Schema};
+use chrono::NaiveDate;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Rows looked at when inferring a column's type
+///
+/// Keeps inference cheap on huge tables; a later value that doesn't fit
+/// turns the column back into text.
+const TYPE_SAMPLE_ROWS: usize = 1000;
+
+fn export_error(e: impl std::fmt::Display) -> GitSheetsError {
+    GitSheetsError::ExportError(e.to_string())
+}
+
+fn data_type(ty: ColumnType) -> DataType {
+    match ty {
+        ColumnType::Text | ColumnType::Code => DataType::Utf8,
+        ColumnType::Date => DataType::Date32,
+        ColumnType::Integer => DataType::Int64,
+        ColumnType::Float => DataType::Float64,
+        ColumnType::Boolean => DataType::Boolean,
+    }
+}
+
+/// Build the Arrow array for one column as `ty`, empty cells as nulls
+///
+/// Returns `None` if a non-empty cell doesn't fit the type.
+fn typed_array(cells: &[&str], ty: ColumnType) -> Option<ArrayRef> {
+    fn collect<T>(cells: &[&str], parse: impl Fn(&str) -> Option<T>) -> Option<Vec<Option<T>>> {
+        cells
+            .iter()
+            .map(|cell| match cell.trim() {
+                "" => Some(None),
+                value => parse(value).map(Some),
+            })
+            .collect()
+    }
+
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("valid date");
+    let array: ArrayRef = match ty {
+        ColumnType::Text | ColumnType::Code => Arc::new(StringArray::from(cells.to_vec())),
+        ColumnType::Integer => Arc::new(Int64Array::from(collect(cells, schema::parse_integer)?)),
+        ColumnType::Float => Arc::new(Float64Array::from(collect(cells, schema::parse_float)?)),
+        ColumnType::Boolean => Arc::new(BooleanArray::from(collect(cells, schema::parse_bool)?)),
+        ColumnType::Date => Arc::new(Date32Array::from(collect(cells, |cell| {
+            let days = (schema::parse_date(cell)? - epoch).num_days();
+            i32::try_from(days).ok()
+        })?)),
+    };
+    Some(array)
+}
+
+impl Table {
+    /// Write the table as a Parquet file, replacing it atomically
+    ///
+    /// Column types are inferred from the first rows and mapped to Arrow
+    /// types (Int64, Float64, Boolean, Date32, or Utf8 for text). Empty cells
+    /// in typed columns become nulls. A column with a value that doesn't fit
+    /// its inferred type is written as text instead; the returned warnings
+    /// say which.
+    pub fn write_parquet(&self, path: &Path) -> Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        let mut fields = Vec::new();
+        let mut columns = Vec::new();
+
+        for (col_idx, header) in self.headers.iter().enumerate() {
+            let cells: Vec<&str> = self
+                .rows
+                .iter()
+                .map(|row| row.get(col_idx).map_or("", String::as_str))
+                .collect();
+            let ty = schema::infer_column_type(cells.iter().take(TYPE_SAMPLE_ROWS).copied());
+
+            let (ty, array) = match typed_array(&cells, ty) {
+                Some(array) => (ty, array),
+                None => {
+                    let misfit = cells
+                        .iter()
+                        .position(|cell| !cell.trim().is_empty() && !schema::fits(ty, cell))
+                        .unwrap_or_default();
+                    warnings.push(format!(
+                        "column '{header}' looked like {ty:?} but row {misfit} holds '{}'; exported as text",
+                        cells[misfit]
+                    ));
+                    let text = typed_array(&cells, ColumnType::Text).expect("text always fits");
+                    (ColumnType::Text, text)
+                }
+            };
+            fields.push(Field::new(header, data_type(ty), true));
+            columns.push(array);
+        }
+
+        let schema = Arc::new(Schema::new(fields));
+        let batch = if columns.is_empty() {
+            RecordBatch::new_empty(Arc::clone(&schema))
+        } else {
+            RecordBatch::try_new(Arc::clone(&schema), columns).map_err(export_error)?
+        };
+        let mut writer = ArrowWriter::try_new(Vec::new(), schema, None).map_err(export_error)?;
+        writer.write(&batch).map_err(export_error)?;
+        let bytes = writer.into_inner().map_err(export_error)?;
+        write_atomic(path, &bytes)?;
+        Ok(warnings)
+    }
+}
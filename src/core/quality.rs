@@ -0,0 +1,92 @@
+// git-sheets: Data quality - problems in a table worth fixing while the
+// spreadsheet is still open
+
+use super::Table;
+use super::schema::{ColumnType, fits};
+
+/// Duplicate keys listed by name before the rest are only counted
+const LISTED_KEYS: usize = 5;
+
+/// The type most non-empty cells of a column fit, with the cells that don't,
+/// if the column is neither uniform nor mostly text
+fn mostly_typed(table: &Table, col: usize) -> Option<(ColumnType, Vec<&str>)> {
+    let values: Vec<&str> = table
+        .rows
+        .iter()
+        .filter_map(|row| row.get(col).map(String::as_str))
+        .filter(|cell| !cell.trim().is_empty())
+        .collect();
+    [ColumnType::Float, ColumnType::Date, ColumnType::Boolean]
+        .into_iter()
+        .filter_map(|ty| {
+            let misfits: Vec<&str> = values.iter().copied().filter(|v| !fits(ty, v)).collect();
+            (!misfits.is_empty() && misfits.len() * 2 < values.len()).then_some((ty, misfits))
+        })
+        .min_by_key(|(_, misfits)| misfits.len())
+}
+
+impl Table {
+    /// Problems in the table that are easiest to fix at capture time
+    ///
+    /// Reports duplicate primary keys, rows with more or fewer cells than
+    /// there are headers, columns with no values, columns whose values are
+    /// mostly numbers, dates or booleans but not all, and columns that look
+    /// like export timestamps. Each problem is one line of text; a clean
+    /// table has none.
+    pub fn quality_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if let Ok(duplicates) = self.duplicate_keys()
+            && !duplicates.is_empty()
+        {
+            let mut listed: Vec<String> = duplicates
+                .iter()
+                .take(LISTED_KEYS)
+                .map(|key| format!("'{}'", key.join("|")))
+                .collect();
+            if duplicates.len() > LISTED_KEYS {
+                listed.push(format!("and {} more", duplicates.len() - LISTED_KEYS));
+            }
+            warnings.push(format!(
+                "{} primary key(s) appear on more than one row: {}",
+                duplicates.len(),
+                listed.join(", ")
+            ));
+        }
+
+        let ragged: Vec<usize> = (0..self.rows.len())
+            .filter(|&idx| self.rows[idx].len() != self.headers.len())
+            .collect();
+        if let Some(first) = ragged.first() {
+            warnings.push(format!(
+                "{} row(s) don't have {} cells, one per header (first at row {first})",
+                ragged.len(),
+                self.headers.len()
+            ));
+        }
+
+        for column in self.empty_columns() {
+            warnings.push(format!(
+                "column '{column}' is entirely empty; it may be an export artifact"
+            ));
+        }
+
+        for (col, column) in self.headers.iter().enumerate() {
+            if let Some((ty, misfits)) = mostly_typed(self, col) {
+                warnings.push(format!(
+                    "column '{column}' is mostly {ty} but {} cell(s) aren't, e.g. {:?}",
+                    misfits.len(),
+                    misfits[0]
+                ));
+            }
+        }
+
+        for column in self.volatile_columns() {
+            warnings.push(format!(
+                "column '{column}' looks like a volatile timestamp; every snapshot will differ on it (consider --ignore-columns {column})"
+            ));
+        }
+
+        warnings
+    }
+}
@@ -0,0 +1,207 @@
+// git-sheets: Schema inference - what kind of values a column holds
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "native")]
+use super::Result;
+use super::Snapshot;
+#[cfg(feature = "native")]
+use std::{fs, path::Path};
+
+/// The kind of values a column holds, inferred from its cells or declared
+/// in a [`Schema`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    /// Anything that isn't consistently one of the other types
+    Text,
+    /// Every non-empty cell is a date
+    Date,
+    /// Every non-empty cell is a whole number
+    #[serde(rename = "int")]
+    Integer,
+    /// Every non-empty cell is a number, not all of them whole
+    Float,
+    /// Every non-empty cell is `true` or `false`, in any case
+    #[serde(rename = "bool")]
+    Boolean,
+    /// Identifiers such as zip codes or account numbers, which may look
+    /// like numbers but are compared as text; only ever declared
+    Code,
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColumnType::Text => "text",
+            ColumnType::Date => "date",
+            ColumnType::Integer => "int",
+            ColumnType::Float => "float",
+            ColumnType::Boolean => "bool",
+            ColumnType::Code => "code",
+        })
+    }
+}
+
+/// Column types declared up front instead of inferred, from a schema file
+///
+/// ```toml
+/// [columns]
+/// Amount = "float"
+/// Zip = "code"
+/// Due = "date"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Schema {
+    /// Type of each declared column, by name
+    #[serde(default)]
+    pub columns: BTreeMap<String, ColumnType>,
+}
+
+impl Schema {
+    /// Read a schema file
+    #[cfg(feature = "native")]
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// One column of a snapshot's table, as listed by `git-sheets columns`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnInfo {
+    /// 0-based position in the table
+    pub index: usize,
+    /// Header text
+    pub name: String,
+    /// Declared type if the snapshot has one, otherwise inferred
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    /// Whether `column_type` came from a schema rather than inference
+    pub declared: bool,
+    /// Number of cells with something other than whitespace
+    pub non_empty: usize,
+    /// Whether the column is part of the primary key
+    pub primary_key: bool,
+}
+
+impl Snapshot {
+    /// Index, name, type and fill of every column, in table order
+    pub fn columns(&self) -> Vec<ColumnInfo> {
+        let table = &self.table;
+        let key = table.primary_key.as_deref().unwrap_or_default();
+        table
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let declared = self.column_types.get(name).copied();
+                ColumnInfo {
+                    index,
+                    name: name.clone(),
+                    column_type: declared.unwrap_or_else(|| table.column_type(index)),
+                    declared: declared.is_some(),
+                    non_empty: table
+                        .rows
+                        .iter()
+                        .filter(|row| row.get(index).is_some_and(|cell| !cell.trim().is_empty()))
+                        .count(),
+                    primary_key: key.contains(&index),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Date layouts commonly produced by spreadsheet exports
+///
+/// Slash dates are read month-first, matching Excel's default US export.
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%m/%d/%Y", "%d-%b-%Y", "%Y/%m/%d", "%d %B %Y"];
+
+/// Parse a cell as a calendar date in one of the known export layouts
+pub fn parse_date(cell: &str) -> Option<NaiveDate> {
+    let cell = cell.trim();
+    DATE_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(cell, fmt).ok())
+}
+
+/// Whether a number is written with leading zeros, like a zip code or an
+/// account id, which reading it as a number would lose
+pub(crate) fn has_leading_zero(cell: &str) -> bool {
+    let digits = cell.trim_start_matches(['-', '+']).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+/// Parse a cell as a whole number, without currency or grouping marks
+///
+/// Numbers with leading zeros are not read, so `007` stays text.
+pub fn parse_integer(cell: &str) -> Option<i64> {
+    let cell = cell.trim();
+    if has_leading_zero(cell) {
+        return None;
+    }
+    cell.parse().ok()
+}
+
+/// Parse a cell as a finite number, without currency or grouping marks
+///
+/// Numbers with leading zeros are not read, as in [`parse_integer`].
+pub fn parse_float(cell: &str) -> Option<f64> {
+    let cell = cell.trim();
+    if has_leading_zero(cell) {
+        return None;
+    }
+    cell.parse().ok().filter(|value: &f64| value.is_finite())
+}
+
+/// Parse a cell as `true` or `false`, in any case
+pub fn parse_bool(cell: &str) -> Option<bool> {
+    let cell = cell.trim();
+    if cell.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if cell.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Whether `cell` can be read as a value of type `ty`
+pub fn fits(ty: ColumnType, cell: &str) -> bool {
+    match ty {
+        ColumnType::Text | ColumnType::Code => true,
+        ColumnType::Date => parse_date(cell).is_some(),
+        ColumnType::Integer => parse_integer(cell).is_some(),
+        ColumnType::Float => parse_float(cell).is_some(),
+        ColumnType::Boolean => parse_bool(cell).is_some(),
+    }
+}
+
+/// Infer a column's type from its values
+///
+/// The narrowest type every value fits wins: booleans, then whole numbers,
+/// then numbers, then dates. Empty cells are ignored; a column with no
+/// values at all is text.
+pub fn infer_column_type<'a>(values: impl IntoIterator<Item = &'a str>) -> ColumnType {
+    let mut candidates = vec![
+        ColumnType::Boolean,
+        ColumnType::Integer,
+        ColumnType::Float,
+        ColumnType::Date,
+    ];
+    let mut seen = false;
+    for value in values.into_iter().filter(|v| !v.trim().is_empty()) {
+        candidates.retain(|&ty| fits(ty, value));
+        if candidates.is_empty() {
+            return ColumnType::Text;
+        }
+        seen = true;
+    }
+    match candidates.first() {
+        Some(&ty) if seen => ty,
+        _ => ColumnType::Text,
+    }
+}
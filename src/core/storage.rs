@@ -0,0 +1,152 @@
+// git-sheets: Storage backends - where serialized snapshots live
+
+use super::Result;
+use std::collections::BTreeMap;
+
+#[cfg(feature = "native")]
+use super::{GitSheetsError, SNAPSHOT_EXTENSIONS, is_snapshot_file, write_atomic};
+#[cfg(feature = "native")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A place to keep serialized snapshots, keyed by snapshot id
+///
+/// Backends treat the bytes as opaque; [`Snapshot::save_to`] and
+/// [`Snapshot::load_from`] produce and read them. Delta snapshots are rebuilt
+/// from the same storage by [`Snapshot::load_materialized_from`] and
+/// [`Snapshot::load_chain_from`], and [`Snapshot::latest_for_source_in`]
+/// finds the newest snapshot of a file.
+///
+/// The rest of the crate works on snapshot files in a directory, the layout
+/// of `FsStorage`: `SnapshotStore`, the path-based loaders and every CLI
+/// command, `fsck` included.
+///
+/// [`Snapshot::save_to`]: super::Snapshot::save_to
+/// [`Snapshot::load_from`]: super::Snapshot::load_from
+/// [`Snapshot::load_materialized_from`]: super::Snapshot::load_materialized_from
+/// [`Snapshot::load_chain_from`]: super::Snapshot::load_chain_from
+/// [`Snapshot::latest_for_source_in`]: super::Snapshot::latest_for_source_in
+pub trait SnapshotStorage {
+    /// Store snapshot `id`, replacing any earlier version
+    fn put(&mut self, id: &str, bytes: &[u8]) -> Result<()>;
+
+    /// The stored bytes of snapshot `id`, or `None` if there is no such snapshot
+    fn get(&self, id: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Ids of every stored snapshot, sorted
+    fn list(&self) -> Result<Vec<String>>;
+}
+
+/// Snapshots as `<id>.toml` or `<id>.json` files in a directory
+///
+/// The layout of the `snapshots/` directory the CLI uses.
+#[cfg(feature = "native")]
+#[derive(Debug, Clone)]
+pub struct FsStorage {
+    dir: PathBuf,
+}
+
+#[cfg(feature = "native")]
+impl FsStorage {
+    /// Storage in `dir`, which must already exist
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The directory snapshots are kept in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Refuse ids that would escape the directory
+    fn check_id(id: &str) -> Result<()> {
+        if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+            return Err(GitSheetsError::FileSystemError(format!(
+                "Invalid snapshot id '{id}'"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "native")]
+impl SnapshotStorage for FsStorage {
+    /// Written atomically, as `.json` if the bytes are JSON and `.toml` otherwise
+    fn put(&mut self, id: &str, bytes: &[u8]) -> Result<()> {
+        Self::check_id(id)?;
+        let extension = if bytes.trim_ascii_start().starts_with(b"{") {
+            "json"
+        } else {
+            "toml"
+        };
+        write_atomic(&self.dir.join(format!("{id}.{extension}")), bytes)?;
+
+        // Drop a copy in the other format so `get` can't find a stale one
+        for other in SNAPSHOT_EXTENSIONS.iter().filter(|ext| **ext != extension) {
+            let stale = self.dir.join(format!("{id}.{other}"));
+            if stale.exists() {
+                fs::remove_file(stale)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<Vec<u8>>> {
+        Self::check_id(id)?;
+        for ext in SNAPSHOT_EXTENSIONS {
+            let path = self.dir.join(format!("{id}.{ext}"));
+            if path.exists() {
+                return Ok(Some(fs::read(path)?));
+            }
+        }
+        Ok(None)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if is_snapshot_file(&path)
+                && let Some(stem) = path.file_stem()
+            {
+                ids.push(stem.to_string_lossy().into_owned());
+            }
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+}
+
+/// Snapshots held in memory, for tests and short-lived services
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryStorage {
+    snapshots: BTreeMap<String, Vec<u8>>,
+}
+
+impl InMemoryStorage {
+    /// Empty storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SnapshotStorage for InMemoryStorage {
+    fn put(&mut self, id: &str, bytes: &[u8]) -> Result<()> {
+        self.snapshots.insert(id.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.snapshots.get(id).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.snapshots.keys().cloned().collect())
+    }
+}
@@ -0,0 +1,129 @@
+// git-sheets: Snapshot store - loads each snapshot file once per command
+
+use super::{GitSheetsError, Result, Snapshot, snapshot_path};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Memoizes snapshots loaded during one command
+///
+/// Walking a chain of delta snapshots otherwise re-reads and re-parses every
+/// ancestor for every descendant. The store keeps each file as parsed and,
+/// separately, with its delta chain applied, so each is loaded at most once.
+#[derive(Debug, Default)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    parsed: RefCell<HashMap<PathBuf, Rc<Snapshot>>>,
+    materialized: RefCell<HashMap<PathBuf, Rc<Snapshot>>>,
+    /// Paths being materialized, to catch delta chains that loop
+    pending: RefCell<HashSet<PathBuf>>,
+}
+
+impl SnapshotStore {
+    /// A store looking up snapshots by id in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Directory searched by [`SnapshotStore::get`]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Number of distinct snapshot files read so far
+    pub fn len(&self) -> usize {
+        self.parsed.borrow().len()
+    }
+
+    /// Whether no snapshot has been read yet
+    pub fn is_empty(&self) -> bool {
+        self.parsed.borrow().is_empty()
+    }
+
+    /// Load a snapshot as stored on disk, without applying its delta
+    pub fn load(&self, path: &Path) -> Result<Rc<Snapshot>> {
+        if let Some(snapshot) = self.parsed.borrow().get(path) {
+            tracing::trace!(path = %path.display(), "snapshot cache hit");
+            return Ok(Rc::clone(snapshot));
+        }
+        let snapshot = Rc::new(Snapshot::load(path)?);
+        self.parsed
+            .borrow_mut()
+            .insert(path.to_path_buf(), Rc::clone(&snapshot));
+        Ok(snapshot)
+    }
+
+    /// Load a snapshot with its full table, like [`Snapshot::load_materialized`]
+    ///
+    /// Parents are looked up by id in the same directory as `path`. Each delta
+    /// is applied to its parent's cached table, so a chain is replayed once.
+    pub fn load_materialized(&self, path: &Path) -> Result<Rc<Snapshot>> {
+        if let Some(snapshot) = self.materialized.borrow().get(path) {
+            return Ok(Rc::clone(snapshot));
+        }
+
+        let parsed = self.load(path)?;
+        let snapshot = if parsed.delta.is_some() {
+            if !self.pending.borrow_mut().insert(path.to_path_buf()) {
+                return Err(GitSheetsError::InvalidDelta(format!(
+                    "delta chain loops at '{}'",
+                    parsed.id
+                )));
+            }
+            // The parent comes back materialized, so it stands in for a full
+            // snapshot and only this snapshot's delta is applied
+            let dir = path.parent().unwrap_or(Path::new("."));
+            let resolver = |id: &str| {
+                let mut parent = (*self.load_materialized(&snapshot_path(dir, id))?).clone();
+                parent.delta = None;
+                Ok(parent)
+            };
+            let table = parsed.materialize(&resolver);
+            self.pending.borrow_mut().remove(path);
+            let mut snapshot = (*parsed).clone();
+            snapshot.table = table?;
+            Rc::new(snapshot)
+        } else {
+            parsed
+        };
+
+        self.materialized
+            .borrow_mut()
+            .insert(path.to_path_buf(), Rc::clone(&snapshot));
+        Ok(snapshot)
+    }
+
+    /// Load the materialized snapshot `id` from the store's directory
+    pub fn get(&self, id: &str) -> Result<Rc<Snapshot>> {
+        self.load_materialized(&snapshot_path(&self.dir, id))
+    }
+
+    /// A snapshot followed by all of its ancestors, newest first
+    ///
+    /// See [`Snapshot::load_chain`].
+    pub fn chain(&self, path: &Path) -> Result<Vec<Rc<Snapshot>>> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut chain = vec![self.load_materialized(path)?];
+
+        while let Some(parent_id) = chain.last().and_then(|s| s.parent.clone()) {
+            let parent_path = snapshot_path(dir, &parent_id);
+            if !parent_path.exists() {
+                return Err(GitSheetsError::FileSystemError(format!(
+                    "Parent snapshot '{parent_id}' not found"
+                )));
+            }
+            if chain.iter().any(|s| s.id == parent_id) {
+                return Err(GitSheetsError::FileSystemError(format!(
+                    "Parent chain loops at '{parent_id}'"
+                )));
+            }
+            chain.push(self.load_materialized(&parent_path)?);
+        }
+
+        Ok(chain)
+    }
+}
@@ -0,0 +1,125 @@
+// git-sheets: Validation - cell-level data quality rules
+
+use super::schema::{ColumnType, fits};
+use super::{GitSheetsError, Result, parse_number};
+use regex::Regex;
+use std::fmt;
+
+/// A rule every cell of a column must satisfy
+pub trait Validator {
+    /// Check one cell, describing the problem if it fails
+    fn check(&self, value: &str) -> std::result::Result<(), String>;
+}
+
+/// A cell that failed a validator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Row index in the table
+    pub row: usize,
+    /// Column index in the table
+    pub col: usize,
+    /// Column name
+    pub column: String,
+    /// The offending cell
+    pub value: String,
+    /// What the validator objected to
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "row {}, column '{}': {:?} {}",
+            self.row, self.column, self.value, self.message
+        )
+    }
+}
+
+/// Cells must match a regular expression (empty cells are skipped)
+pub struct RegexValidator(Regex);
+
+impl RegexValidator {
+    pub fn new(pattern: &str) -> Result<Self> {
+        Regex::new(pattern)
+            .map(Self)
+            .map_err(|e| GitSheetsError::InvalidRule(format!("bad regex '{pattern}': {e}")))
+    }
+}
+
+impl Validator for RegexValidator {
+    fn check(&self, value: &str) -> std::result::Result<(), String> {
+        if value.is_empty() || self.0.is_match(value) {
+            Ok(())
+        } else {
+            Err(format!("does not match /{}/", self.0.as_str()))
+        }
+    }
+}
+
+/// Cells must be values of a declared type (empty cells are skipped)
+pub struct TypeValidator(pub ColumnType);
+
+impl Validator for TypeValidator {
+    fn check(&self, value: &str) -> std::result::Result<(), String> {
+        if value.trim().is_empty() || fits(self.0, value) {
+            Ok(())
+        } else {
+            Err(format!("is not a valid {} value", self.0))
+        }
+    }
+}
+
+/// Cells must be numbers within inclusive bounds (empty cells are skipped)
+pub struct RangeValidator {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Validator for RangeValidator {
+    fn check(&self, value: &str) -> std::result::Result<(), String> {
+        if value.is_empty() {
+            return Ok(());
+        }
+        let Some(number) = parse_number(value) else {
+            return Err("is not a number".to_string());
+        };
+        if let Some(min) = self.min
+            && number < min
+        {
+            return Err(format!("is less than {min}"));
+        }
+        if let Some(max) = self.max
+            && number > max
+        {
+            return Err(format!("is greater than {max}"));
+        }
+        Ok(())
+    }
+}
+
+/// Cells must not be empty or whitespace
+pub struct NonEmptyValidator;
+
+impl Validator for NonEmptyValidator {
+    fn check(&self, value: &str) -> std::result::Result<(), String> {
+        if value.trim().is_empty() {
+            Err("is empty".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Cells must be one of a fixed set of values (empty cells are skipped)
+pub struct OneOfValidator(pub Vec<String>);
+
+impl Validator for OneOfValidator {
+    fn check(&self, value: &str) -> std::result::Result<(), String> {
+        if value.is_empty() || self.0.iter().any(|allowed| allowed == value) {
+            Ok(())
+        } else {
+            Err(format!("is not one of {}", self.0.join(", ")))
+        }
+    }
+}
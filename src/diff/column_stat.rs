@@ -0,0 +1,152 @@
+// git-sheets: Column stat - how many cells of each column a diff touched,
+// the spreadsheet take on `git diff --stat`
+
+use super::{Change, DiffOptions, SnapshotDiff, record_rows};
+use crate::core::{GitSheetsError, Snapshot, Table};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Cells of one column added, changed and removed by a diff
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnStat {
+    /// Column name
+    pub column: String,
+    /// Non-empty cells in added rows, or in every row of an added column
+    pub added: usize,
+    /// Cells whose value changed
+    pub modified: usize,
+    /// Non-empty cells in removed rows, or in every row of a removed column
+    pub removed: usize,
+}
+
+impl ColumnStat {
+    /// Cells touched in any way
+    pub fn total(&self) -> usize {
+        self.added + self.modified + self.removed
+    }
+}
+
+/// Per-column cell counts of a diff, with the rows they fall in
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStat {
+    /// Columns with at least one touched cell, in `to` order followed by
+    /// columns only `from` has
+    pub columns: Vec<ColumnStat>,
+    /// Distinct rows added, removed or changed, as `changed_row_count`
+    pub rows_changed: usize,
+}
+
+impl DiffStat {
+    /// Non-empty cells added, over all columns
+    pub fn cells_added(&self) -> usize {
+        self.columns.iter().map(|c| c.added).sum()
+    }
+
+    /// Cells changed, over all columns
+    pub fn cells_modified(&self) -> usize {
+        self.columns.iter().map(|c| c.modified).sum()
+    }
+
+    /// Non-empty cells removed, over all columns
+    pub fn cells_removed(&self) -> usize {
+        self.columns.iter().map(|c| c.removed).sum()
+    }
+}
+
+/// Count the non-empty cells of an added (or removed) `row` of `table`,
+/// column by name, skipping `whole` columns already counted in full
+fn count_row(
+    stats: &mut IndexMap<String, ColumnStat>,
+    table: &Table,
+    row: &[String],
+    whole: &HashSet<String>,
+    added: bool,
+) {
+    for (header, cell) in table.headers.iter().zip(row) {
+        if !cell.trim().is_empty()
+            && !whole.contains(header)
+            && let Some(stat) = stats.get_mut(header)
+        {
+            if added {
+                stat.added += 1;
+            } else {
+                stat.removed += 1;
+            }
+        }
+    }
+}
+
+impl SnapshotDiff {
+    /// Count the cells each column gained, changed and lost
+    ///
+    /// Streams `iter_changes`, so it agrees with the full change list for the
+    /// same options. Cells are attributed by column name: a changed cell to
+    /// its `from` column, a cell of an added row to its `to` column. An added
+    /// or removed column counts all of its non-empty cells once, and nothing
+    /// else (positional diffs also report its cells as changed). Split and
+    /// merged rows count as removed and added rows. Columns nothing touched
+    /// are left out.
+    pub fn stat(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<DiffStat, GitSheetsError> {
+        let mut stats: IndexMap<String, ColumnStat> = IndexMap::new();
+        for header in to.table.headers.iter().chain(&from.table.headers) {
+            stats.entry(header.clone()).or_insert_with(|| ColumnStat {
+                column: header.clone(),
+                ..ColumnStat::default()
+            });
+        }
+
+        let (mut added_columns, mut removed_columns) = (HashSet::new(), HashSet::new());
+        let mut rows: HashSet<(bool, usize)> = HashSet::new();
+        for change in Self::iter_changes(from, to, opts)? {
+            record_rows(&mut rows, &change);
+            match change {
+                Change::ColumnAdded { name, index } => {
+                    let cells = to.table.rows.iter().filter_map(|row| row.get(index));
+                    stats[&name].added += cells.filter(|c| !c.trim().is_empty()).count();
+                    added_columns.insert(name);
+                }
+                Change::ColumnRemoved { name, index } => {
+                    let cells = from.table.rows.iter().filter_map(|row| row.get(index));
+                    stats[&name].removed += cells.filter(|c| !c.trim().is_empty()).count();
+                    removed_columns.insert(name);
+                }
+                Change::RowAdded { data, .. } => {
+                    count_row(&mut stats, &to.table, &data, &added_columns, true);
+                }
+                Change::RowRemoved { data, .. } => {
+                    count_row(&mut stats, &from.table, &data, &removed_columns, false);
+                }
+                Change::RowSplit { from: old, into } => {
+                    count_row(&mut stats, &from.table, &old.data, &removed_columns, false);
+                    for new in &into {
+                        count_row(&mut stats, &to.table, &new.data, &added_columns, true);
+                    }
+                }
+                Change::RowMerged { from: old, into } => {
+                    for part in &old {
+                        count_row(&mut stats, &from.table, &part.data, &removed_columns, false);
+                    }
+                    count_row(&mut stats, &to.table, &into.data, &added_columns, true);
+                }
+                Change::CellChanged { col, .. } => {
+                    if let Some(header) = from.table.headers.get(col)
+                        && !removed_columns.contains(header)
+                    {
+                        stats[header].modified += 1;
+                    }
+                }
+                Change::RowModified { .. } | Change::ColumnRenamed { .. } => {}
+            }
+        }
+
+        Ok(DiffStat {
+            columns: stats.into_values().filter(|s| s.total() > 0).collect(),
+            rows_changed: rows.len(),
+        })
+    }
+}
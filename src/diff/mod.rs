@@ -1,19 +1,34 @@
 // git-sheets: Diff module - computing differences between snapshots
 // A tool for Excel sufferers who deserve better
 
-use crate::core::GitSheetsError;
+use crate::core::schema::{ColumnType, parse_bool, parse_date};
+use crate::core::{GitSheetsError, parse_number};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use regroup::{Regroupings, detect_regroupings, row_ref};
+
+#[cfg(feature = "native")]
+use crate::core::write_atomic;
+#[cfg(feature = "native")]
+use std::{fs, path::Path};
+
+pub mod column_stat;
+mod regroup;
+pub mod severity;
+pub mod stats;
+pub use column_stat::{ColumnStat, DiffStat};
+pub use severity::{RowSeverity, SeverityWeights, rank_by_severity};
+pub use stats::{ColumnNumericDelta, NumericStats};
 
 // Similar crate version 2.7.0
 
 // Re-export from core module
-pub use crate::core::{Snapshot, TableHashes};
+pub use crate::core::{Snapshot, Table, TableHashes};
 
 /// Summary of changes between snapshots
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DiffSummary {
     /// Number of rows added
     pub rows_added: usize,
@@ -25,41 +40,63 @@ pub struct DiffSummary {
     pub columns_added: usize,
     /// Number of columns removed
     pub columns_removed: usize,
-}
-
-impl Default for DiffSummary {
-    fn default() -> Self {
-        Self {
-            rows_added: 0,
-            rows_removed: 0,
-            rows_modified: 0,
-            columns_added: 0,
-            columns_removed: 0,
-        }
-    }
+    /// Number of removed/added column pairs reported as renames
+    #[serde(default)]
+    pub columns_renamed: usize,
+    /// Number of removed rows reported as split into several added rows
+    #[serde(default)]
+    pub rows_split: usize,
+    /// Number of added rows reported as several removed rows merged
+    #[serde(default)]
+    pub rows_merged: usize,
 }
 
 /// Individual change types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Row-level variants carry the primary key values of the row when the
+/// snapshots define one. Changes serialize internally tagged by `type`,
+/// since TOML has no representation for externally tagged struct variants.
+///
+/// Compatibility: JSON diffs written before delta snapshots tagged changes
+/// externally (`{"CellChanged": {...}}`). That shape still deserializes, but
+/// is no longer written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "Self", tag = "type")]
 pub enum Change {
     RowAdded {
         index: usize,
         data: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<Vec<String>>,
     },
     RowRemoved {
         index: usize,
         data: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<Vec<String>>,
     },
     RowModified {
         index: usize,
         old_data: Vec<String>,
         new_data: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<Vec<String>>,
+        /// Index of the row in `to`, with `DiffOptions::row_indices`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        new_index: Option<usize>,
     },
     CellChanged {
         row: usize,
         col: usize,
         old: String,
         new: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "CellChangeKind::is_value")]
+        kind: CellChangeKind,
+        /// Index of the row in `to`, with `DiffOptions::row_indices`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        new_index: Option<usize>,
     },
     ColumnAdded {
         name: String,
@@ -69,10 +106,1082 @@ pub enum Change {
         name: String,
         index: usize,
     },
+    /// A removed and an added column whose values mostly agree
+    ColumnRenamed {
+        from: String,
+        to: String,
+        from_index: usize,
+        to_index: usize,
+        /// Fraction of compared rows whose values matched
+        similarity: f64,
+    },
+    /// One removed row whose numbers were divided among several added rows
+    RowSplit {
+        from: RowRef,
+        into: Vec<RowRef>,
+    },
+    /// Several removed rows whose numbers were added up into one added row
+    RowMerged {
+        from: Vec<RowRef>,
+        into: RowRef,
+    },
+}
+
+impl Serialize for Change {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Change::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Change {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = match serde_json::Value::deserialize(deserializer)? {
+            // The old externally tagged shape: a variant name over its fields
+            serde_json::Value::Object(map) if map.len() == 1 && !map.contains_key("type") => {
+                let (tag, mut fields) = map.into_iter().next().expect("map has one entry");
+                if let Some(fields) = fields.as_object_mut() {
+                    fields.insert("type".to_string(), tag.into());
+                }
+                fields
+            }
+            value => value,
+        };
+        Change::deserialize(value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A row taking part in a split or merge
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowRef {
+    /// Index of the row in its table
+    pub index: usize,
+    /// Primary key values of the row
+    pub key: Vec<String>,
+    /// The row's cells
+    pub data: Vec<String>,
+}
+
+impl fmt::Display for RowRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (key {})", self.index, self.key.join(", "))
+    }
+}
+
+/// What kind of edit a cell change is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CellChangeKind {
+    /// The content changed
+    #[default]
+    Value,
+    /// Only leading, trailing or repeated whitespace changed
+    WhitespaceOnly,
+    /// Text corrected a little, e.g. a typo fix: at most a third of the
+    /// characters differ
+    MinorEdit,
+    /// Text replaced by substantially different text
+    MajorEdit,
+}
+
+impl CellChangeKind {
+    /// Classify the change from `old` to `new`
+    ///
+    /// Changes between two text cells are minor or major edits by their
+    /// Levenshtein distance relative to the longer value; numbers, dates and
+    /// empty cells are plain value changes.
+    pub fn classify(old: &str, new: &str) -> Self {
+        let text = severity::cell_type(old) == severity::CellType::Text
+            && severity::cell_type(new) == severity::CellType::Text;
+        Self::classify_as(text, old, new)
+    }
+
+    /// Classify the change from `old` to `new`, told whether the column
+    /// holds text
+    fn classify_as(text: bool, old: &str, new: &str) -> Self {
+        if old != new && old.split_whitespace().eq(new.split_whitespace()) {
+            CellChangeKind::WhitespaceOnly
+        } else if text {
+            let longest = old.chars().count().max(new.chars().count());
+            if edit_distance(old, new) * 3 <= longest {
+                CellChangeKind::MinorEdit
+            } else {
+                CellChangeKind::MajorEdit
+            }
+        } else {
+            CellChangeKind::Value
+        }
+    }
+
+    fn is_value(&self) -> bool {
+        *self == CellChangeKind::Value
+    }
+}
+
+/// Levenshtein distance between two strings, in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+impl Change {
+    /// Which table a row-level change points into, and the row index there
+    ///
+    /// The flag is true for the `to` table (added rows) and false for the
+    /// `from` table. Column changes have no row.
+    pub fn row_position(&self) -> Option<(bool, usize)> {
+        match self {
+            Change::RowAdded { index, .. } => Some((true, *index)),
+            Change::RowRemoved { index, .. } | Change::RowModified { index, .. } => {
+                Some((false, *index))
+            }
+            Change::CellChanged { row, .. } => Some((false, *row)),
+            Change::RowSplit { from, .. } => Some((false, from.index)),
+            Change::RowMerged { into, .. } => Some((true, into.index)),
+            Change::ColumnAdded { .. }
+            | Change::ColumnRemoved { .. }
+            | Change::ColumnRenamed { .. } => None,
+        }
+    }
+
+    /// Every row this change touches, in either table, flagged as in
+    /// `row_position`
+    ///
+    /// A matched row's place in `to` is only known when the diff recorded
+    /// it (`DiffOptions::row_indices`).
+    pub fn row_positions(&self) -> Vec<(bool, usize)> {
+        match self {
+            Change::CellChanged {
+                row: index,
+                new_index,
+                ..
+            }
+            | Change::RowModified {
+                index, new_index, ..
+            } => std::iter::once((false, *index))
+                .chain(new_index.map(|new| (true, new)))
+                .collect(),
+            Change::RowSplit { from, into } => std::iter::once((false, from.index))
+                .chain(into.iter().map(|row| (true, row.index)))
+                .collect(),
+            Change::RowMerged { from, into } => from
+                .iter()
+                .map(|row| (false, row.index))
+                .chain(std::iter::once((true, into.index)))
+                .collect(),
+            _ => self.row_position().into_iter().collect(),
+        }
+    }
+
+    /// Primary key of the row this change touches, if rows were matched by key
+    pub fn row_key(&self) -> Option<&[String]> {
+        match self {
+            Change::RowAdded { key, .. }
+            | Change::RowRemoved { key, .. }
+            | Change::RowModified { key, .. }
+            | Change::CellChanged { key, .. } => key.as_deref(),
+            Change::RowSplit { from: row, .. } | Change::RowMerged { into: row, .. } => {
+                Some(&row.key)
+            }
+            Change::ColumnAdded { .. }
+            | Change::ColumnRemoved { .. }
+            | Change::ColumnRenamed { .. } => None,
+        }
+    }
+
+    /// How a cell change edited its cell; `None` for other changes
+    pub fn cell_kind(&self) -> Option<CellChangeKind> {
+        match self {
+            Change::CellChanged { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a cell change that only touched whitespace
+    pub fn is_whitespace_only(&self) -> bool {
+        matches!(
+            self,
+            Change::CellChanged {
+                kind: CellChangeKind::WhitespaceOnly,
+                ..
+            }
+        )
+    }
+}
+
+impl DiffSummary {
+    /// Count one change, e.g. while streaming `iter_changes`
+    pub fn record(&mut self, change: &Change) {
+        match change {
+            Change::RowAdded { .. } => self.rows_added += 1,
+            Change::RowRemoved { .. } => self.rows_removed += 1,
+            Change::RowModified { .. } => self.rows_modified += 1,
+            Change::ColumnAdded { .. } => self.columns_added += 1,
+            Change::ColumnRemoved { .. } => self.columns_removed += 1,
+            Change::ColumnRenamed { .. } => self.columns_renamed += 1,
+            Change::RowSplit { .. } => self.rows_split += 1,
+            Change::RowMerged { .. } => self.rows_merged += 1,
+            Change::CellChanged { .. } => {}
+        }
+    }
+}
+
+impl fmt::Display for DiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Rows added: {}", self.rows_added)?;
+        writeln!(f, "Rows removed: {}", self.rows_removed)?;
+        writeln!(f, "Rows modified: {}", self.rows_modified)?;
+        writeln!(f, "Columns added: {}", self.columns_added)?;
+        writeln!(f, "Columns removed: {}", self.columns_removed)?;
+        write!(f, "Columns renamed: {}", self.columns_renamed)?;
+        // Only found with `DiffOptions::detect_splits`, so left out when zero
+        if self.rows_split > 0 {
+            write!(f, "\nRows split: {}", self.rows_split)?;
+        }
+        if self.rows_merged > 0 {
+            write!(f, "\nRows merged: {}", self.rows_merged)?;
+        }
+        Ok(())
+    }
+}
+
+/// Add the rows a change touches to `rows`, as (in `to`, index) pairs
+fn record_rows(rows: &mut HashSet<(bool, usize)>, change: &Change) {
+    match change {
+        Change::RowSplit { from, into } => {
+            rows.insert((false, from.index));
+            rows.extend(into.iter().map(|row| (true, row.index)));
+        }
+        Change::RowMerged { from, into } => {
+            rows.extend(from.iter().map(|row| (false, row.index)));
+            rows.insert((true, into.index));
+        }
+        change => rows.extend(change.row_position()),
+    }
+}
+
+/// Format an optional row key as ` (key a, b)`
+fn key_suffix(key: &Option<Vec<String>>) -> String {
+    match key {
+        Some(values) => format!(" (key {})", values.join(", ")),
+        None => String::new(),
+    }
+}
+
+/// Format a matched row's positions as ` (old #5 -> new #8)`, if known
+fn index_suffix(old: usize, new: &Option<usize>) -> String {
+    match new {
+        Some(new) => format!(" (old #{old} -> new #{new})"),
+        None => String::new(),
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::RowAdded { index, data, key } => {
+                write!(f, "Row added at {index}{}: {data:?}", key_suffix(key))
+            }
+            Change::RowRemoved { index, data, key } => {
+                write!(f, "Row removed at {index}{}: {data:?}", key_suffix(key))
+            }
+            Change::CellChanged {
+                row,
+                col,
+                old,
+                new,
+                key,
+                kind,
+                new_index,
+            } => match kind {
+                CellChangeKind::Value | CellChangeKind::MajorEdit => write!(
+                    f,
+                    "Cell changed at ({row}, {col}){}{}: {old} -> {new}",
+                    key_suffix(key),
+                    index_suffix(*row, new_index)
+                ),
+                // Quoted, since the difference is otherwise invisible
+                CellChangeKind::WhitespaceOnly => write!(
+                    f,
+                    "Cell changed at ({row}, {col}){}{}: {old:?} -> {new:?} (whitespace only)",
+                    key_suffix(key),
+                    index_suffix(*row, new_index)
+                ),
+                CellChangeKind::MinorEdit => write!(
+                    f,
+                    "Cell changed at ({row}, {col}){}{}: {old} -> {new} (minor edit)",
+                    key_suffix(key),
+                    index_suffix(*row, new_index)
+                ),
+            },
+            Change::RowModified {
+                index,
+                old_data,
+                new_data,
+                key,
+                new_index,
+            } => write!(
+                f,
+                "Row modified at {index}{}{}: {old_data:?} -> {new_data:?}",
+                key_suffix(key),
+                index_suffix(*index, new_index)
+            ),
+            Change::ColumnAdded { name, index } => write!(f, "Column added at {index}: {name}"),
+            Change::ColumnRemoved { name, index } => {
+                write!(f, "Column removed at {index}: {name}")
+            }
+            Change::ColumnRenamed {
+                from,
+                to,
+                to_index,
+                similarity,
+                ..
+            } => write!(
+                f,
+                "Column renamed at {to_index}: {from} -> {to} ({:.0}% similar)",
+                similarity * 100.0
+            ),
+            Change::RowSplit { from, into } => {
+                let into: Vec<String> = into.iter().map(RowRef::to_string).collect();
+                write!(f, "Row split at {from} into rows {}", into.join("; "))
+            }
+            Change::RowMerged { from, into } => {
+                let from: Vec<String> = from.iter().map(RowRef::to_string).collect();
+                write!(f, "Rows merged into {into} from rows {}", from.join("; "))
+            }
+        }
+    }
+}
+
+/// Options controlling how two snapshots are compared
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Report a removed and an added column as a rename when at least this
+    /// fraction of their values match; `None` disables rename detection
+    pub rename_threshold: Option<f64>,
+    /// Match rows of both tables by these columns, overriding their own keys
+    pub force_key: Option<Vec<String>>,
+    /// Record each matched row's index in `to` as well as in `from`
+    pub row_indices: bool,
+    /// Report a removed row whose numbers were divided among several added
+    /// rows as a split, and the reverse as a merge (heuristic; needs keys)
+    pub detect_splits: bool,
+    /// Columns holding delimited lists, by name, with their delimiter: cells
+    /// holding the same items in any order count as equal
+    pub set_columns: BTreeMap<String, String>,
+    /// Leave rows with these key values out entirely, e.g. a totals row that
+    /// is recomputed on every export (ignored when rows are paired by position)
+    pub ignore_row_keys: Vec<Vec<String>>,
+    /// Compare cells by column name, and across detected renames, instead
+    /// of by position; see [`SnapshotDiff::compute_with_schema_reconciliation`]
+    pub align_columns: bool,
+}
+
+/// Rename threshold used by schema reconciliation when none is set
+pub const DEFAULT_RENAME_THRESHOLD: f64 = 0.9;
+
+impl DiffOptions {
+    /// These options with columns aligned by name and renames detected (at
+    /// `DEFAULT_RENAME_THRESHOLD` unless a threshold is set)
+    pub fn with_schema_reconciliation(&self) -> Self {
+        Self {
+            align_columns: true,
+            rename_threshold: self.rename_threshold.or(Some(DEFAULT_RENAME_THRESHOLD)),
+            ..self.clone()
+        }
+    }
+
+    fn ignores(&self, key: &[String]) -> bool {
+        self.ignore_row_keys.iter().any(|ignored| ignored == key)
+    }
+}
+
+/// How cells are compared, column by column
+///
+/// By default rows are compared position by position, a cell past the end
+/// of the shorter row comparing as empty, so a column is set-valued when it
+/// has a set-valued name in both tables at the same position. With
+/// `DiffOptions::align_columns`, each `from` column is compared with the
+/// `to` column of the same name, or the one it was renamed to, and columns
+/// only one table has are skipped.
+///
+/// Columns with a declared type (see `Snapshot::column_types`, the newer
+/// snapshot's declaration winning) compare by typed value, so `1,000` equals
+/// `1000` in a number column and `2024-01-02` equals `1/2/2024` in a date one.
+#[derive(Debug, Clone, Default)]
+struct CellEq {
+    /// Delimiter of each set-valued column, by `from` index
+    delimiters: Vec<Option<String>>,
+    /// Declared type of each column, by `from` index
+    types: Vec<Option<ColumnType>>,
+    /// Aligned (from index, to index) column pairs, if aligning by name
+    pairs: Option<Vec<(usize, usize)>>,
+}
+
+impl CellEq {
+    fn new(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+        renames: &[(usize, usize, f64)],
+    ) -> Self {
+        let declared = |name: &str| {
+            to.column_types
+                .get(name)
+                .or_else(|| from.column_types.get(name))
+                .copied()
+        };
+        let (from, to) = (&from.table, &to.table);
+        if !opts.align_columns {
+            let same_name = || {
+                from.headers
+                    .iter()
+                    .zip(&to.headers)
+                    .map(|(old, new)| (old == new).then_some(old))
+            };
+            return Self {
+                delimiters: same_name()
+                    .map(|name| name.and_then(|name| opts.set_columns.get(name).cloned()))
+                    .collect(),
+                types: same_name()
+                    .map(|name| name.and_then(|name| declared(name)))
+                    .collect(),
+                pairs: None,
+            };
+        }
+
+        let pairs = from
+            .headers
+            .iter()
+            .enumerate()
+            .filter_map(|(from_col, name)| {
+                let to_col = to.headers.iter().position(|h| h == name).or_else(|| {
+                    renames
+                        .iter()
+                        .find(|&&(renamed, _, _)| renamed == from_col)
+                        .map(|&(_, to_col, _)| to_col)
+                })?;
+                Some((from_col, to_col))
+            })
+            .collect();
+        let delimiters = from
+            .headers
+            .iter()
+            .map(|name| opts.set_columns.get(name).cloned())
+            .collect();
+        Self {
+            delimiters,
+            types: from.headers.iter().map(|name| declared(name)).collect(),
+            pairs: Some(pairs),
+        }
+    }
+
+    /// What kind of edit the change from `old` to `new` in column `col` is
+    fn kind(&self, col: usize, old: &str, new: &str) -> CellChangeKind {
+        match self.types.get(col).copied().flatten() {
+            Some(ColumnType::Text | ColumnType::Code) => {
+                CellChangeKind::classify_as(true, old, new)
+            }
+            Some(_) => CellChangeKind::classify_as(false, old, new),
+            None => CellChangeKind::classify(old, new),
+        }
+    }
+
+    /// Compared cells of two rows that differ, as (`from` column, old, new)
+    fn changed_cells<'r>(
+        &'r self,
+        old: &'r [String],
+        new: &'r [String],
+    ) -> Box<dyn Iterator<Item = (usize, &'r str, &'r str)> + 'r> {
+        let cells: Box<dyn Iterator<Item = (usize, &str, &str)>> = match &self.pairs {
+            Some(pairs) => Box::new(pairs.iter().map(move |&(from_col, to_col)| {
+                let cell = |row: &'r [String], col: usize| row.get(col).map_or("", String::as_str);
+                (from_col, cell(old, from_col), cell(new, to_col))
+            })),
+            None => Box::new((0..old.len().max(new.len())).map(move |col| {
+                let cell = |row: &'r [String]| row.get(col).map_or("", String::as_str);
+                (col, cell(old), cell(new))
+            })),
+        };
+        Box::new(cells.filter(|&(col, a, b)| !self.cell(col, a, b)))
+    }
+
+    /// Whether two cells in column `col` hold the same value
+    fn cell(&self, col: usize, old: &str, new: &str) -> bool {
+        if old == new {
+            return true;
+        }
+        fn same<T: PartialEq>(parse: impl Fn(&str) -> Option<T>, old: &str, new: &str) -> bool {
+            matches!((parse(old), parse(new)), (Some(a), Some(b)) if a == b)
+        }
+        match self.types.get(col).copied().flatten() {
+            Some(ColumnType::Integer | ColumnType::Float) => return same(parse_number, old, new),
+            Some(ColumnType::Date) => return same(parse_date, old, new),
+            Some(ColumnType::Boolean) => return same(parse_bool, old, new),
+            _ => {}
+        }
+        let Some(Some(delimiter)) = self.delimiters.get(col) else {
+            return false;
+        };
+        let items = |cell: &str| -> BTreeSet<String> {
+            cell.split(delimiter.as_str())
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        items(old) == items(new)
+    }
+
+    /// Whether two rows hold the same values (in the aligned columns)
+    fn row(&self, old: &[String], new: &[String]) -> bool {
+        (self.pairs.is_some() || old.len() == new.len())
+            && self.changed_cells(old, new).next().is_none()
+    }
+}
+
+/// How rows of the two tables are paired up
+///
+/// Precedence, highest first:
+/// 1. `DiffOptions::force_key`: both tables are keyed by those column names,
+///    and it is an error if either table lacks one of them.
+/// 2. Both tables declare the same primary key column names: each table's
+///    own key is used.
+/// 3. Neither table declares a key, or the key definitions differ: rows are
+///    paired by position, since matching keys of different shapes would
+///    report every row as changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowMatching {
+    /// By primary key values, with the key column indices in each table
+    Key { from: Vec<usize>, to: Vec<usize> },
+    /// By row position
+    Position,
+}
+
+impl RowMatching {
+    /// Decide how to pair the rows of `from` and `to`
+    pub fn resolve(from: &Table, to: &Table, opts: &DiffOptions) -> Result<Self, GitSheetsError> {
+        if let Some(names) = &opts.force_key {
+            let columns = |table: &Table| {
+                names
+                    .iter()
+                    .map(|name| {
+                        table.headers.iter().position(|h| h == name).ok_or_else(|| {
+                            GitSheetsError::InvalidColumn(format!("Key column '{name}' not found"))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            tracing::debug!(key = ?names, "matching rows by forced key");
+            return Ok(RowMatching::Key {
+                from: columns(from)?,
+                to: columns(to)?,
+            });
+        }
+
+        let from_key = from.primary_key.clone().unwrap_or_default();
+        let to_key = to.primary_key.clone().unwrap_or_default();
+        if from_key.is_empty() && to_key.is_empty() {
+            tracing::debug!("no primary key, matching rows by position");
+            Ok(RowMatching::Position)
+        } else if from.primary_key_names() == to.primary_key_names() {
+            tracing::debug!(key = ?from.primary_key_names(), "matching rows by primary key");
+            Ok(RowMatching::Key {
+                from: from_key,
+                to: to_key,
+            })
+        } else {
+            tracing::debug!(
+                from_key = ?from.primary_key_names(),
+                to_key = ?to.primary_key_names(),
+                "primary keys differ, matching rows by position"
+            );
+            Ok(RowMatching::Position)
+        }
+    }
+
+    /// Key columns of one side, or `None` when matching by position
+    fn columns(&self, to_side: bool) -> Option<&[usize]> {
+        match self {
+            RowMatching::Key { from, to } => Some(if to_side { to } else { from }),
+            RowMatching::Position => None,
+        }
+    }
+
+    /// Pair the rows of `from` and `to` for display, in `to` order
+    ///
+    /// Matched rows are `(Some, Some)`, removed rows `(Some, None)` and added
+    /// rows `(None, Some)`. Removed rows are placed before the first
+    /// matched row that followed them in `from`. Tables without a key are
+    /// paired by position.
+    pub fn pair_rows(&self, from: &Table, to: &Table) -> Vec<(Option<usize>, Option<usize>)> {
+        let columns = |to_side| self.columns(to_side).filter(|cols| !cols.is_empty());
+        let from_lookup = row_lookup(from, columns(false));
+        let from_match = |j: usize| {
+            lookup_key(to, j, columns(true)).and_then(|key| from_lookup.get(&key).copied())
+        };
+        let matched: Vec<Option<usize>> = (0..to.rows.len()).map(from_match).collect();
+        let claimed: HashSet<usize> = matched.iter().flatten().copied().collect();
+
+        let mut pairs = Vec::new();
+        let mut cursor = 0;
+        for (j, i) in matched.into_iter().enumerate() {
+            if let Some(i) = i {
+                pairs.extend(
+                    (cursor..i)
+                        .filter(|idx| !claimed.contains(idx))
+                        .map(|idx| (Some(idx), None)),
+                );
+                cursor = cursor.max(i + 1);
+            }
+            pairs.push((i, Some(j)));
+        }
+        pairs.extend(
+            (cursor..from.rows.len())
+                .filter(|idx| !claimed.contains(idx))
+                .map(|idx| (Some(idx), None)),
+        );
+        pairs
+    }
+}
+
+/// Lazily computed changes between two snapshots
+///
+/// Created by [`SnapshotDiff::iter_changes`]. Columns are reported first,
+/// then added, removed and modified rows, each in table order.
+pub struct ChangeIter<'a> {
+    from: &'a Table,
+    to: &'a Table,
+    matching: RowMatching,
+    lookups: RowLookups,
+    /// Detected renames as (from index, to index, similarity)
+    renames: Vec<(usize, usize, f64)>,
+    phase: Phase,
+    cursor: usize,
+    /// Cell changes of the current modified row not yet yielded
+    pending: VecDeque<Change>,
+    /// Whether to fill in `new_index` on matched rows
+    row_indices: bool,
+    /// Detected splits and merges, reported instead of their rows' adds
+    /// and removes
+    regrouped: Vec<Change>,
+    regrouped_from: HashSet<usize>,
+    regrouped_to: HashSet<usize>,
+    cell_eq: CellEq,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    ColumnsAdded,
+    ColumnsRemoved,
+    ColumnsRenamed,
+    RowsAdded,
+    RowsRemoved,
+    RowsRegrouped,
+    RowsModified,
+    Done,
+}
+
+/// Tables with at most this many rows are paired without lookups, unless
+/// renames or splits are detected, which need them
+///
+/// Scanning for a key costs a pass over the other table, which outgrows
+/// building and hashing every key at around 50 rows in
+/// `benches/small_tables.rs`.
+const SMALL_TABLE_ROWS: usize = 32;
+
+/// How a `ChangeIter` finds the row of the other table with the same key
+enum RowLookups {
+    /// Key values to row index, per table
+    Hashed {
+        from: HashMap<Vec<String>, usize>,
+        to: HashMap<Vec<String>, usize>,
+    },
+    /// Key cells compared in place, for small tables: no key is copied
+    /// unless a change reports it
+    Scanned { ignored: Vec<Vec<String>> },
+}
+
+impl ChangeIter<'_> {
+    /// The row of the other table with the same key as row `idx`, if any
+    ///
+    /// `None` if the row takes no part in the diff: it has no key, its key
+    /// is ignored, or a later row has the same key. Rows with duplicate keys
+    /// are only compared once, through the last occurrence, matching how the
+    /// lookups are built.
+    fn counterpart(&self, to_side: bool, idx: usize) -> Option<Option<usize>> {
+        let (table, other) = if to_side {
+            (self.to, self.from)
+        } else {
+            (self.from, self.to)
+        };
+        let columns = self.matching.columns(to_side);
+        let ignored = match &self.lookups {
+            RowLookups::Hashed { from, to } => {
+                let (lookup, other_lookup) = if to_side { (to, from) } else { (from, to) };
+                let key = lookup_key(table, idx, columns)?;
+                return (lookup.get(&key) == Some(&idx)).then(|| other_lookup.get(&key).copied());
+            }
+            RowLookups::Scanned { ignored } => ignored,
+        };
+
+        let (Some(columns), Some(other_columns)) = (columns, self.matching.columns(!to_side))
+        else {
+            return Some((idx < other.rows.len()).then_some(idx));
+        };
+        let key = || key_cells(table, idx, columns);
+        let unpaired = key().next().is_none()
+            || ignored.iter().any(|ignored| ignored.iter().eq(key()))
+            || (idx + 1..table.rows.len()).any(|later| key_cells(table, later, columns).eq(key()));
+        if unpaired {
+            return None;
+        }
+        Some(
+            (0..other.rows.len())
+                .rev()
+                .find(|&row| key_cells(other, row, other_columns).eq(key())),
+        )
+    }
+
+    /// The key to report on a change to row `idx`; positions aren't keys
+    fn reported_key(&self, to_side: bool, idx: usize) -> Option<Vec<String>> {
+        if self.matching == RowMatching::Position {
+            return None;
+        }
+        let table = if to_side { self.to } else { self.from };
+        lookup_key(table, idx, self.matching.columns(to_side))
+    }
+
+    fn advance(&mut self, phase: Phase) {
+        self.phase = phase;
+        self.cursor = 0;
+    }
+}
+
+impl Iterator for ChangeIter<'_> {
+    type Item = Change;
+
+    fn next(&mut self) -> Option<Change> {
+        loop {
+            if let Some(change) = self.pending.pop_front() {
+                return Some(change);
+            }
+
+            let idx = self.cursor;
+            self.cursor += 1;
+
+            match self.phase {
+                Phase::ColumnsAdded => {
+                    let Some(header) = self.to.headers.get(idx) else {
+                        self.advance(Phase::ColumnsRemoved);
+                        continue;
+                    };
+                    if !self.from.headers.contains(header)
+                        && !self.renames.iter().any(|&(_, to, _)| to == idx)
+                    {
+                        return Some(Change::ColumnAdded {
+                            name: header.clone(),
+                            index: idx,
+                        });
+                    }
+                }
+                Phase::ColumnsRemoved => {
+                    let Some(header) = self.from.headers.get(idx) else {
+                        self.advance(Phase::ColumnsRenamed);
+                        continue;
+                    };
+                    if !self.to.headers.contains(header)
+                        && !self.renames.iter().any(|&(from, _, _)| from == idx)
+                    {
+                        return Some(Change::ColumnRemoved {
+                            name: header.clone(),
+                            index: idx,
+                        });
+                    }
+                }
+                Phase::ColumnsRenamed => {
+                    let Some(&(from_index, to_index, similarity)) = self.renames.get(idx) else {
+                        self.advance(Phase::RowsAdded);
+                        continue;
+                    };
+                    return Some(Change::ColumnRenamed {
+                        from: self.from.headers[from_index].clone(),
+                        to: self.to.headers[to_index].clone(),
+                        from_index,
+                        to_index,
+                        similarity,
+                    });
+                }
+                Phase::RowsAdded => {
+                    if idx >= self.to.rows.len() {
+                        self.advance(Phase::RowsRemoved);
+                        continue;
+                    }
+                    if self.counterpart(true, idx) == Some(None)
+                        && !self.regrouped_to.contains(&idx)
+                    {
+                        return Some(Change::RowAdded {
+                            index: idx,
+                            data: self.to.rows[idx].clone(),
+                            key: self.reported_key(true, idx),
+                        });
+                    }
+                }
+                Phase::RowsRemoved => {
+                    if idx >= self.from.rows.len() {
+                        self.advance(Phase::RowsRegrouped);
+                        continue;
+                    }
+                    if self.counterpart(false, idx) == Some(None)
+                        && !self.regrouped_from.contains(&idx)
+                    {
+                        return Some(Change::RowRemoved {
+                            index: idx,
+                            data: self.from.rows[idx].clone(),
+                            key: self.reported_key(false, idx),
+                        });
+                    }
+                }
+                Phase::RowsRegrouped => {
+                    let Some(change) = self.regrouped.get(idx) else {
+                        self.advance(Phase::RowsModified);
+                        continue;
+                    };
+                    return Some(change.clone());
+                }
+                Phase::RowsModified => {
+                    if idx >= self.from.rows.len() {
+                        self.advance(Phase::Done);
+                        continue;
+                    }
+                    let Some(Some(to_idx)) = self.counterpart(false, idx) else {
+                        continue;
+                    };
+                    let from_row = &self.from.rows[idx];
+                    let to_row = &self.to.rows[to_idx];
+                    if self.cell_eq.row(from_row, to_row) {
+                        continue;
+                    }
+                    let key = self.reported_key(false, idx);
+                    let new_index = self.row_indices.then_some(to_idx);
+
+                    // Report cell-level changes; a row only counts as modified
+                    // when it differs without any differing overlapping cell
+                    for (col, old, new) in self.cell_eq.changed_cells(from_row, to_row) {
+                        self.pending.push_back(Change::CellChanged {
+                            row: idx,
+                            col,
+                            old: old.to_string(),
+                            new: new.to_string(),
+                            key: key.clone(),
+                            kind: self.cell_eq.kind(col, old, new),
+                            new_index,
+                        });
+                    }
+                    if self.pending.is_empty() {
+                        return Some(Change::RowModified {
+                            index: idx,
+                            old_data: from_row.clone(),
+                            new_data: to_row.clone(),
+                            key,
+                            new_index,
+                        });
+                    }
+                }
+                Phase::Done => return None,
+            }
+        }
+    }
+}
+
+/// A change to one column's value for one keyed row
+///
+/// `old` is `None` when the row (or column) did not exist before, and `new`
+/// is `None` when it no longer exists.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnValueChange {
+    /// Primary key values of the row
+    pub key: Vec<String>,
+    /// Value in the older snapshot
+    pub old: Option<String>,
+    /// Value in the newer snapshot
+    pub new: Option<String>,
+}
+
+/// Primary keys that appeared or disappeared between two snapshots
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyChanges {
+    /// Keys only in the newer snapshot, in its row order
+    pub added: Vec<Vec<String>>,
+    /// Keys only in the older snapshot, in its row order
+    pub removed: Vec<Vec<String>>,
+}
+
+/// Map each row's key values to its index
+///
+/// `columns` are the key columns, or `None` to key rows by position. An
+/// empty key (no primary key) produces an empty map.
+fn row_lookup(table: &Table, columns: Option<&[usize]>) -> HashMap<Vec<String>, usize> {
+    let mut lookup = HashMap::new();
+    for idx in 0..table.rows.len() {
+        if let Some(pk_values) = lookup_key(table, idx, columns) {
+            lookup.insert(pk_values, idx);
+        }
+    }
+    lookup
+}
+
+/// Splits and merges among the rows only one side has, when rows are
+/// matched by a non-empty key
+fn find_regroupings(
+    from: &Table,
+    to: &Table,
+    matching: &RowMatching,
+    from_lookup: &HashMap<Vec<String>, usize>,
+    to_lookup: &HashMap<Vec<String>, usize>,
+) -> Regroupings {
+    let (Some(from_keys), Some(to_keys)) = (matching.columns(false), matching.columns(true)) else {
+        return Regroupings::default();
+    };
+    if from_keys.is_empty() || to_keys.is_empty() {
+        return Regroupings::default();
+    }
+    let unmatched = |lookup: &HashMap<Vec<String>, usize>, other: &HashMap<Vec<String>, usize>| {
+        let mut rows: Vec<usize> = lookup
+            .iter()
+            .filter(|(key, _)| !other.contains_key(*key))
+            .map(|(_, &idx)| idx)
+            .collect();
+        rows.sort_unstable();
+        rows
+    };
+    detect_regroupings(
+        from,
+        to,
+        (from_keys, to_keys),
+        &unmatched(from_lookup, to_lookup),
+        &unmatched(to_lookup, from_lookup),
+    )
+}
+
+/// Pair removed and added columns whose values mostly agree
+///
+/// Rows are paired through the lookups, or by position when there are no
+/// keys to pair them by. Each removed column is matched to the unclaimed added column
+/// with the highest similarity, provided it reaches `threshold`. Returns
+/// (from index, to index, similarity) in `to` column order.
+fn detect_renames(
+    from: &Table,
+    to: &Table,
+    from_lookup: &HashMap<Vec<String>, usize>,
+    to_lookup: &HashMap<Vec<String>, usize>,
+    threshold: f64,
+) -> Vec<(usize, usize, f64)> {
+    let removed: Vec<usize> = (0..from.headers.len())
+        .filter(|&i| !to.headers.contains(&from.headers[i]))
+        .collect();
+    let mut added: Vec<usize> = (0..to.headers.len())
+        .filter(|&i| !from.headers.contains(&to.headers[i]))
+        .collect();
+    if removed.is_empty() || added.is_empty() {
+        return Vec::new();
+    }
+
+    let row_pairs: Vec<(usize, usize)> = if !from_lookup.is_empty() {
+        let mut pairs: Vec<(usize, usize)> = from_lookup
+            .iter()
+            .filter_map(|(key, &f)| to_lookup.get(key).map(|&t| (f, t)))
+            .collect();
+        pairs.sort_unstable();
+        pairs
+    } else {
+        (0..from.rows.len().min(to.rows.len()))
+            .map(|i| (i, i))
+            .collect()
+    };
+    if row_pairs.is_empty() {
+        return Vec::new();
+    }
+
+    let similarity = |from_col: usize, to_col: usize| {
+        let matching = row_pairs
+            .iter()
+            .filter(|&&(f, t)| from.rows[f].get(from_col) == to.rows[t].get(to_col))
+            .count();
+        matching as f64 / row_pairs.len() as f64
+    };
+
+    let mut renames = Vec::new();
+    for from_col in removed {
+        let best = added
+            .iter()
+            .enumerate()
+            .map(|(pos, &to_col)| (pos, to_col, similarity(from_col, to_col)))
+            .filter(|&(_, _, score)| score >= threshold)
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        if let Some((pos, to_col, score)) = best {
+            added.remove(pos);
+            renames.push((from_col, to_col, score));
+        }
+    }
+    renames.sort_by_key(|&(_, to_col, _)| to_col);
+    renames
+}
+
+/// Lookups of both tables, without the rows `DiffOptions::ignore_row_keys`
+/// leaves out
+fn row_lookups(
+    from: &Table,
+    to: &Table,
+    matching: &RowMatching,
+    opts: &DiffOptions,
+) -> (HashMap<Vec<String>, usize>, HashMap<Vec<String>, usize>) {
+    let mut from_lookup = row_lookup(from, matching.columns(false));
+    let mut to_lookup = row_lookup(to, matching.columns(true));
+    if *matching != RowMatching::Position {
+        for key in &opts.ignore_row_keys {
+            from_lookup.remove(key);
+            to_lookup.remove(key);
+        }
+    }
+    (from_lookup, to_lookup)
+}
+
+/// Key values used to match a row across snapshots
+///
+/// With no key columns the row's position is its key.
+fn lookup_key(table: &Table, idx: usize, columns: Option<&[usize]>) -> Option<Vec<String>> {
+    let row = table.rows.get(idx)?;
+    let Some(pk_indices) = columns else {
+        return Some(vec![idx.to_string()]);
+    };
+    let pk_values: Vec<String> = pk_indices
+        .iter()
+        .filter_map(|&i| row.get(i).cloned())
+        .collect();
+    (!pk_values.is_empty()).then_some(pk_values)
+}
+
+/// Key cells of a row, borrowed: the values `lookup_key` would copy
+fn key_cells<'t>(
+    table: &'t Table,
+    idx: usize,
+    columns: &'t [usize],
+) -> impl Iterator<Item = &'t String> {
+    let row = &table.rows[idx];
+    columns.iter().filter_map(|&col| row.get(col))
 }
 
 /// A diff between two snapshots
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SnapshotDiff {
     /// ID of the from snapshot
     pub from_id: String,
@@ -87,148 +1196,359 @@ pub struct SnapshotDiff {
 impl SnapshotDiff {
     /// Create a diff between two snapshots
     pub fn compute(from: &Snapshot, to: &Snapshot) -> Result<Self, GitSheetsError> {
-        let mut changes = Vec::new();
-        let mut summary = DiffSummary {
-            rows_added: 0,
-            rows_removed: 0,
-            rows_modified: 0,
-            columns_added: 0,
-            columns_removed: 0,
+        Self::compute_with(from, to, &DiffOptions::default())
+    }
+
+    /// Create a diff between two snapshots with explicit options
+    #[tracing::instrument(level = "debug", skip_all, fields(from = %from.id, to = %to.id))]
+    pub fn compute_with(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<Self, GitSheetsError> {
+        let mut summary = DiffSummary::default();
+        let changes: Vec<Change> = Self::iter_changes(from, to, opts)?
+            .inspect(|change| summary.record(change))
+            .collect();
+        tracing::debug!(changes = changes.len(), "diff computed");
+
+        Ok(Self {
+            from_id: from.id.clone(),
+            to_id: to.id.clone(),
+            summary,
+            changes,
+        })
+    }
+
+    /// Create a diff that lines columns up by name rather than by position
+    ///
+    /// For snapshots far apart, whose columns were added, dropped, reordered
+    /// or renamed in between: each cell is compared with the cell of the
+    /// same column (or the column it was renamed to) in `to`, so no change is
+    /// reported against the wrong column, and a row is never reported as
+    /// modified just because columns came or went. Renames are detected at
+    /// `opts.rename_threshold`, or `DEFAULT_RENAME_THRESHOLD` if unset.
+    /// `CellChanged::col` is the column's index in `from`.
+    pub fn compute_with_schema_reconciliation(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<Self, GitSheetsError> {
+        Self::compute_with(from, to, &opts.with_schema_reconciliation())
+    }
+
+    /// Yield the changes between two snapshots one at a time
+    ///
+    /// Produces exactly the changes `compute` would collect, in the same
+    /// order, but only the primary key lookups are built up front, so memory
+    /// stays flat however many changes there are. Rows are paired as
+    /// described on [`RowMatching`].
+    pub fn iter_changes<'a>(
+        from: &'a Snapshot,
+        to: &'a Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<ChangeIter<'a>, GitSheetsError> {
+        let matching = RowMatching::resolve(&from.table, &to.table, opts)?;
+        let small = from.table.rows.len() <= SMALL_TABLE_ROWS
+            && to.table.rows.len() <= SMALL_TABLE_ROWS
+            && opts.rename_threshold.is_none()
+            && !opts.detect_splits;
+        let (lookups, renames, regroupings) = if small {
+            let ignored = opts.ignore_row_keys.clone();
+            (
+                RowLookups::Scanned { ignored },
+                Vec::new(),
+                Regroupings::default(),
+            )
+        } else {
+            let (from_lookup, to_lookup) = row_lookups(&from.table, &to.table, &matching, opts);
+            let renames = opts.rename_threshold.map_or_else(Vec::new, |threshold| {
+                detect_renames(&from.table, &to.table, &from_lookup, &to_lookup, threshold)
+            });
+            let regroupings = if opts.detect_splits {
+                find_regroupings(&from.table, &to.table, &matching, &from_lookup, &to_lookup)
+            } else {
+                Regroupings::default()
+            };
+            tracing::debug!(
+                from_keys = from_lookup.len(),
+                to_keys = to_lookup.len(),
+                renames = renames.len(),
+                splits = regroupings.splits.len(),
+                merges = regroupings.merges.len(),
+                "row lookups built"
+            );
+            let lookups = RowLookups::Hashed {
+                from: from_lookup,
+                to: to_lookup,
+            };
+            (lookups, renames, regroupings)
         };
 
-        // Compare headers (columns)
-        let from_headers = &from.table.headers;
-        let to_headers = &to.table.headers;
+        let keys = |to_side: bool| matching.columns(to_side).unwrap_or_default();
+        let splits = regroupings
+            .splits
+            .iter()
+            .map(|(old, new)| Change::RowSplit {
+                from: row_ref(&from.table, *old, keys(false)),
+                into: new
+                    .iter()
+                    .map(|&idx| row_ref(&to.table, idx, keys(true)))
+                    .collect(),
+            });
+        let merges = regroupings
+            .merges
+            .iter()
+            .map(|(old, new)| Change::RowMerged {
+                from: old
+                    .iter()
+                    .map(|&idx| row_ref(&from.table, idx, keys(false)))
+                    .collect(),
+                into: row_ref(&to.table, *new, keys(true)),
+            });
+        let regrouped = splits.chain(merges).collect();
 
-        // Check for added columns
-        for (idx, header) in to_headers.iter().enumerate() {
-            if !from_headers.contains(header) {
-                changes.push(Change::ColumnAdded {
-                    name: header.clone(),
-                    index: idx,
-                });
-                summary.columns_added += 1;
-            }
+        Ok(ChangeIter {
+            from: &from.table,
+            to: &to.table,
+            matching,
+            lookups,
+            phase: Phase::ColumnsAdded,
+            cursor: 0,
+            pending: VecDeque::new(),
+            row_indices: opts.row_indices,
+            regrouped,
+            regrouped_from: regroupings.old_rows(),
+            regrouped_to: regroupings.new_rows(),
+            cell_eq: CellEq::new(from, to, opts, &renames),
+            renames,
+        })
+    }
+
+    /// Count changes between two snapshots without building the change list
+    ///
+    /// Produces the same numbers as `compute(from, to).summary` by streaming
+    /// `iter_changes`: each change is counted and dropped in turn, so memory
+    /// stays flat when a diff would contain millions of changes.
+    pub fn summarize(from: &Snapshot, to: &Snapshot) -> DiffSummary {
+        Self::summarize_with(from, to, &DiffOptions::default())
+            .expect("rows can always be matched without a forced key")
+    }
+
+    /// Count changes between two snapshots with explicit options
+    #[tracing::instrument(level = "debug", skip_all, fields(from = %from.id, to = %to.id))]
+    pub fn summarize_with(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<DiffSummary, GitSheetsError> {
+        let mut summary = DiffSummary::default();
+        for change in Self::iter_changes(from, to, opts)? {
+            summary.record(&change);
         }
+        Ok(summary)
+    }
 
-        // Check for removed columns
-        for (idx, header) in from_headers.iter().enumerate() {
-            if !to_headers.contains(header) {
-                changes.push(Change::ColumnRemoved {
-                    name: header.clone(),
-                    index: idx,
-                });
-                summary.columns_removed += 1;
-            }
+    /// Keys in `opts.ignore_row_keys` that match no row of either snapshot,
+    /// probably typos
+    pub fn unmatched_ignored_keys(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<Vec<Vec<String>>, GitSheetsError> {
+        let matching = RowMatching::resolve(&from.table, &to.table, opts)?;
+        let from_lookup = row_lookup(&from.table, matching.columns(false));
+        let to_lookup = row_lookup(&to.table, matching.columns(true));
+        Ok(opts
+            .ignore_row_keys
+            .iter()
+            .filter(|key| !from_lookup.contains_key(*key) && !to_lookup.contains_key(*key))
+            .cloned()
+            .collect())
+    }
+
+    /// Number of distinct rows added, removed or changed in any cell
+    ///
+    /// Unlike `DiffSummary::rows_modified`, a row counts once however many of
+    /// its cells changed. Column changes don't count.
+    pub fn changed_row_count(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<usize, GitSheetsError> {
+        let mut rows: HashSet<(bool, usize)> = HashSet::new();
+        for change in Self::iter_changes(from, to, opts)? {
+            record_rows(&mut rows, &change);
         }
+        Ok(rows.len())
+    }
 
-        // Compare rows using primary key-based identification
-        let from_rows = &from.table.rows;
-        let to_rows = &to.table.rows;
+    /// Save diff to disk as TOML
+    #[cfg(feature = "native")]
+    pub fn save(&self, path: &Path) -> Result<(), GitSheetsError> {
+        let toml_string = toml::to_string_pretty(self)?;
+        write_atomic(path, toml_string.as_bytes())
+    }
 
-        // Create lookup maps for rows by primary key
-        let mut from_row_lookup: HashMap<Vec<String>, usize> = HashMap::new();
-        let mut to_row_lookup: HashMap<Vec<String>, usize> = HashMap::new();
+    /// Save diff to disk as minified JSON, for storage and transport
+    ///
+    /// Much smaller and faster to write than `save` for large diffs. Give
+    /// `path` a `.json` extension so `load` reads it back.
+    #[cfg(feature = "native")]
+    pub fn save_compact(&self, path: &Path) -> Result<(), GitSheetsError> {
+        write_atomic(path, &serde_json::to_vec(self)?)
+    }
 
-        if let Some(pk_indices) = &from.table.primary_key {
-            for (idx, row) in from_rows.iter().enumerate() {
-                let pk_values: Vec<String> = pk_indices
-                    .iter()
-                    .filter_map(|&i| row.get(i).cloned())
-                    .collect();
-                if !pk_values.is_empty() {
-                    from_row_lookup.insert(pk_values, idx);
-                }
-            }
+    /// Load a diff saved with `save`, or a JSON diff from a `.json` path
+    #[cfg(feature = "native")]
+    pub fn load(path: &Path) -> Result<Self, GitSheetsError> {
+        let content = fs::read_to_string(path)?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            return Ok(serde_json::from_str(&content)?);
         }
+        Ok(toml::from_str(&content)?)
+    }
 
-        if let Some(pk_indices) = &to.table.primary_key {
-            for (idx, row) in to_rows.iter().enumerate() {
-                let pk_values: Vec<String> = pk_indices
-                    .iter()
-                    .filter_map(|&i| row.get(i).cloned())
-                    .collect();
-                if !pk_values.is_empty() {
-                    to_row_lookup.insert(pk_values, idx);
-                }
-            }
+    /// List every keyed row whose value in `column` differs between two snapshots
+    ///
+    /// Both snapshots need a primary key. Rows come out in `to` order,
+    /// followed by rows that only exist in `from`.
+    pub fn column_changes(
+        from: &Snapshot,
+        to: &Snapshot,
+        column: &str,
+    ) -> Result<Vec<ColumnValueChange>, GitSheetsError> {
+        if from.table.primary_key.is_none() || to.table.primary_key.is_none() {
+            return Err(GitSheetsError::NoPrimaryKey);
         }
 
-        // Check for added rows (rows not in from but in to)
-        let mut added_rows = Vec::new();
-        for (pk_values, to_idx) in &to_row_lookup {
-            if !from_row_lookup.contains_key(pk_values) {
-                added_rows.push((to_idx, to_rows[*to_idx].clone()));
-            }
-        }
+        let value = |table: &Table, row_idx: usize| {
+            let col_idx = table.headers.iter().position(|h| h == column)?;
+            table.rows[row_idx].get(col_idx).cloned()
+        };
+
+        let from_lookup = row_lookup(&from.table, from.table.primary_key.as_deref());
+        let to_lookup = row_lookup(&to.table, to.table.primary_key.as_deref());
+        let mut changes = Vec::new();
 
-        // Check for removed rows (rows not in to but in from)
-        let mut removed_rows = Vec::new();
-        for (pk_values, from_idx) in &from_row_lookup {
-            if !to_row_lookup.contains_key(pk_values) {
-                removed_rows.push((from_idx, from_rows[*from_idx].clone()));
+        for to_idx in 0..to.table.rows.len() {
+            let key = to.table.get_row_key(to_idx)?;
+            let old = from_lookup
+                .get(&key)
+                .and_then(|&from_idx| value(&from.table, from_idx));
+            let new = value(&to.table, to_idx);
+            if old != new {
+                changes.push(ColumnValueChange { key, old, new });
             }
         }
 
-        // Check for modified rows (rows with same primary key but different content)
-        let mut modified_rows = Vec::new();
-        for (pk_values, from_idx) in &from_row_lookup {
-            if let Some(to_idx) = to_row_lookup.get(pk_values) {
-                if from_rows[*from_idx] != to_rows[*to_idx] {
-                    modified_rows.push((from_idx, to_idx));
+        for from_idx in 0..from.table.rows.len() {
+            let key = from.table.get_row_key(from_idx)?;
+            if !to_lookup.contains_key(&key) {
+                let old = value(&from.table, from_idx);
+                if old.is_some() {
+                    changes.push(ColumnValueChange {
+                        key,
+                        old,
+                        new: None,
+                    });
                 }
             }
         }
 
-        // Add added rows
-        for (index, data) in added_rows {
-            changes.push(Change::RowAdded {
-                index: *index,
-                data,
-            });
-            summary.rows_added += 1;
-        }
+        Ok(changes)
+    }
 
-        // Add removed rows
-        for (index, data) in removed_rows {
-            changes.push(Change::RowRemoved {
-                index: *index,
-                data,
-            });
-            summary.rows_removed += 1;
+    /// List the primary keys added and removed, without comparing any cells
+    ///
+    /// Rows are keyed as `compute_with` would key them. Fails with
+    /// `NoPrimaryKey` when that would pair rows by position instead.
+    /// Duplicate keys are reported once.
+    pub fn key_changes(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<KeyChanges, GitSheetsError> {
+        let matching = RowMatching::resolve(&from.table, &to.table, opts)?;
+        let (Some(from_cols), Some(to_cols)) = (matching.columns(false), matching.columns(true))
+        else {
+            return Err(GitSheetsError::NoPrimaryKey);
+        };
+        if from_cols.is_empty() {
+            return Err(GitSheetsError::NoPrimaryKey);
         }
 
-        // Add modified rows - but avoid double-counting by only adding row modification
-        // if there are no other changes for this row (cell changes would be handled separately)
-        for (from_idx, to_idx) in modified_rows {
-            // Check if this row has cell-level changes
-            let mut has_cell_changes = false;
-            for (col_idx, (from_cell, to_cell)) in from_rows[*from_idx]
-                .iter()
-                .zip(to_rows[*to_idx].iter())
-                .enumerate()
-            {
-                if from_cell != to_cell {
-                    changes.push(Change::CellChanged {
-                        row: *from_idx,
-                        col: col_idx,
-                        old: from_cell.clone(),
-                        new: to_cell.clone(),
-                    });
-                    has_cell_changes = true;
-                }
-            }
+        // Keys of `table` missing from `other`, first occurrence only
+        let only_in = |table: &Table, cols: &[usize], other: &Table, other_cols: &[usize]| {
+            let other_lookup = row_lookup(other, Some(other_cols));
+            let mut seen = HashSet::new();
+            (0..table.rows.len())
+                .filter_map(|idx| lookup_key(table, idx, Some(cols)))
+                .filter(|key| !other_lookup.contains_key(key) && !opts.ignores(key))
+                .filter(|key| seen.insert(key.clone()))
+                .collect()
+        };
+        let added = only_in(&to.table, to_cols, &from.table, from_cols);
+        let removed = only_in(&from.table, from_cols, &to.table, to_cols);
 
-            // Only add RowModified if there are no cell changes (avoid double counting)
-            if !has_cell_changes {
-                changes.push(Change::RowModified {
-                    index: *from_idx,
-                    old_data: from_rows[*from_idx].clone(),
-                    new_data: to_rows[*to_idx].clone(),
-                });
-                summary.rows_modified += 1;
-            }
+        Ok(KeyChanges { added, removed })
+    }
+
+    /// Diff a log-style table that only ever gains rows
+    ///
+    /// Reports each row of `to` whose key isn't in `from` as added, without
+    /// comparing any cells. Rows are keyed as `compute_with` would key them;
+    /// `NoPrimaryKey` if that would pair them by position. Fails with
+    /// `AppendOnlyViolation` if the columns differ or any key of `from` is
+    /// missing from `to`.
+    pub fn compute_append_only(
+        from: &Snapshot,
+        to: &Snapshot,
+        opts: &DiffOptions,
+    ) -> Result<Self, GitSheetsError> {
+        if from.table.headers != to.table.headers {
+            return Err(GitSheetsError::AppendOnlyViolation(format!(
+                "columns changed from {:?} to {:?}",
+                from.table.headers, to.table.headers
+            )));
         }
+        let matching = RowMatching::resolve(&from.table, &to.table, opts)?;
+        let (Some(from_cols), Some(to_cols)) = (matching.columns(false), matching.columns(true))
+        else {
+            return Err(GitSheetsError::NoPrimaryKey);
+        };
+        if from_cols.is_empty() {
+            return Err(GitSheetsError::NoPrimaryKey);
+        }
+
+        let to_lookup = row_lookup(&to.table, Some(to_cols));
+        let missing: Vec<Vec<String>> = (0..from.table.rows.len())
+            .filter_map(|idx| lookup_key(&from.table, idx, Some(from_cols)))
+            .filter(|key| !to_lookup.contains_key(key) && !opts.ignores(key))
+            .collect();
+        if let Some(first) = missing.first() {
+            return Err(GitSheetsError::AppendOnlyViolation(format!(
+                "{} row(s) of {} are missing from {}, starting with key {}",
+                missing.len(),
+                from.id,
+                to.id,
+                first.join("|")
+            )));
+        }
+
+        let from_lookup = row_lookup(&from.table, Some(from_cols));
+        let mut summary = DiffSummary::default();
+        let changes: Vec<Change> = (0..to.table.rows.len())
+            .filter_map(|idx| {
+                let key = lookup_key(&to.table, idx, Some(to_cols))?;
+                (!from_lookup.contains_key(&key) && !opts.ignores(&key)).then(|| Change::RowAdded {
+                    index: idx,
+                    data: to.table.rows[idx].clone(),
+                    key: Some(key),
+                })
+            })
+            .inspect(|change| summary.record(change))
+            .collect();
 
         Ok(Self {
             from_id: from.id.clone(),
@@ -238,11 +1558,120 @@ impl SnapshotDiff {
         })
     }
 
-    /// Save diff to disk as TOML
-    pub fn save(&self, path: &Path) -> Result<(), GitSheetsError> {
-        let toml_string = toml::to_string_pretty(self)?;
-        fs::write(path, toml_string)?;
-        Ok(())
+    /// Apply this diff to the `from` table to rebuild the `to` table
+    ///
+    /// Rows are matched the same way `compute` matched them: removed and
+    /// modified rows by their index in `from`, added rows by their index in
+    /// `to`. Cells are replayed by position, as the default `compute`
+    /// compares them: when columns are added or removed, the cell changes
+    /// shift each row's cells into place, a cell changed past the end of a
+    /// row extends it, and rows as wide as the `from` header are fitted to
+    /// the new one.
+    /// Changes the diff does not record (reordered rows, or cells of columns
+    /// aligned by name with `DiffOptions::align_columns`) cannot be
+    /// replayed, so callers that need an exact result should compare the
+    /// hash of the output with the target.
+    pub fn apply(&self, from: &Table) -> Result<Table, GitSheetsError> {
+        let mut rows: Vec<Option<Vec<String>>> = from.rows.iter().cloned().map(Some).collect();
+        let mut headers = from.headers.clone();
+        let mut added_rows = Vec::new();
+        let mut added_columns = Vec::new();
+        // Cells emptied per row, dropped if they end up past the last column
+        let mut emptied: HashMap<usize, HashSet<usize>> = HashMap::new();
+
+        let out_of_range = |idx: usize| {
+            GitSheetsError::InvalidRowIndex(format!(
+                "Row index {idx} exceeds row count {}",
+                from.rows.len()
+            ))
+        };
+
+        for change in &self.changes {
+            match change {
+                Change::RowRemoved { index, .. } => {
+                    *rows.get_mut(*index).ok_or_else(|| out_of_range(*index))? = None;
+                }
+                Change::RowModified {
+                    index, new_data, ..
+                } => {
+                    let row = rows.get_mut(*index).ok_or_else(|| out_of_range(*index))?;
+                    *row = Some(new_data.clone());
+                }
+                Change::CellChanged { row, col, new, .. } => {
+                    let cells = rows
+                        .get_mut(*row)
+                        .ok_or_else(|| out_of_range(*row))?
+                        .as_mut()
+                        .ok_or_else(|| out_of_range(*row))?;
+                    if *col >= cells.len() {
+                        cells.resize(col + 1, String::new());
+                    }
+                    cells[*col] = new.clone();
+                    if new.is_empty() {
+                        emptied.entry(*row).or_default().insert(*col);
+                    }
+                }
+                Change::RowAdded { index, data, .. } => added_rows.push((*index, data.clone())),
+                Change::RowSplit { from, into } => {
+                    *rows
+                        .get_mut(from.index)
+                        .ok_or_else(|| out_of_range(from.index))? = None;
+                    added_rows.extend(into.iter().map(|row| (row.index, row.data.clone())));
+                }
+                Change::RowMerged { from, into } => {
+                    for row in from {
+                        *rows
+                            .get_mut(row.index)
+                            .ok_or_else(|| out_of_range(row.index))? = None;
+                    }
+                    added_rows.push((into.index, into.data.clone()));
+                }
+                Change::ColumnRemoved { name, .. } => headers.retain(|h| h != name),
+                Change::ColumnAdded { name, index } => added_columns.push((*index, name.clone())),
+                Change::ColumnRenamed { from, to, .. } => {
+                    if let Some(header) = headers.iter_mut().find(|h| *h == from) {
+                        *header = to.clone();
+                    }
+                }
+            }
+        }
+
+        // Insert in ascending target position so earlier inserts don't shift later ones
+        added_columns.sort_by_key(|(idx, _)| *idx);
+        for (index, name) in added_columns {
+            headers.insert(index.min(headers.len()), name);
+        }
+
+        // Full-width rows take the new width: a missing cell compares equal to
+        // an empty one, so empty cells past either end never show up as changes
+        for (idx, row) in rows.iter_mut().enumerate() {
+            let Some(cells) = row.as_mut() else { continue };
+            let full_width = from.rows[idx].len() == from.headers.len();
+            if full_width && cells.len() < headers.len() {
+                cells.resize(headers.len(), String::new());
+            }
+            while cells.len() > headers.len()
+                && cells.last().is_some_and(String::is_empty)
+                && (full_width
+                    || emptied
+                        .get(&idx)
+                        .is_some_and(|c| c.contains(&(cells.len() - 1))))
+            {
+                cells.pop();
+            }
+        }
+
+        let mut rows: Vec<Vec<String>> = rows.into_iter().flatten().collect();
+        added_rows.sort_by_key(|(idx, _)| *idx);
+        for (index, data) in added_rows {
+            rows.insert(index.min(rows.len()), data);
+        }
+
+        Ok(Table {
+            headers,
+            rows,
+            primary_key: from.primary_key.clone(),
+        })
     }
 
     /// Enhanced diff using Patience algorithm for better row comparison
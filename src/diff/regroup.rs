@@ -0,0 +1,137 @@
+// git-sheets: Split and merged rows - one removed row whose numbers were
+// spread over several added rows, or several removed rows added up into one
+
+use super::RowRef;
+use crate::core::{Table, parse_number};
+use std::collections::{HashMap, HashSet};
+
+/// Removed and added rows paired up as splits and merges
+#[derive(Debug, Default)]
+pub(super) struct Regroupings {
+    /// Each split as (from index, to indices)
+    pub splits: Vec<(usize, Vec<usize>)>,
+    /// Each merge as (from indices, to index)
+    pub merges: Vec<(Vec<usize>, usize)>,
+}
+
+impl Regroupings {
+    /// Rows of `from` that took part in a split or merge
+    pub fn old_rows(&self) -> HashSet<usize> {
+        let split = self.splits.iter().map(|(from, _)| *from);
+        let merged = self
+            .merges
+            .iter()
+            .flat_map(|(from, _)| from.iter().copied());
+        split.chain(merged).collect()
+    }
+
+    /// Rows of `to` that took part in a split or merge
+    pub fn new_rows(&self) -> HashSet<usize> {
+        let split = self.splits.iter().flat_map(|(_, to)| to.iter().copied());
+        let merged = self.merges.iter().map(|(_, to)| *to);
+        split.chain(merged).collect()
+    }
+}
+
+/// A row as it takes part in a split or merge
+pub(super) fn row_ref(table: &Table, index: usize, key_columns: &[usize]) -> RowRef {
+    let data = table.rows[index].clone();
+    let key = key_columns
+        .iter()
+        .filter_map(|&col| data.get(col).cloned())
+        .collect();
+    RowRef { index, key, data }
+}
+
+/// One row's shared non-key cells: text cells by value, numeric cells as
+/// `None` in the signature and their values alongside
+fn split_row(row: &[String], columns: &[usize]) -> (Vec<Option<String>>, Vec<f64>) {
+    let mut signature = Vec::new();
+    let mut numbers = Vec::new();
+    for &col in columns {
+        let cell = row.get(col).map_or("", String::as_str);
+        match parse_number(cell).filter(|_| !cell.trim().is_empty()) {
+            Some(value) => {
+                signature.push(None);
+                numbers.push(value);
+            }
+            None => signature.push(Some(cell.to_string())),
+        }
+    }
+    (signature, numbers)
+}
+
+/// Whether the numbers of `parts` add up to `whole`, column by column
+fn adds_up(whole: &[f64], parts: &[&[f64]]) -> bool {
+    whole.iter().enumerate().all(|(i, &total)| {
+        let sum: f64 = parts.iter().map(|part| part[i]).sum();
+        (sum - total).abs() <= 1e-9 * total.abs().max(1.0)
+    })
+}
+
+/// Find removed rows spread over several added rows, and the reverse
+///
+/// Rows are compared over the columns both tables share, key columns aside.
+/// A group qualifies when its text cells all equal the whole row's, at
+/// least one shared cell is numeric, and each numeric column of the parts
+/// sums to the whole row's value. Only groups of exactly one row on one side
+/// and two or more on the other are reported, so the check is a single pass
+/// over the unmatched rows rather than a search over subsets.
+pub(super) fn detect_regroupings(
+    from: &Table,
+    to: &Table,
+    key_columns: (&[usize], &[usize]),
+    removed: &[usize],
+    added: &[usize],
+) -> Regroupings {
+    let (from_keys, to_keys) = key_columns;
+    let (from_columns, to_columns): (Vec<usize>, Vec<usize>) = from
+        .headers
+        .iter()
+        .enumerate()
+        .filter(|(col, _)| !from_keys.contains(col))
+        .filter_map(|(from_col, name)| {
+            let to_col = to.headers.iter().position(|h| h == name)?;
+            (!to_keys.contains(&to_col)).then_some((from_col, to_col))
+        })
+        .unzip();
+
+    type Bucket = (Vec<(usize, Vec<f64>)>, Vec<(usize, Vec<f64>)>);
+    let mut buckets: HashMap<Vec<Option<String>>, Bucket> = HashMap::new();
+    for &idx in removed {
+        let (signature, numbers) = split_row(&from.rows[idx], &from_columns);
+        if !numbers.is_empty() {
+            buckets.entry(signature).or_default().0.push((idx, numbers));
+        }
+    }
+    for &idx in added {
+        let (signature, numbers) = split_row(&to.rows[idx], &to_columns);
+        if !numbers.is_empty() {
+            buckets.entry(signature).or_default().1.push((idx, numbers));
+        }
+    }
+
+    let mut found = Regroupings::default();
+    for (old, new) in buckets.into_values() {
+        match (&old[..], &new[..]) {
+            ([(whole, total)], parts) if parts.len() >= 2 => {
+                let numbers: Vec<&[f64]> = parts.iter().map(|(_, n)| n.as_slice()).collect();
+                if adds_up(total, &numbers) {
+                    let into = parts.iter().map(|(idx, _)| *idx).collect();
+                    found.splits.push((*whole, into));
+                }
+            }
+            (parts, [(whole, total)]) if parts.len() >= 2 => {
+                let numbers: Vec<&[f64]> = parts.iter().map(|(_, n)| n.as_slice()).collect();
+                if adds_up(total, &numbers) {
+                    let from = parts.iter().map(|(idx, _)| *idx).collect();
+                    found.merges.push((from, *whole));
+                }
+            }
+            _ => {}
+        }
+    }
+    found.splits.sort();
+    found.merges.sort_by_key(|(_, to)| *to);
+    found
+}
@@ -0,0 +1,136 @@
+// git-sheets: Severity - ranking changes so the dangerous ones come first
+
+use super::{CellChangeKind, Change};
+use crate::core::{parse_number, schema::parse_date};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How much each kind of change counts towards a row's severity
+///
+/// Read from the `[severity]` table of `.gitsheets/config.toml`; missing
+/// weights keep their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeverityWeights {
+    /// A row disappeared
+    pub row_removed: f64,
+    /// A cell switched between number, date, text and empty
+    pub type_changed: f64,
+    /// A number changed
+    pub numeric: f64,
+    /// Any other content change
+    pub text: f64,
+    /// Only whitespace changed
+    pub whitespace: f64,
+    /// A row appeared
+    pub row_added: f64,
+}
+
+impl Default for SeverityWeights {
+    fn default() -> Self {
+        Self {
+            row_removed: 10.0,
+            type_changed: 5.0,
+            numeric: 3.0,
+            text: 2.0,
+            whitespace: 0.5,
+            row_added: 1.0,
+        }
+    }
+}
+
+/// What kind of value a cell holds, for spotting type changes
+#[derive(PartialEq, Eq)]
+pub(super) enum CellType {
+    Empty,
+    Number,
+    Date,
+    Text,
+}
+
+pub(super) fn cell_type(cell: &str) -> CellType {
+    if cell.trim().is_empty() {
+        CellType::Empty
+    } else if parse_number(cell).is_some() {
+        CellType::Number
+    } else if parse_date(cell).is_some() {
+        CellType::Date
+    } else {
+        CellType::Text
+    }
+}
+
+impl SeverityWeights {
+    /// Weight of a single change; column changes are not row-level and score 0
+    pub fn score(&self, change: &Change) -> f64 {
+        match change {
+            Change::RowRemoved { .. } => self.row_removed,
+            Change::RowAdded { .. } => self.row_added,
+            Change::RowModified { .. } | Change::RowSplit { .. } | Change::RowMerged { .. } => {
+                self.text
+            }
+            Change::CellChanged {
+                kind: CellChangeKind::WhitespaceOnly,
+                ..
+            } => self.whitespace,
+            Change::CellChanged { old, new, .. } => match (cell_type(old), cell_type(new)) {
+                (from, to) if from != to => self.type_changed,
+                (CellType::Number, CellType::Number) => self.numeric,
+                _ => self.text,
+            },
+            Change::ColumnAdded { .. }
+            | Change::ColumnRemoved { .. }
+            | Change::ColumnRenamed { .. } => 0.0,
+        }
+    }
+}
+
+/// The changes to one row and their combined weight
+#[derive(Debug, Clone)]
+pub struct RowSeverity {
+    /// Sum of the weights of `changes`
+    pub score: f64,
+    /// Changes to the row, in diff order
+    pub changes: Vec<Change>,
+}
+
+/// Group changes by row and sort the rows worst-first
+///
+/// Column changes affect every row, so they are kept together as the first
+/// group regardless of score. Rows with equal scores stay in diff order.
+pub fn rank_by_severity(
+    changes: impl IntoIterator<Item = Change>,
+    weights: &SeverityWeights,
+) -> Vec<RowSeverity> {
+    let mut columns = Vec::new();
+    let mut rows: Vec<RowSeverity> = Vec::new();
+    let mut row_index: HashMap<(bool, usize), usize> = HashMap::new();
+    for change in changes {
+        let Some(position) = change.row_position() else {
+            columns.push(change);
+            continue;
+        };
+        let score = weights.score(&change);
+        let idx = *row_index.entry(position).or_insert_with(|| {
+            rows.push(RowSeverity {
+                score: 0.0,
+                changes: Vec::new(),
+            });
+            rows.len() - 1
+        });
+        rows[idx].score += score;
+        rows[idx].changes.push(change);
+    }
+
+    rows.sort_by(|a, b| b.score.total_cmp(&a.score));
+    if !columns.is_empty() {
+        rows.insert(
+            0,
+            RowSeverity {
+                score: 0.0,
+                changes: columns,
+            },
+        );
+    }
+    rows
+}
@@ -0,0 +1,167 @@
+// git-sheets: Numeric stats - the aggregate effect of a change set
+
+use super::SnapshotDiff;
+use crate::core::{ColumnType, Snapshot, Table, parse_number};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Aggregates of one numeric column in one snapshot
+///
+/// Empty cells are skipped; `mean`, `min` and `max` are `None` when the
+/// column has no values.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NumericStats {
+    /// Number of non-empty cells
+    pub count: usize,
+    /// Sum of the values
+    pub sum: f64,
+    /// Mean of the values
+    pub mean: Option<f64>,
+    /// Smallest value
+    pub min: Option<f64>,
+    /// Largest value
+    pub max: Option<f64>,
+}
+
+impl NumericStats {
+    fn of(values: &[f64]) -> Self {
+        let sum = values.iter().sum();
+        Self {
+            count: values.len(),
+            sum,
+            mean: (!values.is_empty()).then(|| sum / values.len() as f64),
+            min: values.iter().copied().reduce(f64::min),
+            max: values.iter().copied().reduce(f64::max),
+        }
+    }
+}
+
+/// How a numeric column's aggregates moved between two snapshots
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnNumericDelta {
+    /// Column name
+    pub column: String,
+    /// Aggregates in the older snapshot
+    pub from: NumericStats,
+    /// Aggregates in the newer snapshot
+    pub to: NumericStats,
+}
+
+impl ColumnNumericDelta {
+    /// Change in the column total
+    pub fn sum_delta(&self) -> f64 {
+        self.to.sum - self.from.sum
+    }
+
+    /// Change in the mean, if both snapshots have values
+    pub fn mean_delta(&self) -> Option<f64> {
+        Some(self.to.mean? - self.from.mean?)
+    }
+
+    /// Change in the smallest value, if both snapshots have values
+    pub fn min_delta(&self) -> Option<f64> {
+        Some(self.to.min? - self.from.min?)
+    }
+
+    /// Change in the largest value, if both snapshots have values
+    pub fn max_delta(&self) -> Option<f64> {
+        Some(self.to.max? - self.from.max?)
+    }
+
+    /// Whether any aggregate differs
+    pub fn is_changed(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+/// Format a change with its sign, to at most six decimals
+fn signed(delta: f64) -> String {
+    let text = format!("{delta:+.6}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" || text == "+0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+fn optional(delta: Option<f64>) -> String {
+    delta.map_or_else(|| "n/a".to_string(), signed)
+}
+
+impl fmt::Display for ColumnNumericDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: sum {}, mean {}, min {}, max {}",
+            self.column,
+            signed(self.sum_delta()),
+            optional(self.mean_delta()),
+            optional(self.min_delta()),
+            optional(self.max_delta())
+        )
+    }
+}
+
+/// Values of a column if every non-empty cell is a number and there is at
+/// least one, otherwise `None`
+fn numeric_values(table: &Table, col: usize) -> Option<Vec<f64>> {
+    let mut values = Vec::new();
+    for cell in table.rows.iter().filter_map(|row| row.get(col)) {
+        if cell.trim().is_empty() {
+            continue;
+        }
+        values.push(parse_number(cell)?);
+    }
+    Some(values)
+}
+
+impl SnapshotDiff {
+    /// Sum, mean, min and max of every numeric column in both snapshots
+    ///
+    /// Columns are matched by name, in `to` order. A column counts as numeric
+    /// when all of its non-empty cells parse as numbers in both snapshots
+    /// (`$` and thousands separators allowed) and it has a value in at least
+    /// one, unless a snapshot declares it some other type. Primary key
+    /// columns are left out: their totals mean nothing.
+    pub fn numeric_summary(from: &Snapshot, to: &Snapshot) -> Vec<ColumnNumericDelta> {
+        let is_key = |table: &Table, col: usize| {
+            table
+                .primary_key
+                .as_ref()
+                .is_some_and(|key| key.contains(&col))
+        };
+
+        let mut deltas = Vec::new();
+        for (to_col, column) in to.table.headers.iter().enumerate() {
+            let Some(from_col) = from.table.headers.iter().position(|h| h == column) else {
+                continue;
+            };
+            if is_key(&from.table, from_col) || is_key(&to.table, to_col) {
+                continue;
+            }
+            let declared = to
+                .column_types
+                .get(column)
+                .or(from.column_types.get(column));
+            if declared.is_some_and(|ty| !matches!(ty, ColumnType::Integer | ColumnType::Float)) {
+                continue;
+            }
+            let (Some(old), Some(new)) = (
+                numeric_values(&from.table, from_col),
+                numeric_values(&to.table, to_col),
+            ) else {
+                continue;
+            };
+            if old.is_empty() && new.is_empty() {
+                continue;
+            }
+            deltas.push(ColumnNumericDelta {
+                column: column.clone(),
+                from: NumericStats::of(&old),
+                to: NumericStats::of(&new),
+            });
+        }
+        deltas
+    }
+}
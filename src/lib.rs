@@ -1,15 +1,26 @@
 // git-sheets: Version control for spreadsheets
 // A tool for Excel sufferers who deserve better
 
+#[cfg(feature = "native")]
 pub mod cli;
 pub mod core;
 pub mod diff;
 
 // Re-export core types for convenience
-pub use core::{Dependency, GitSheetsError, Result, Snapshot, Table, TableHashes};
+pub use core::{
+    ColumnType, Config, Dependency, GitSheetsError, InMemoryStorage, LineEnding, LoadOptions,
+    QuoteStyle, Result, Snapshot, SnapshotStorage, Table, TableHashes, WriteOptions,
+};
+
+#[cfg(feature = "native")]
+pub use core::{FsStorage, SnapshotStore};
 
 // Re-export diff types
-pub use diff::{Change, DiffSummary, SnapshotDiff};
+pub use diff::{
+    CellChangeKind, Change, ChangeIter, ColumnValueChange, DiffOptions, DiffSummary, KeyChanges,
+    RowMatching, RowRef, SnapshotDiff,
+};
 
 // Re-export CLI module
+#[cfg(feature = "native")]
 pub use cli::Cli;
@@ -1,7 +1,62 @@
 use gitsheets::{
-    core::{Snapshot, Table},
-    diff::SnapshotDiff,
+    FsStorage, GitSheetsError, InMemoryStorage, LineEnding, LoadOptions, QuoteStyle,
+    SnapshotStorage, WriteOptions,
+    core::{
+        ColumnType, RepoLock, Schema, Side, Snapshot, SnapshotStore, Table, TableHashes, Validator,
+        find_repo_root, hash_row, logical_hash,
+        merkle::verify_merkle_proof,
+        read_at_revision, read_committed, relative_to_repo_root,
+        validate::{NonEmptyValidator, OneOfValidator, RangeValidator, RegexValidator},
+    },
+    diff::{
+        CellChangeKind, Change, DiffOptions, DiffSummary, RowMatching, SeverityWeights,
+        SnapshotDiff, rank_by_severity,
+    },
 };
+use sha2::Digest;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Run the git-sheets binary in `dir` without git, failing the test if it
+/// fails, and return what it printed
+fn git_sheets(dir: &Path, args: &[&str]) -> String {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_git-sheets"))
+        .arg("--no-git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git-sheets {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Run the git-sheets binary like `git_sheets`, expecting it to fail, and
+/// return its stderr
+fn git_sheets_fails(dir: &Path, args: &[&str]) -> String {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_git-sheets"))
+        .arg("--no-git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "git-sheets {args:?} succeeded");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/// Snapshot files of a repository made with `git_sheets`, in name order
+fn snapshot_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir.join("snapshots"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    files.sort();
+    files
+}
 
 #[test]
 fn test_snapshot_creation() {
@@ -71,3 +126,2880 @@ fn test_diff_computation() {
     assert_eq!(diff.from_id, snapshot1.id);
     assert_eq!(diff.to_id, snapshot2.id);
 }
+
+#[test]
+fn test_reorder_columns() {
+    let table = Table {
+        headers: vec!["ID".to_string(), "Name".to_string(), "Amount".to_string()],
+        rows: vec![
+            vec!["1".to_string(), "Alice".to_string(), "100".to_string()],
+            vec!["2".to_string(), "Bob".to_string(), "200".to_string()],
+        ],
+        primary_key: Some(vec![0]),
+    };
+
+    let order = vec!["Amount".to_string(), "ID".to_string(), "Name".to_string()];
+    let reordered = table.reorder_columns(&order).unwrap();
+
+    // Row data follows its column
+    assert_eq!(reordered.headers, order);
+    assert_eq!(reordered.rows[0], vec!["100", "1", "Alice"]);
+    assert_eq!(reordered.rows[1], vec!["200", "2", "Bob"]);
+
+    // Primary key index is remapped to the new position of "ID"
+    assert_eq!(reordered.primary_key, Some(vec![1]));
+    assert_eq!(reordered.get_row_key(1).unwrap(), vec!["2"]);
+
+    // Unknown and missing headers are rejected
+    let unknown = vec!["Amount".to_string(), "ID".to_string(), "Email".to_string()];
+    assert!(table.reorder_columns(&unknown).is_err());
+    let missing = vec!["Amount".to_string(), "ID".to_string()];
+    assert!(table.reorder_columns(&missing).is_err());
+}
+
+#[test]
+fn test_csv_error_reports_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("broken.csv");
+    std::fs::write(&path, "ID,Name\n1,Alice\n2,Bob,extra\n3,Carol\n").unwrap();
+
+    match Table::from_csv(&path) {
+        Err(GitSheetsError::Csv { line, snippet, .. }) => {
+            assert_eq!(line, 3);
+            assert_eq!(snippet, "2,Bob,extra");
+        }
+        other => panic!("expected a line-numbered CSV error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_snapshot_id_disambiguation() {
+    let table = Table {
+        headers: vec!["ID".to_string()],
+        rows: vec![vec!["1".to_string()]],
+        primary_key: None,
+    };
+
+    let mut snapshot = Snapshot::with_id_length(table, None, 8);
+    let hash = snapshot.hashes.table_hash.clone();
+    let stamp = snapshot.timestamp.timestamp();
+    assert_eq!(snapshot.id, format!("{stamp}-{}", &hash[..8]));
+
+    // Another table already took both the 8- and 12-char forms of this id
+    let existing = vec![
+        format!("{stamp}-{}", &hash[..8]),
+        format!("{stamp}-{}", &hash[..12]),
+    ];
+    snapshot.disambiguate_id(&existing);
+    assert_eq!(snapshot.id, format!("{stamp}-{}", &hash[..16]));
+
+    // No collision leaves the id alone
+    snapshot.disambiguate_id(&[]);
+    assert_eq!(snapshot.id, format!("{stamp}-{}", &hash[..16]));
+}
+
+#[test]
+fn test_identical_snapshots_in_one_second_get_distinct_ids() {
+    let table = Table {
+        headers: vec!["ID".to_string()],
+        rows: vec![vec!["1".to_string()]],
+        primary_key: None,
+    };
+    let first = Snapshot::new(table, Some("first".to_string()));
+    let mut storage = InMemoryStorage::new();
+    storage.put(&first.id, &first.to_toml().unwrap()).unwrap();
+
+    // The same table taken again within the same second, until even the
+    // full table hash is taken
+    let mut ids = vec![first.id.clone()];
+    for n in 2..=16 {
+        let mut again = first.clone();
+        again.message = Some(format!("copy {n}"));
+        again.disambiguate_id(&storage.list().unwrap());
+        assert!(!ids.contains(&again.id));
+        storage.put(&again.id, &again.to_toml().unwrap()).unwrap();
+        ids.push(again.id);
+    }
+    let full = format!(
+        "{}-{}",
+        first.timestamp.timestamp(),
+        first.hashes.table_hash
+    );
+    assert_eq!(ids[13], full);
+    assert_eq!(ids[14], format!("{full}-2"));
+    assert_eq!(ids[15], format!("{full}-3"));
+
+    ids.sort();
+    assert_eq!(storage.list().unwrap(), ids);
+    let stored = storage.get(&first.id).unwrap().unwrap();
+    assert_eq!(
+        Snapshot::from_bytes(&stored).unwrap().message.as_deref(),
+        Some("first")
+    );
+}
+
+#[test]
+fn test_short_hashes_never_panic_or_hide_disambiguation() {
+    let table = Table {
+        headers: vec!["ID".to_string()],
+        rows: vec![vec!["1".to_string()]],
+        primary_key: None,
+    };
+    let mut snapshot = Snapshot::with_id_length(table, None, 8);
+    let hash = snapshot.hashes.table_hash.clone();
+    let stamp = snapshot.timestamp.timestamp();
+
+    assert_eq!(snapshot.hashes.short(6), &hash[..6]);
+    assert_eq!(snapshot.hashes.short(0), &hash[..1]);
+    assert_eq!(snapshot.hashes.short(1000), hash);
+
+    // Longer display lengths show more of the hash; shorter ones never cut
+    // below the stored id
+    assert_eq!(snapshot.short_id(20), format!("{stamp}-{}", &hash[..20]));
+    assert_eq!(snapshot.short_id(4), snapshot.id);
+
+    snapshot.id = "HEAD:sales.csv".to_string();
+    assert_eq!(snapshot.short_id(20), "HEAD:sales.csv");
+}
+
+#[test]
+fn test_atomic_save_never_clobbers_existing_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snap.toml");
+
+    let table = Table {
+        headers: vec!["ID".to_string()],
+        rows: vec![vec!["1".to_string()]],
+        primary_key: None,
+    };
+    let good = Snapshot::new(table.clone(), Some("good".to_string()));
+    good.save(&path).unwrap();
+    let good_bytes = std::fs::read(&path).unwrap();
+
+    // Block the temp file so the next write fails before the rename
+    let tmp_path = dir.path().join(".snap.toml.tmp");
+    std::fs::create_dir(&tmp_path).unwrap();
+    let other = Snapshot::new(table.clone(), Some("other".to_string()));
+    assert!(other.save(&path).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), good_bytes);
+
+    // A successful save replaces the file and leaves no temp file behind
+    std::fs::remove_dir(&tmp_path).unwrap();
+    other.save(&path).unwrap();
+    assert_eq!(
+        Snapshot::load(&path).unwrap().message.as_deref(),
+        Some("other")
+    );
+    assert!(!tmp_path.exists());
+}
+
+#[test]
+fn test_hash_has_no_field_boundary_collisions() {
+    let table = |headers: &[&str], rows: &[&[&str]]| Table {
+        headers: headers.iter().map(|s| s.to_string()).collect(),
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect(),
+        primary_key: None,
+    };
+
+    // Cell boundaries
+    let a = table(&["X", "Y"], &[&["ab", "c"]]);
+    let b = table(&["X", "Y"], &[&["a", "bc"]]);
+    assert_ne!(
+        TableHashes::compute(&a).table_hash,
+        TableHashes::compute(&b).table_hash
+    );
+
+    // Header/data boundary
+    let c = table(&["ab"], &[&["c"]]);
+    let d = table(&["a"], &[&["bc"]]);
+    assert_ne!(
+        TableHashes::compute(&c).table_hash,
+        TableHashes::compute(&d).table_hash
+    );
+
+    // Row boundaries
+    let e = table(&["X"], &[&["a", "b"], &["c"]]);
+    let f = table(&["X"], &[&["a"], &["b", "c"]]);
+    assert_ne!(
+        TableHashes::compute(&e).table_hash,
+        TableHashes::compute(&f).table_hash
+    );
+}
+
+#[test]
+fn test_rename_threshold_detects_fuzzy_column_rename() {
+    let table = |value_header: &str, last: &str| Table {
+        headers: vec!["ID".to_string(), value_header.to_string()],
+        rows: ["1", "2", "3", "4", "5"]
+            .iter()
+            .map(|id| {
+                let value = if *id == "5" { last } else { id };
+                vec![id.to_string(), value.to_string()]
+            })
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(table("Qty", "5"), None);
+    let to = Snapshot::new(table("Quantity", "50"), None);
+
+    // 4 of 5 values match: a rename at 0.8, separate add/remove at 0.9
+    let opts = |threshold| DiffOptions {
+        rename_threshold: Some(threshold),
+        ..DiffOptions::default()
+    };
+    let diff = SnapshotDiff::compute_with(&from, &to, &opts(0.8)).unwrap();
+    assert_eq!(diff.summary.columns_renamed, 1);
+    assert_eq!(diff.summary.columns_added, 0);
+    assert!(diff.changes.iter().any(|c| matches!(
+        c,
+        Change::ColumnRenamed { from, to, .. } if from == "Qty" && to == "Quantity"
+    )));
+
+    let diff = SnapshotDiff::compute_with(&from, &to, &opts(0.9)).unwrap();
+    assert_eq!(diff.summary.columns_renamed, 0);
+    assert_eq!(diff.summary.columns_added, 1);
+    assert_eq!(diff.summary.columns_removed, 1);
+}
+
+#[test]
+fn test_normalize_dates_maps_formats_to_iso() {
+    let csv = "ID,Due,Note\n1,1/2/2024,a\n2,2024-01-02,b\n3,02-Jan-2024,c\n4,2024/01/02,d\n";
+    let options = LoadOptions {
+        normalize_dates: true,
+        ..LoadOptions::default()
+    };
+    let (table, notes) = Table::from_csv_bytes(csv.as_bytes().to_vec(), &options).unwrap();
+
+    assert!(table.rows.iter().all(|row| row[1] == "2024-01-02"));
+    assert_eq!(
+        notes,
+        vec!["normalized dates in column 'Due' to ISO-8601".to_string()]
+    );
+
+    // Display format no longer affects the hash
+    let iso = "ID,Due,Note\n1,2024-01-02,a\n2,2024-01-02,b\n3,2024-01-02,c\n4,2024-01-02,d\n";
+    let (expected, _) = Table::from_csv_bytes(iso.as_bytes().to_vec(), &options).unwrap();
+    assert_eq!(
+        TableHashes::compute(&table).table_hash,
+        TableHashes::compute(&expected).table_hash
+    );
+
+    // Without the option, cells are left as written
+    let (raw, _) = Table::from_csv_bytes(csv.as_bytes().to_vec(), &LoadOptions::default()).unwrap();
+    assert_eq!(raw.rows[0][1], "1/2/2024");
+}
+
+#[test]
+fn test_apply_replays_added_and_removed_columns() {
+    let table = |headers: &[&str], rows: &[&[&str]]| Table {
+        headers: headers.iter().map(|s| s.to_string()).collect(),
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let narrow = Snapshot::new(
+        table(
+            &["ID", "Region", "Qty"],
+            &[
+                &["1", "North", "10"],
+                &["2", "South", "20"],
+                &["3", "East", "30"],
+            ],
+        ),
+        None,
+    );
+    let wide = Snapshot::new(
+        table(
+            &["ID", "Qty", "Owner", "Note"],
+            &[
+                &["1", "10", "ann", ""],
+                &["2", "25", "bob", "late"],
+                &["4", "40", "", "new"],
+            ],
+        ),
+        None,
+    );
+
+    // Growing and shrinking the row width both replay exactly
+    for (from, to) in [(&narrow, &wide), (&wide, &narrow)] {
+        let diff = SnapshotDiff::compute(from, to).unwrap();
+        assert_eq!(diff.apply(&from.table).unwrap(), to.table);
+    }
+}
+
+#[test]
+fn test_delta_snapshot_materializes_full_table() {
+    let table = |rows: &[[&str; 2]]| Table {
+        headers: vec!["ID".to_string(), "Qty".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let base = Snapshot::new(table(&[["1", "10"], ["2", "20"], ["3", "30"]]), None);
+    let second = table(&[["1", "10"], ["2", "25"], ["4", "40"]]);
+    let third = table(&[["1", "11"], ["2", "25"], ["4", "40"]]);
+
+    let delta1 = Snapshot::new_delta(&base, &second).unwrap();
+    let mut full1 = delta1.clone();
+    full1.table = second.clone();
+    let delta2 = Snapshot::new_delta(&full1, &third).unwrap();
+    assert!(delta2.table.rows.is_empty());
+    assert_eq!(delta2.parent.as_deref(), Some(delta1.id.as_str()));
+
+    let store = [base.clone(), delta1.clone()];
+    let resolver = |id: &str| {
+        store
+            .iter()
+            .find(|s| s.id == id)
+            .cloned()
+            .ok_or_else(|| GitSheetsError::FileSystemError(format!("missing {id}")))
+    };
+
+    let rebuilt = delta2.materialize(&resolver).unwrap();
+    assert_eq!(rebuilt.rows, third.rows);
+
+    // Unmaterialized deltas don't verify; materialized ones do
+    assert!(!delta2.verify());
+    let mut materialized = delta2.clone();
+    materialized.table = rebuilt;
+    assert!(materialized.verify());
+
+    // A tampered delta is caught against the recorded hash
+    let mut tampered = delta2.clone();
+    if let Some(Change::CellChanged { new, .. }) =
+        tampered.delta.as_mut().and_then(|d| d.changes.first_mut())
+    {
+        *new = "99".to_string();
+    }
+    assert!(matches!(
+        tampered.materialize(&resolver),
+        Err(GitSheetsError::InvalidDelta(_))
+    ));
+}
+
+#[test]
+fn test_mismatched_primary_keys_fall_back_to_position() {
+    let table = |primary_key: Vec<usize>| Table {
+        headers: vec!["ID".to_string(), "Region".to_string(), "Amount".to_string()],
+        rows: vec![
+            vec!["1".to_string(), "EU".to_string(), "100".to_string()],
+            vec!["2".to_string(), "US".to_string(), "200".to_string()],
+        ],
+        primary_key: Some(primary_key),
+    };
+    let from = Snapshot::new(table(vec![0]), None);
+    let mut to_table = table(vec![0, 1]);
+    to_table.rows[1][2] = "250".to_string();
+    let to = Snapshot::new(to_table, None);
+
+    // Differing keys: pair rows by position rather than reporting every row
+    // as removed and re-added
+    let opts = DiffOptions::default();
+    assert_eq!(
+        RowMatching::resolve(&from.table, &to.table, &opts).unwrap(),
+        RowMatching::Position
+    );
+    let diff = SnapshotDiff::compute(&from, &to).unwrap();
+    assert_eq!(diff.summary.rows_added, 0);
+    assert_eq!(diff.summary.rows_removed, 0);
+    assert_eq!(diff.changes.len(), 1);
+    assert!(matches!(
+        &diff.changes[0],
+        Change::CellChanged {
+            row: 1,
+            col: 2,
+            key: None,
+            ..
+        }
+    ));
+
+    // A forced key takes precedence over both tables' own keys
+    let forced = DiffOptions {
+        force_key: Some(vec!["ID".to_string()]),
+        ..DiffOptions::default()
+    };
+    let diff = SnapshotDiff::compute_with(&from, &to, &forced).unwrap();
+    assert!(matches!(
+        &diff.changes[..],
+        [Change::CellChanged { key: Some(key), .. }] if key == &["2".to_string()]
+    ));
+
+    // Forcing a column one table lacks is an error, not a guess
+    let missing = DiffOptions {
+        force_key: Some(vec!["SKU".to_string()]),
+        ..DiffOptions::default()
+    };
+    assert!(matches!(
+        SnapshotDiff::compute_with(&from, &to, &missing),
+        Err(GitSheetsError::InvalidColumn(_))
+    ));
+}
+
+#[test]
+fn test_quick_dimensions_respects_quoted_newlines() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.csv");
+    std::fs::write(&path, "ID,Note\n1,\"two\nlines\"\n2,plain\n").unwrap();
+
+    let table = Table::from_csv(&path).unwrap();
+    assert_eq!(
+        Table::quick_dimensions(&path).unwrap(),
+        (table.headers.len(), table.rows.len())
+    );
+    assert_eq!(Table::quick_dimensions(&path).unwrap(), (2, 2));
+}
+
+#[test]
+fn test_snapshot_round_trips_through_toml_and_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let table = Table {
+        headers: vec!["ID".to_string(), "Name".to_string()],
+        rows: vec![
+            vec!["1".to_string(), "Alice".to_string()],
+            vec!["2".to_string(), "Bob, \"Jr\"".to_string()],
+        ],
+        primary_key: Some(vec![0]),
+    };
+    let mut snapshot = Snapshot::new(table, Some("formats".to_string()));
+    snapshot.source = Some("people.csv".to_string());
+    snapshot.author = Some("Jane Doe <jane@example.com>".to_string());
+    snapshot.seal().unwrap();
+
+    let toml_path = dir.path().join("snap.toml");
+    let json_path = dir.path().join("snap.json");
+    snapshot.save(&toml_path).unwrap();
+    snapshot.save_json(&json_path).unwrap();
+    assert!(
+        std::fs::read_to_string(&json_path)
+            .unwrap()
+            .starts_with('{')
+    );
+
+    let from_toml = Snapshot::load(&toml_path).unwrap();
+    let from_json = Snapshot::load(&json_path).unwrap();
+    let as_value = |s: &Snapshot| serde_json::to_value(s).unwrap();
+    assert_eq!(as_value(&from_toml), as_value(&snapshot));
+    assert_eq!(as_value(&from_json), as_value(&snapshot));
+    assert!(from_json.verify());
+    assert_eq!(
+        from_toml.author.as_deref(),
+        Some("Jane Doe <jane@example.com>")
+    );
+}
+
+#[test]
+fn test_validate_reports_each_failing_cell() {
+    let table = Table {
+        headers: vec![
+            "ID".to_string(),
+            "Email".to_string(),
+            "Amount".to_string(),
+            "Status".to_string(),
+        ],
+        rows: vec![
+            vec![
+                "1".to_string(),
+                "alice@example.com".to_string(),
+                "100".to_string(),
+                "open".to_string(),
+            ],
+            vec![
+                "".to_string(),
+                "bob".to_string(),
+                "-5".to_string(),
+                "lost".to_string(),
+            ],
+            vec![
+                "3".to_string(),
+                "".to_string(),
+                "n/a".to_string(),
+                "".to_string(),
+            ],
+        ],
+        primary_key: Some(vec![0]),
+    };
+    let validators: Vec<(usize, Box<dyn Validator>)> = vec![
+        (0, Box::new(NonEmptyValidator)),
+        (1, Box::new(RegexValidator::new(r"^[^@]+@[^@]+$").unwrap())),
+        (
+            2,
+            Box::new(RangeValidator {
+                min: Some(0.0),
+                max: None,
+            }),
+        ),
+        (
+            3,
+            Box::new(OneOfValidator(vec![
+                "open".to_string(),
+                "closed".to_string(),
+            ])),
+        ),
+    ];
+
+    let errors = table.validate(&validators);
+    let failing: Vec<(usize, &str)> = errors.iter().map(|e| (e.row, e.column.as_str())).collect();
+    assert_eq!(
+        failing,
+        vec![
+            (1, "ID"),
+            (1, "Email"),
+            (1, "Amount"),
+            (1, "Status"),
+            (2, "Amount")
+        ]
+    );
+    assert_eq!(errors[2].message, "is less than 0");
+    assert_eq!(errors[4].message, "is not a number");
+
+    assert!(matches!(
+        RegexValidator::new("("),
+        Err(GitSheetsError::InvalidRule(_))
+    ));
+}
+
+#[test]
+fn test_schema_types_are_checked_and_drive_the_diff() {
+    let schema: Schema = toml::from_str(
+        r#"
+        [columns]
+        Zip = "code"
+        Amount = "float"
+        Due = "date"
+        "#,
+    )
+    .unwrap();
+    let table = |rows: &[[&str; 4]]| Table {
+        headers: vec![
+            "ID".to_string(),
+            "Zip".to_string(),
+            "Amount".to_string(),
+            "Due".to_string(),
+        ],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+
+    let from_table = table(&[
+        ["1", "02139", "1000", "2024-01-02"],
+        ["2", "10001", "5", ""],
+    ]);
+    assert!(from_table.apply_schema(&schema).unwrap().is_empty());
+    let bad = table(&[["1", "02139", "lots", "soon"]]);
+    let errors = bad.apply_schema(&schema).unwrap();
+    let failing: Vec<&str> = errors.iter().map(|e| e.column.as_str()).collect();
+    assert_eq!(failing, vec!["Amount", "Due"]);
+    assert_eq!(errors[0].message, "is not a valid float value");
+
+    let mut missing = schema.clone();
+    missing
+        .columns
+        .insert("Region".to_string(), ColumnType::Text);
+    assert!(matches!(
+        from_table.apply_schema(&missing),
+        Err(GitSheetsError::InvalidColumn(_))
+    ));
+
+    // Reformatted numbers and dates are the same values; a code is text
+    let mut from = Snapshot::new(from_table, None);
+    let mut to = Snapshot::new(
+        table(&[["1", "02139", "1,000", "1/2/2024"], ["2", "10002", "5", ""]]),
+        None,
+    );
+    from.column_types = schema.columns.clone();
+    to.column_types = schema.columns.clone();
+    let diff = SnapshotDiff::compute(&from, &to).unwrap();
+    assert!(matches!(
+        &diff.changes[..],
+        [Change::CellChanged {
+            col: 1,
+            kind: CellChangeKind::MinorEdit,
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_pair_rows_aligns_by_key_in_to_order() {
+    let table = |ids: &[&str]| Table {
+        headers: vec!["ID".to_string()],
+        rows: ids.iter().map(|id| vec![id.to_string()]).collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = table(&["1", "2", "3"]);
+    let to = table(&["1", "4", "3"]);
+
+    let matching = RowMatching::resolve(&from, &to, &DiffOptions::default()).unwrap();
+    assert_eq!(
+        matching.pair_rows(&from, &to),
+        vec![
+            (Some(0), Some(0)),
+            (None, Some(1)),
+            (Some(1), None),
+            (Some(2), Some(2)),
+        ]
+    );
+
+    let unkeyed = |t: Table| Table {
+        primary_key: None,
+        ..t
+    };
+    let (from, to) = (unkeyed(from), unkeyed(table(&["1", "2"])));
+    let matching = RowMatching::resolve(&from, &to, &DiffOptions::default()).unwrap();
+    assert_eq!(
+        matching.pair_rows(&from, &to),
+        vec![(Some(0), Some(0)), (Some(1), Some(1)), (Some(2), None)]
+    );
+}
+
+#[test]
+fn test_csv_round_trip_preserves_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let table = Table {
+        headers: vec!["ID".to_string(), "Note".to_string()],
+        rows: vec![
+            vec!["1".to_string(), "plain".to_string()],
+            vec!["2".to_string(), "comma, \"quote\"\nand newline".to_string()],
+        ],
+        primary_key: None,
+    };
+
+    let path = dir.path().join("restored.csv");
+    table.write_csv(&path).unwrap();
+    let restored = Table::from_csv(&path).unwrap();
+    assert_eq!(restored, table);
+}
+
+#[test]
+fn test_whitespace_only_cell_changes_are_flagged() {
+    let options = LoadOptions {
+        preserve_whitespace: true,
+        ..LoadOptions::default()
+    };
+    let load = |csv: &str| {
+        let (mut table, _) = Table::from_csv_bytes(csv.as_bytes().to_vec(), &options).unwrap();
+        table.set_primary_key(vec![0]);
+        Snapshot::new(table, None)
+    };
+    let from = load("ID,Name,City\n1,Alice,Paris\n");
+    let to = load("ID,Name,City\n1,Alice ,Lyon\n");
+
+    let diff = SnapshotDiff::compute(&from, &to).unwrap();
+    let kinds: Vec<(usize, CellChangeKind)> = diff
+        .changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::CellChanged { col, kind, .. } => Some((*col, *kind)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (1, CellChangeKind::WhitespaceOnly),
+            (2, CellChangeKind::MajorEdit)
+        ]
+    );
+
+    // The kind survives a JSON round trip, and older diffs without it load as value changes
+    let json = serde_json::to_string(&diff).unwrap();
+    assert!(json.contains("WhitespaceOnly"));
+    let legacy: Change =
+        serde_json::from_str(r#"{"type":"CellChanged","row":0,"col":1,"old":"a","new":"b"}"#)
+            .unwrap();
+    assert!(!legacy.is_whitespace_only());
+}
+
+#[test]
+fn test_text_edits_are_classified_by_edit_distance() {
+    let classify = CellChangeKind::classify;
+    assert_eq!(classify("Jon", "John"), CellChangeKind::MinorEdit);
+    assert_eq!(classify("Recieved", "Received"), CellChangeKind::MinorEdit);
+    assert_eq!(
+        classify("Jon", "Administrative Services"),
+        CellChangeKind::MajorEdit
+    );
+    assert_eq!(classify("Paris", "Lyon"), CellChangeKind::MajorEdit);
+    // Numbers and empty cells aren't text edits
+    assert_eq!(classify("100", "101"), CellChangeKind::Value);
+    assert_eq!(classify("", "John"), CellChangeKind::Value);
+
+    let json = serde_json::to_string(&Change::CellChanged {
+        row: 0,
+        col: 1,
+        old: "Jon".to_string(),
+        new: "John".to_string(),
+        key: None,
+        kind: CellChangeKind::MinorEdit,
+        new_index: None,
+    })
+    .unwrap();
+    assert!(json.contains("MinorEdit"));
+}
+
+#[test]
+fn test_normalized_csv_hashes_like_its_source() {
+    let raw = "\u{feff}ID,Due,Note\r\n1, 1/2/2024 ,\"a\r\nb\"\r\n2,2024-01-03,c\r\n";
+    let options = LoadOptions {
+        normalize_dates: true,
+        ..LoadOptions::default()
+    };
+    let (table, notes) = Table::from_csv_bytes(raw.as_bytes().to_vec(), &options).unwrap();
+    assert_eq!(notes.len(), 3);
+
+    let canonical = table.to_csv_bytes().unwrap();
+    let (reloaded, notes) = Table::from_csv_bytes(canonical.clone(), &options).unwrap();
+    assert!(notes.is_empty());
+    assert_eq!(
+        TableHashes::compute(&reloaded).table_hash,
+        TableHashes::compute(&table).table_hash
+    );
+    assert_eq!(reloaded.to_csv_bytes().unwrap(), canonical);
+}
+
+#[test]
+fn test_invalid_utf8_fails_with_its_position_or_is_replaced() {
+    let raw = b"ID,Name\n1,Alice\n2,Bj\xF6rn\n3,Caf\xE9 \xE9\n".to_vec();
+
+    let err = Table::from_csv_bytes(raw.clone(), &LoadOptions::default()).unwrap_err();
+    assert!(matches!(&err, GitSheetsError::InvalidEncoding(_)));
+    let message = err.to_string();
+    assert!(
+        message.contains("byte offset 20 (line 3, byte 5 of the line)"),
+        "{message}"
+    );
+
+    let options = LoadOptions {
+        lossy_utf8: true,
+        ..LoadOptions::default()
+    };
+    let (table, notes) = Table::from_csv_bytes(raw, &options).unwrap();
+    assert_eq!(table.rows[1][1], "Bj\u{FFFD}rn");
+    assert_eq!(table.rows[2][1], "Caf\u{FFFD} \u{FFFD}");
+    assert_eq!(
+        notes,
+        vec!["replaced invalid UTF-8 in 2 cell(s) with U+FFFD".to_string()]
+    );
+}
+
+#[test]
+fn test_envelope_checksum_catches_metadata_corruption() {
+    let dir = tempfile::tempdir().unwrap();
+    let table = Table {
+        headers: vec!["ID".to_string()],
+        rows: vec![vec!["1".to_string()]],
+        primary_key: None,
+    };
+    let snapshot = Snapshot::new(table, Some("Quarter close".to_string()));
+
+    for name in ["snap.toml", "snap.json"] {
+        let path = dir.path().join(name);
+        snapshot.save(&path).unwrap();
+        assert!(Snapshot::load(&path).unwrap().checksum.is_some());
+
+        // The table still verifies, but the message was tampered with
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, contents.replace("Quarter close", "Quarter closed")).unwrap();
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(GitSheetsError::ChecksumMismatch(_))
+        ));
+    }
+}
+
+#[test]
+fn test_fixed_width_slices_and_pads_lines() {
+    let report = "ACCT      NAME           BALANCE\r\n\
+                  0001      Alice Smith     120.50\r\n\
+                  \r\n\
+                  0002      Bob\r\n";
+    let table = Table::from_fixed_width_str(report, &[10, 15, 7], true).unwrap();
+    assert_eq!(table.headers, vec!["ACCT", "NAME", "BALANCE"]);
+    assert_eq!(
+        table.rows,
+        vec![
+            vec!["0001", "Alice Smith", "120.50"],
+            vec!["0002", "Bob", ""]
+        ]
+    );
+
+    let headless = Table::from_fixed_width_str("0001      Alice", &[10, 5], false).unwrap();
+    assert_eq!(headless.headers, vec!["column_1", "column_2"]);
+    assert_eq!(headless.rows, vec![vec!["0001", "Alice"]]);
+}
+
+#[test]
+fn test_rank_by_severity_puts_worst_rows_first() {
+    let table = |rows: &[[&str; 3]]| Table {
+        headers: vec!["ID".to_string(), "Name".to_string(), "Amount".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(
+        table(&[
+            ["1", "Alice", "100"],
+            ["2", "Bob", "200"],
+            ["3", "Carol", "n/a"],
+            ["4", "Dan", "400"],
+        ]),
+        None,
+    );
+    let to = Snapshot::new(
+        table(&[
+            ["1", "Alicia", "100"],
+            ["2", "Bob", "250"],
+            ["3", "Carol", "300"],
+        ]),
+        None,
+    );
+    let changes = SnapshotDiff::compute(&from, &to).unwrap().changes;
+
+    let keys = |weights: &SeverityWeights| -> Vec<String> {
+        rank_by_severity(changes.clone(), weights)
+            .iter()
+            .map(|row| match &row.changes[0] {
+                Change::RowRemoved { key, .. } | Change::CellChanged { key, .. } => {
+                    key.clone().unwrap().join("|")
+                }
+                other => panic!("unexpected change {other:?}"),
+            })
+            .collect()
+    };
+    // Removed row, then type change, then numeric edit, then text edit
+    assert_eq!(keys(&SeverityWeights::default()), vec!["4", "3", "2", "1"]);
+
+    // Weights come from the [severity] table of the config, defaulting the rest
+    let config: gitsheets::Config = toml::from_str("[severity]\ntext = 50\n").unwrap();
+    assert_eq!(config.severity.row_removed, 10.0);
+    assert_eq!(keys(&config.severity), vec!["1", "4", "3", "2"]);
+}
+
+#[test]
+fn test_key_changes_lists_membership_only() {
+    let table = |rows: &[[&str; 2]], key: Option<Vec<usize>>| Table {
+        headers: vec!["ID".to_string(), "Name".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: key,
+    };
+    let from = Snapshot::new(
+        table(
+            &[["1", "Alice"], ["2", "Bob"], ["3", "Carol"]],
+            Some(vec![0]),
+        ),
+        None,
+    );
+    let to = Snapshot::new(
+        table(
+            &[["1", "Alicia"], ["4", "Dan"], ["4", "Dan again"]],
+            Some(vec![0]),
+        ),
+        None,
+    );
+
+    let keys = SnapshotDiff::key_changes(&from, &to, &DiffOptions::default()).unwrap();
+    assert_eq!(keys.added, vec![vec!["4".to_string()]]);
+    assert_eq!(
+        keys.removed,
+        vec![vec!["2".to_string()], vec!["3".to_string()]]
+    );
+
+    let unkeyed = Snapshot::new(table(&[["1", "Alice"]], None), None);
+    assert!(matches!(
+        SnapshotDiff::key_changes(&unkeyed, &unkeyed, &DiffOptions::default()),
+        Err(GitSheetsError::NoPrimaryKey)
+    ));
+}
+
+#[test]
+fn test_write_csv_with_quote_style_and_line_ending() {
+    let table = Table {
+        headers: vec!["ID".to_string(), "Note".to_string()],
+        rows: vec![vec!["1".to_string(), "a;b".to_string()]],
+        primary_key: None,
+    };
+    let write = |options: WriteOptions| {
+        let mut out = Vec::new();
+        table.write_csv_with(&mut out, &options).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(write(WriteOptions::default()), "ID,Note\n1,a;b\n");
+    assert_eq!(
+        write(WriteOptions {
+            delimiter: b';',
+            ..WriteOptions::default()
+        }),
+        "ID;Note\n1;\"a;b\"\n"
+    );
+    assert_eq!(
+        write(WriteOptions {
+            quote_style: QuoteStyle::Always,
+            line_ending: LineEnding::Crlf,
+            ..WriteOptions::default()
+        }),
+        "\"ID\",\"Note\"\r\n\"1\",\"a;b\"\r\n"
+    );
+}
+
+#[test]
+fn test_snapshot_store_loads_each_file_once() {
+    let table = |qty: &str| Table {
+        headers: vec!["ID".to_string(), "Qty".to_string()],
+        rows: vec![vec!["1".to_string(), qty.to_string()]],
+        primary_key: Some(vec![0]),
+    };
+    let base = Snapshot::new(table("10"), None);
+    let delta1 = Snapshot::new_delta(&base, &table("20")).unwrap();
+    let mut full1 = delta1.clone();
+    full1.table = table("20");
+    let delta2 = Snapshot::new_delta(&full1, &table("30")).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    for snapshot in [&base, &delta1, &delta2] {
+        snapshot
+            .save(&dir.path().join(format!("{}.toml", snapshot.id)))
+            .unwrap();
+    }
+    let tip = dir.path().join(format!("{}.toml", delta2.id));
+
+    let store = SnapshotStore::new(dir.path());
+    let chain = store.chain(&tip).unwrap();
+    let qtys: Vec<&str> = chain.iter().map(|s| s.table.rows[0][1].as_str()).collect();
+    assert_eq!(qtys, ["30", "20", "10"]);
+    assert!(chain.iter().all(|s| s.verify()));
+    assert_eq!(store.len(), 3);
+
+    // Later lookups come from the cache, not the disk
+    let again = store.get(&delta1.id).unwrap();
+    assert!(std::rc::Rc::ptr_eq(&again, &chain[1]));
+    assert_eq!(store.len(), 3);
+    assert_eq!(
+        Snapshot::load_materialized(&tip).unwrap().table.rows,
+        chain[0].table.rows
+    );
+}
+
+#[test]
+fn test_read_committed_returns_head_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = git2::Repository::init(dir.path()).unwrap();
+    let file = dir.path().join("data.csv");
+    std::fs::write(&file, "ID,Qty\n1,10\n").unwrap();
+
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("data.csv")).unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = git2::Signature::now("test", "test@localhost").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "add", &tree, &[])
+        .unwrap();
+
+    // Working-tree edits don't affect what was committed
+    std::fs::write(&file, "ID,Qty\n1,99\n").unwrap();
+    assert_eq!(read_committed(&file).unwrap(), b"ID,Qty\n1,10\n");
+
+    let untracked = dir.path().join("other.csv");
+    std::fs::write(&untracked, "ID\n").unwrap();
+    assert!(matches!(
+        read_committed(&untracked),
+        Err(GitSheetsError::FileSystemError(_))
+    ));
+}
+
+#[test]
+fn test_read_at_revision_reads_older_commits() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = git2::Repository::init(dir.path()).unwrap();
+    let signature = git2::Signature::now("test", "test@localhost").unwrap();
+    let commit = |files: &[(&str, &str)]| {
+        for (name, content) in files {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+        let mut index = repo.index().unwrap();
+        for (name, _) in files {
+            index.add_path(Path::new(name)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parents: Vec<git2::Commit> = repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .into_iter()
+            .collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "edit",
+            &tree,
+            &parents,
+        )
+        .unwrap();
+    };
+    commit(&[("a.toml", "one")]);
+    commit(&[("a.toml", "two"), ("b.toml", "new")]);
+
+    let a = dir.path().join("a.toml");
+    assert_eq!(read_at_revision(&a, "HEAD~1").unwrap(), b"one");
+    assert_eq!(read_at_revision(&a, "HEAD").unwrap(), b"two");
+
+    // Files added later, and revisions that don't exist, are errors
+    let b = dir.path().join("b.toml");
+    assert!(read_at_revision(&b, "HEAD~1").is_err());
+    assert!(read_at_revision(&a, "HEAD~5").is_err());
+}
+
+#[test]
+fn test_dependency_paths_resolve_from_any_checkout() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    git2::Repository::init(&repo).unwrap();
+    std::fs::create_dir_all(repo.join("data")).unwrap();
+    std::fs::write(repo.join("data/rates.csv"), "Code,Rate\nEUR,1.1\n").unwrap();
+    let hash = format!("{:x}", sha2::Sha256::digest(b"Code,Rate\nEUR,1.1\n"));
+    assert_eq!(
+        find_repo_root(&repo.join("data"))
+            .unwrap()
+            .canonicalize()
+            .unwrap(),
+        repo.canonicalize().unwrap()
+    );
+
+    let table = Table {
+        headers: vec!["ID".to_string()],
+        rows: vec![vec!["1".to_string()]],
+        primary_key: None,
+    };
+    let mut snapshot = Snapshot::new(table, None);
+    let stored = relative_to_repo_root(&repo.join("data/rates.csv")).unwrap();
+    assert_eq!(stored, Path::new("data/rates.csv"));
+    snapshot.add_dependency("rates".to_string(), Some(stored), hash);
+
+    // A second checkout elsewhere verifies against its own copy
+    let clone = dir.path().join("clone");
+    std::fs::create_dir_all(clone.join("data")).unwrap();
+    std::fs::copy(repo.join("data/rates.csv"), clone.join("data/rates.csv")).unwrap();
+    assert_eq!(
+        snapshot.dependencies[0].resolve(&clone),
+        clone.join("data/rates.csv")
+    );
+    snapshot.verify_dependencies_in(&clone).unwrap();
+
+    std::fs::write(clone.join("data/rates.csv"), "Code,Rate\nEUR,1.2\n").unwrap();
+    assert!(matches!(
+        snapshot.verify_dependencies_in(&clone),
+        Err(GitSheetsError::DependencyHashMismatch(_))
+    ));
+
+    // Older snapshots stored paths relative to where they were taken; tests
+    // run from the package root
+    snapshot.add_dependency(
+        "manifest".to_string(),
+        Some("Cargo.toml".into()),
+        String::new(),
+    );
+    assert_eq!(
+        snapshot.dependencies[1].resolve(&clone),
+        Path::new("Cargo.toml")
+    );
+}
+
+#[test]
+fn test_null_tokens_read_as_empty() {
+    let options = LoadOptions {
+        null_tokens: vec!["NULL".to_string(), "N/A".to_string(), "-".to_string()],
+        ..LoadOptions::default()
+    };
+    let database = "ID,Region,Qty,Note\n1,NULL,10,a-b\n2,East, N/A ,-\n3,null,-,NULL\n";
+    let spreadsheet = "ID,Region,Qty,Note\n1,,10,a-b\n2,East,,\n3,null,,\n";
+
+    let (from_db, notes) = Table::from_csv_bytes(database.as_bytes().to_vec(), &options).unwrap();
+    let (from_sheet, sheet_notes) =
+        Table::from_csv_bytes(spreadsheet.as_bytes().to_vec(), &options).unwrap();
+
+    // Only whole cells match, and matching is case-sensitive
+    assert_eq!(from_db.rows, from_sheet.rows);
+    assert_eq!(from_db.rows[0][3], "a-b");
+    assert_eq!(from_db.rows[2][1], "null");
+    assert_eq!(
+        notes,
+        ["normalized 5 null token(s) (NULL, N/A, -) to empty".to_string()]
+    );
+    assert!(sheet_notes.is_empty());
+    assert_eq!(
+        TableHashes::compute(&from_db).table_hash,
+        TableHashes::compute(&from_sheet).table_hash
+    );
+
+    // Off by default
+    let (raw, _) =
+        Table::from_csv_bytes(database.as_bytes().to_vec(), &LoadOptions::default()).unwrap();
+    assert_eq!(raw.rows[0][1], "NULL");
+}
+
+#[test]
+fn test_snapshot_storage_backends_round_trip() {
+    let table = Table {
+        headers: vec!["ID".to_string(), "Qty".to_string()],
+        rows: vec![vec!["1".to_string(), "10".to_string()]],
+        primary_key: Some(vec![0]),
+    };
+    let snapshot = Snapshot::new(table, Some("stored".to_string()));
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut backends: Vec<Box<dyn SnapshotStorage>> = vec![
+        Box::new(InMemoryStorage::new()),
+        Box::new(FsStorage::new(dir.path())),
+    ];
+    for storage in &mut backends {
+        snapshot.save_to(storage.as_mut()).unwrap();
+        assert_eq!(storage.list().unwrap(), vec![snapshot.id.clone()]);
+
+        let loaded = Snapshot::load_from(storage.as_ref(), &snapshot.id).unwrap();
+        assert_eq!(loaded.table.rows, snapshot.table.rows);
+        assert!(loaded.verify());
+        assert!(storage.get("missing").unwrap().is_none());
+    }
+
+    // JSON bytes land in a .json file, replacing the TOML copy
+    let mut fs_storage = FsStorage::new(dir.path());
+    fs_storage
+        .put(&snapshot.id, &snapshot.to_json().unwrap())
+        .unwrap();
+    assert!(dir.path().join(format!("{}.json", snapshot.id)).exists());
+    assert!(!dir.path().join(format!("{}.toml", snapshot.id)).exists());
+    assert_eq!(
+        Snapshot::load_from(&fs_storage, &snapshot.id)
+            .unwrap()
+            .message
+            .as_deref(),
+        Some("stored")
+    );
+    assert!(fs_storage.get("../escape").is_err());
+}
+
+#[test]
+fn test_delta_chains_load_from_any_storage() {
+    let table = |rows: &[[&str; 2]]| Table {
+        headers: vec!["ID".to_string(), "Qty".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let second = table(&[["1", "10"], ["2", "25"]]);
+    let third = table(&[["1", "11"], ["2", "25"], ["3", "30"]]);
+    let mut base = Snapshot::new(table(&[["1", "10"], ["2", "20"]]), None);
+    let mut delta1 = Snapshot::new_delta(&base, &second).unwrap();
+    let mut full1 = delta1.clone();
+    full1.table = second.clone();
+    let mut delta2 = Snapshot::new_delta(&full1, &third).unwrap();
+
+    let mut storage = InMemoryStorage::new();
+    for (secs, snapshot) in [&mut base, &mut delta1, &mut delta2]
+        .into_iter()
+        .enumerate()
+    {
+        snapshot.source = Some("sales.csv".to_string());
+        snapshot.timestamp += chrono::Duration::seconds(secs as i64);
+        snapshot.save_to(&mut storage).unwrap();
+    }
+
+    let loaded = Snapshot::load_materialized_from(&storage, &delta2.id).unwrap();
+    assert_eq!(loaded.table.rows, third.rows);
+
+    let chain = Snapshot::load_chain_from(&storage, &delta2.id).unwrap();
+    let ids: Vec<&str> = chain.iter().map(|s| s.id.as_str()).collect();
+    assert_eq!(
+        ids,
+        [delta2.id.as_str(), delta1.id.as_str(), base.id.as_str()]
+    );
+    assert_eq!(chain[0].table.rows, third.rows);
+    assert_eq!(chain[1].table.rows, second.rows);
+    assert_eq!(chain[2].table.rows, base.table.rows);
+
+    let latest = Snapshot::latest_for_source_in(&storage, "sales.csv").unwrap();
+    assert_eq!(latest.unwrap().id, delta2.id);
+    assert!(
+        Snapshot::latest_for_source_in(&storage, "other.csv")
+            .unwrap()
+            .is_none()
+    );
+
+    // Errors name the snapshot by its id
+    let mut sealed = base.clone();
+    sealed.seal().unwrap();
+    let toml = String::from_utf8(sealed.to_toml().unwrap()).unwrap();
+    let tampered = toml.replace("sales.csv", "costs.csv");
+    storage.put("broken", tampered.as_bytes()).unwrap();
+    let err = Snapshot::load_from(&storage, "broken").unwrap_err();
+    assert!(err.to_string().contains("snapshot 'broken'"), "{err}");
+}
+
+#[test]
+fn test_newer_schema_versions_warn_or_fail_clearly() {
+    let table = Table {
+        headers: vec!["ID".to_string()],
+        rows: vec![vec!["1".to_string()]],
+        primary_key: Some(vec![0]),
+    };
+    let snapshot = Snapshot::new(table, None);
+    assert_eq!(snapshot.schema_version, Snapshot::SCHEMA_VERSION);
+
+    // Seal a JSON document the way a newer writer would, over every field
+    let seal = |mut value: serde_json::Value| {
+        let object = value.as_object_mut().unwrap();
+        object.remove("checksum");
+        let mut sorted = serde_json::Value::Object(object.clone());
+        sorted.sort_all_objects();
+        let checksum = format!(
+            "{:x}",
+            sha2::Sha256::digest(serde_json::to_vec(&sorted).unwrap())
+        );
+        object.insert("checksum".to_string(), checksum.into());
+        serde_json::to_vec(&value).unwrap()
+    };
+    let mut value = serde_json::to_value(&snapshot).unwrap();
+    value["schema_version"] = (Snapshot::SCHEMA_VERSION + 1).into();
+
+    // Nothing unknown: loads, keeping the newer version number
+    let loaded = Snapshot::from_bytes(&seal(value.clone())).unwrap();
+    assert_eq!(loaded.schema_version, Snapshot::SCHEMA_VERSION + 1);
+    assert_eq!(loaded.table.rows, snapshot.table.rows);
+
+    // An unknown field would be dropped, so loading fails instead
+    let mut extended = value.clone();
+    extended["reviewers"] = serde_json::json!(["ana"]);
+    assert!(matches!(
+        Snapshot::from_bytes(&seal(extended)),
+        Err(GitSheetsError::UnsupportedSchema(_))
+    ));
+
+    // A reshaped field fails clearly rather than with a bare parse error
+    let mut reshaped = value;
+    reshaped["table"] = serde_json::json!("stored elsewhere");
+    assert!(matches!(
+        Snapshot::from_bytes(&seal(reshaped)),
+        Err(GitSheetsError::UnsupportedSchema(_))
+    ));
+
+    // Files from before the field existed read as the legacy version
+    let mut legacy = serde_json::to_value(&snapshot).unwrap();
+    legacy.as_object_mut().unwrap().remove("schema_version");
+    assert_eq!(
+        Snapshot::from_bytes(&seal(legacy)).unwrap().schema_version,
+        1
+    );
+}
+
+#[test]
+fn test_verify_ignoring_column_order_separates_moves_from_corruption() {
+    let table = Table {
+        headers: vec!["ID".to_string(), "Name".to_string(), "Qty".to_string()],
+        rows: vec![
+            vec!["1".to_string(), "Alice".to_string(), "10".to_string()],
+            vec!["2".to_string(), "Bob".to_string(), "20".to_string()],
+        ],
+        primary_key: None,
+    };
+    let mut snapshot = Snapshot::new(table, None);
+
+    // Move Qty to the front: the table hash breaks, the columns don't
+    for row in snapshot
+        .table
+        .rows
+        .iter_mut()
+        .chain(std::iter::once(&mut snapshot.table.headers))
+    {
+        row.rotate_right(1);
+    }
+    assert!(!snapshot.verify());
+    assert!(snapshot.verify_content_ignoring_column_order());
+
+    snapshot.table.rows[1][0] = "21".to_string();
+    assert!(!snapshot.verify_content_ignoring_column_order());
+}
+
+#[test]
+fn test_spot_check_samples_stored_row_hashes() {
+    let table = Table {
+        headers: vec!["ID".to_string(), "Qty".to_string()],
+        rows: (0..100)
+            .map(|i| vec![i.to_string(), (i * 10).to_string()])
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let mut snapshot = Snapshot::new(table, None);
+
+    // Nothing to sample without row hashes
+    assert!(!snapshot.spot_check(10, 1));
+    snapshot.store_row_hashes();
+    assert!(snapshot.spot_check(10, 1));
+    assert!(snapshot.spot_check(1000, 1));
+
+    // Checking every row always finds a tampered one; a seeded sample
+    // either finds it or not, but the same way every time
+    snapshot.table.rows[42][1] = "999".to_string();
+    assert!(!snapshot.spot_check(100, 7));
+    let first = snapshot.spot_check(20, 7);
+    assert_eq!(snapshot.spot_check(20, 7), first);
+    assert!((0..50).any(|seed| !snapshot.spot_check(20, seed)));
+
+    // A row count that disagrees with the hashes fails outright
+    snapshot.table.rows.pop();
+    assert!(!snapshot.spot_check(1, 1));
+}
+
+#[test]
+fn test_numeric_summary_reports_column_aggregates() {
+    let table = |rows: &[[&str; 3]]| Table {
+        headers: vec!["ID".to_string(), "Amount".to_string(), "Name".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(table(&[["1", "$1,000", "a"], ["2", "200", "b"]]), None);
+    let to = Snapshot::new(
+        table(&[["1", "1,500", "a"], ["2", "", "b"], ["3", "100", "c"]]),
+        None,
+    );
+
+    // The key and the text column are left out
+    let deltas = SnapshotDiff::numeric_summary(&from, &to);
+    assert_eq!(deltas.len(), 1);
+    let amount = &deltas[0];
+    assert_eq!(amount.column, "Amount");
+    assert_eq!(amount.from.count, 2);
+    assert_eq!(amount.to.count, 2);
+    assert_eq!(amount.sum_delta(), 400.0);
+    assert_eq!(amount.mean_delta(), Some(200.0));
+    assert_eq!(amount.min_delta(), Some(-100.0));
+    assert_eq!(amount.max_delta(), Some(500.0));
+    assert_eq!(
+        amount.to_string(),
+        "Amount: sum +400, mean +200, min -100, max +500"
+    );
+}
+
+#[test]
+fn test_compact_diff_round_trips_like_the_pretty_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let table = |rows: &[[&str; 2]]| Table {
+        headers: vec!["ID".to_string(), "Name".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(table(&[["1", "a"], ["2", "b"]]), None);
+    let to = Snapshot::new(table(&[["1", "A"], ["3", "c"]]), None);
+    let diff = SnapshotDiff::compute(&from, &to).unwrap();
+
+    let pretty = dir.path().join("diff.toml");
+    let compact = dir.path().join("diff.json");
+    diff.save(&pretty).unwrap();
+    diff.save_compact(&compact).unwrap();
+    assert!(!std::fs::read_to_string(&compact).unwrap().contains('\n'));
+
+    let value = |diff: &SnapshotDiff| serde_json::to_value(diff).unwrap();
+    assert_eq!(value(&SnapshotDiff::load(&pretty).unwrap()), value(&diff));
+    assert_eq!(value(&SnapshotDiff::load(&compact).unwrap()), value(&diff));
+
+    // JSON diffs from before delta snapshots tagged each change externally
+    let mut legacy = value(&diff);
+    for change in legacy["changes"].as_array_mut().unwrap() {
+        let mut fields = change.as_object().unwrap().clone();
+        let tag = fields.remove("type").unwrap();
+        *change = serde_json::json!({ tag.as_str().unwrap(): fields });
+    }
+    let old = dir.path().join("old.json");
+    std::fs::write(&old, legacy.to_string()).unwrap();
+    assert_eq!(value(&SnapshotDiff::load(&old).unwrap()), value(&diff));
+}
+
+#[test]
+fn test_empty_columns_are_found_in_saved_snapshots() {
+    let dir = tempfile::tempdir().unwrap();
+    let table = Table {
+        headers: vec![
+            "ID".to_string(),
+            "Name".to_string(),
+            "Notes".to_string(),
+            "Extra".to_string(),
+        ],
+        rows: vec![
+            vec![
+                "1".to_string(),
+                "a".to_string(),
+                " ".to_string(),
+                "".to_string(),
+            ],
+            vec!["2".to_string(), "".to_string()],
+        ],
+        primary_key: None,
+    };
+    assert_eq!(table.empty_columns(), vec!["Notes", "Extra"]);
+
+    // Only the column with no content at all is flagged in the hashes
+    let hashes = TableHashes::compute(&table);
+    assert_eq!(hashes.empty_columns(), vec!["Extra"]);
+    assert_eq!(
+        hashes.header_hashes["Extra"],
+        TableHashes::EMPTY_COLUMN_HASH
+    );
+    assert_eq!(hashes.header_hashes["Notes"].len(), 64);
+    assert!(
+        TableHashes::compute_with_version(&table, 2)
+            .empty_columns()
+            .is_empty()
+    );
+
+    // Found again after a save and reload, with no list of them stored
+    let snapshot = Snapshot::new(table, None);
+    let path = dir.path().join("snapshot.toml");
+    snapshot.save(&path).unwrap();
+    assert!(
+        !std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("empty_columns")
+    );
+    let loaded = Snapshot::load(&path).unwrap();
+    assert!(loaded.verify());
+    assert_eq!(loaded.table.empty_columns(), vec!["Notes", "Extra"]);
+    assert_eq!(loaded.hashes.empty_columns(), vec!["Extra"]);
+}
+
+#[test]
+fn test_row_indices_record_both_positions_of_matched_rows() {
+    let table = |rows: &[[&str; 2]]| Table {
+        headers: vec!["ID".to_string(), "Name".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(table(&[["1", "a"], ["42", "b"]]), None);
+    let to = Snapshot::new(table(&[["7", "z"], ["1", "a"], ["42", "B"]]), None);
+
+    // Off by default
+    let diff = SnapshotDiff::compute(&from, &to).unwrap();
+    assert!(diff.changes.iter().all(|change| !matches!(
+        change,
+        Change::CellChanged {
+            new_index: Some(_),
+            ..
+        }
+    )));
+
+    let opts = DiffOptions {
+        row_indices: true,
+        ..DiffOptions::default()
+    };
+    let diff = SnapshotDiff::compute_with(&from, &to, &opts).unwrap();
+    let cell = diff
+        .changes
+        .iter()
+        .find(|change| matches!(change, Change::CellChanged { .. }))
+        .unwrap();
+    assert!(matches!(
+        cell,
+        Change::CellChanged {
+            row: 1,
+            new_index: Some(2),
+            ..
+        }
+    ));
+    assert_eq!(
+        cell.to_string(),
+        "Cell changed at (1, 1) (key 42) (old #1 -> new #2): b -> B"
+    );
+}
+
+#[test]
+fn test_detect_splits_reports_split_and_merged_rows() {
+    let table = |rows: &[[&str; 3]]| Table {
+        headers: vec!["ID".to_string(), "Region".to_string(), "Amount".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(
+        table(&[
+            ["1", "north", "100"],
+            ["2", "south", "30"],
+            ["3", "south", "20"],
+            ["4", "west", "5"],
+        ]),
+        None,
+    );
+    let to = Snapshot::new(
+        table(&[
+            ["1a", "north", "60"],
+            ["1b", "north", "40"],
+            ["23", "south", "50"],
+            ["5", "west", "6"],
+        ]),
+        None,
+    );
+    let opts = DiffOptions {
+        detect_splits: true,
+        ..DiffOptions::default()
+    };
+    let diff = SnapshotDiff::compute_with(&from, &to, &opts).unwrap();
+
+    let split = diff
+        .changes
+        .iter()
+        .find_map(|change| match change {
+            Change::RowSplit { from, into } => Some((from.key.clone(), into.len())),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(split, (vec!["1".to_string()], 2));
+    assert!(diff.changes.iter().any(|change| matches!(
+        change,
+        Change::RowMerged { from, into } if from.len() == 2 && into.key == ["23"]
+    )));
+
+    // Rows that don't add up stay plain adds and removes
+    assert_eq!(diff.summary.rows_added, 1);
+    assert_eq!(diff.summary.rows_removed, 1);
+    let summary = SnapshotDiff::summarize_with(&from, &to, &opts).unwrap();
+    assert_eq!(
+        (summary.rows_added, summary.rows_removed),
+        (diff.summary.rows_added, diff.summary.rows_removed)
+    );
+    assert_eq!((summary.rows_split, summary.rows_merged), (1, 1));
+
+    // Off by default, and the diff still replays
+    let plain = SnapshotDiff::compute(&from, &to).unwrap();
+    assert_eq!(plain.summary.rows_split, 0);
+    assert_eq!(diff.apply(&from.table).unwrap().rows, to.table.rows);
+}
+
+#[test]
+fn test_set_columns_ignore_reordered_items() {
+    let table = |rows: &[[&str; 2]]| Table {
+        headers: vec!["ID".to_string(), "Tags".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(table(&[["1", "a;b;c"], ["2", "x"]]), None);
+    let to = Snapshot::new(table(&[["1", "c; a;b"], ["2", "x;y"]]), None);
+
+    let plain = SnapshotDiff::compute(&from, &to).unwrap();
+    assert_eq!(plain.changes.len(), 2);
+
+    let opts = DiffOptions {
+        set_columns: [("Tags".to_string(), ";".to_string())].into(),
+        ..DiffOptions::default()
+    };
+    let diff = SnapshotDiff::compute_with(&from, &to, &opts).unwrap();
+    // Only the real change is left, with its cells as they were written
+    assert!(matches!(
+        &diff.changes[..],
+        [Change::CellChanged { old, new, .. }] if old == "x" && new == "x;y"
+    ));
+}
+
+#[test]
+fn test_ignore_row_keys_leave_rows_out_of_the_diff() {
+    let table = |rows: &[[&str; 2]]| Table {
+        headers: vec!["ID".to_string(), "Amount".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(table(&[["1", "10"], ["2", "20"], ["TOTAL", "30"]]), None);
+    let to = Snapshot::new(table(&[["1", "15"], ["2", "20"], ["TOTAL", "35"]]), None);
+
+    let opts = DiffOptions {
+        ignore_row_keys: vec![vec!["TOTAL".to_string()], vec!["SUM".to_string()]],
+        ..DiffOptions::default()
+    };
+    let diff = SnapshotDiff::compute_with(&from, &to, &opts).unwrap();
+    assert!(matches!(
+        &diff.changes[..],
+        [Change::CellChanged { old, new, .. }] if old == "10" && new == "15"
+    ));
+    assert_eq!(
+        SnapshotDiff::unmatched_ignored_keys(&from, &to, &opts).unwrap(),
+        vec![vec!["SUM".to_string()]]
+    );
+}
+
+#[test]
+fn test_small_tables_diff_like_large_ones() {
+    let table = |rows: &[&[&str]], padding: usize, key: bool| Table {
+        headers: vec!["ID".to_string(), "Name".to_string(), "Amount".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .chain((0..padding).map(|i| vec![format!("pad{i}"), "x".to_string(), i.to_string()]))
+            .collect(),
+        primary_key: key.then(|| vec![0]),
+    };
+    // Duplicate keys, an ignored key, short rows and a row with no key
+    // cells; as many rows on each side, so padding lines up by position too
+    let from: &[&[&str]] = &[
+        &["1", "Ann", "10"],
+        &["2", "Bo", "20"],
+        &["2", "Bob", "21"],
+        &["3", "Cy"],
+        &["TOTAL", "", "51"],
+        &["4", "Di", "40"],
+        &[],
+    ];
+    let to: &[&[&str]] = &[
+        &["1", "Anne", "10"],
+        &["2", "Bo", "20"],
+        &["2", "Bob", "22"],
+        &["3", "Cy", "30"],
+        &["5", "Ed", "50"],
+        &["TOTAL", "", "112"],
+        &["5", "Eve", "55"],
+    ];
+    let opts = DiffOptions {
+        ignore_row_keys: vec![vec!["TOTAL".to_string()]],
+        ..DiffOptions::default()
+    };
+
+    for key in [true, false] {
+        let diff = |padding: usize| {
+            let from = Snapshot::new(table(from, padding, key), None);
+            let to = Snapshot::new(table(to, padding, key), None);
+            SnapshotDiff::compute_with(&from, &to, &opts)
+                .unwrap()
+                .changes
+        };
+        let small = diff(0);
+        assert!(!small.is_empty());
+        assert_eq!(small, diff(200), "key: {key}");
+    }
+}
+
+#[test]
+fn test_schema_reconciliation_compares_cells_by_column_name() {
+    let table = |headers: &[&str], rows: &[&[&str]]| Table {
+        headers: headers.iter().map(|h| h.to_string()).collect(),
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    // Only ID, Name and City survive in place; Code is renamed to Ref
+    let from = Snapshot::new(
+        table(
+            &["ID", "Name", "Code", "Notes", "City"],
+            &[
+                &["1", "Ann", "A1", "x", "Oslo"],
+                &["2", "Bo", "B2", "y", "Rome"],
+            ],
+        ),
+        None,
+    );
+    let to = Snapshot::new(
+        table(
+            &["ID", "Ref", "City", "Name", "Region"],
+            &[
+                &["1", "A1", "Oslo", "Anne", "N"],
+                &["2", "B2", "Rome", "Bo", "S"],
+            ],
+        ),
+        None,
+    );
+
+    let opts = DiffOptions::default();
+    let diff = SnapshotDiff::compute_with_schema_reconciliation(&from, &to, &opts).unwrap();
+    assert!(diff.changes.iter().any(|c| matches!(
+        c,
+        Change::ColumnRenamed { from, to, .. } if from == "Code" && to == "Ref"
+    )));
+    assert!(diff.changes.iter().any(|c| matches!(
+        c,
+        Change::ColumnRemoved { name, .. } if name == "Notes"
+    )));
+    assert!(diff.changes.iter().any(|c| matches!(
+        c,
+        Change::ColumnAdded { name, .. } if name == "Region"
+    )));
+    // The one real edit, against the right column; nothing else
+    let cells: Vec<(usize, &str, &str)> = diff
+        .changes
+        .iter()
+        .filter_map(|c| match c {
+            Change::CellChanged { col, old, new, .. } => Some((*col, old.as_str(), new.as_str())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(cells, vec![(1, "Ann", "Anne")]);
+    assert_eq!(diff.summary.rows_modified, 0);
+
+    let summary =
+        SnapshotDiff::summarize_with(&from, &to, &opts.with_schema_reconciliation()).unwrap();
+    assert_eq!(summary.columns_renamed, 1);
+    assert_eq!(summary.rows_modified, 0);
+
+    // Compared by position, every shifted column looks edited
+    let positional = SnapshotDiff::compute(&from, &to).unwrap();
+    assert!(positional.changes.len() > diff.changes.len());
+}
+
+#[test]
+fn test_changed_row_count_counts_each_row_once() {
+    let table = |rows: &[[&str; 3]]| Table {
+        headers: vec!["ID".to_string(), "Amount".to_string(), "Name".to_string()],
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let from = Snapshot::new(
+        table(&[["1", "10", "a"], ["2", "20", "b"], ["3", "30", "c"]]),
+        None,
+    );
+    let to = Snapshot::new(
+        table(&[["1", "11", "A"], ["2", "20", "b"], ["4", "40", "d"]]),
+        None,
+    );
+
+    // Row 1 has two changed cells, row 3 is removed, row 4 is added
+    let count = SnapshotDiff::changed_row_count(&from, &to, &DiffOptions::default()).unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(
+        SnapshotDiff::changed_row_count(&from, &from, &DiffOptions::default()).unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_diff_without_primary_key_pairs_rows_by_position() {
+    let snapshot = |rows: &[[&str; 2]]| {
+        Snapshot::new(
+            Table {
+                headers: vec!["Name".to_string(), "Qty".to_string()],
+                rows: rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+                    .collect(),
+                primary_key: None,
+            },
+            None,
+        )
+    };
+    let from = snapshot(&[["a", "1"], ["b", "2"]]);
+    let to = snapshot(&[["a", "1"], ["b", "3"], ["c", "4"]]);
+    let opts = DiffOptions::default();
+
+    assert_eq!(
+        RowMatching::resolve(&from.table, &to.table, &opts).unwrap(),
+        RowMatching::Position
+    );
+    let diff = SnapshotDiff::compute_with(&from, &to, &opts).unwrap();
+    assert_eq!(diff.summary.rows_added, 1);
+    assert!(diff.changes.iter().any(|change| matches!(
+        change,
+        Change::CellChanged { row: 1, col: 1, old, new, key: None, .. } if old == "2" && new == "3"
+    )));
+    assert!(diff.changes.iter().any(|change| matches!(
+        change,
+        Change::RowAdded {
+            index: 2,
+            key: None,
+            ..
+        }
+    )));
+    assert_eq!(SnapshotDiff::summarize(&from, &to), diff.summary);
+}
+
+#[test]
+fn test_threshold_row_count_sees_changes_without_primary_key() {
+    let snapshot = |rows: &[[&str; 2]]| {
+        Snapshot::new(
+            Table {
+                headers: vec!["Name".to_string(), "Qty".to_string()],
+                rows: rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+                    .collect(),
+                primary_key: None,
+            },
+            None,
+        )
+    };
+    let from = snapshot(&[["a", "1"], ["b", "2"]]);
+    let to = snapshot(&[["a", "1"], ["b", "3"], ["c", "4"]]);
+
+    // What `diff --threshold-rows` measures: row 1 changed, row 2 added
+    let opts = DiffOptions::default();
+    assert_eq!(
+        SnapshotDiff::changed_row_count(&from, &to, &opts).unwrap(),
+        2
+    );
+    assert_eq!(
+        SnapshotDiff::changed_row_count(&to, &from, &opts).unwrap(),
+        2
+    );
+    assert_eq!(
+        SnapshotDiff::changed_row_count(&from, &from, &opts).unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_diff_stat_counts_cells_per_column() {
+    let snapshot = |headers: &[&str], rows: &[&[&str]]| {
+        Snapshot::new(
+            Table {
+                headers: headers.iter().map(|h| h.to_string()).collect(),
+                rows: rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+                    .collect(),
+                primary_key: Some(vec![0]),
+            },
+            None,
+        )
+    };
+    let from = snapshot(
+        &["ID", "Amount", "Note"],
+        &[&["1", "10", "a"], &["2", "20", ""], &["3", "30", "c"]],
+    );
+    let to = snapshot(
+        &["ID", "Amount", "Note", "Owner"],
+        &[
+            &["1", "11", "a", "ann"],
+            &["2", "20", "b", ""],
+            &["4", "", "d", "dan"],
+        ],
+    );
+
+    let stat = SnapshotDiff::stat(&from, &to, &DiffOptions::default()).unwrap();
+    let counts: Vec<(&str, usize, usize, usize)> = stat
+        .columns
+        .iter()
+        .map(|c| (c.column.as_str(), c.added, c.modified, c.removed))
+        .collect();
+    // Row 4's empty Amount isn't counted, nor is its Owner twice
+    assert_eq!(
+        counts,
+        [
+            ("ID", 1, 0, 1),
+            ("Amount", 0, 1, 1),
+            ("Note", 1, 1, 1),
+            ("Owner", 2, 0, 0),
+        ]
+    );
+    assert_eq!(stat.rows_changed, 4);
+    assert_eq!(
+        (
+            stat.cells_added(),
+            stat.cells_modified(),
+            stat.cells_removed()
+        ),
+        (4, 2, 3)
+    );
+    assert!(
+        SnapshotDiff::stat(&from, &from, &DiffOptions::default())
+            .unwrap()
+            .columns
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_append_only_diff_lists_new_rows_and_rejects_removals() {
+    let snapshot = |rows: &[[&str; 2]], key: Option<Vec<usize>>| {
+        Snapshot::new(
+            Table {
+                headers: vec!["TxID".to_string(), "Amount".to_string()],
+                rows: rows
+                    .iter()
+                    .map(|row| row.iter().map(|s| s.to_string()).collect())
+                    .collect(),
+                primary_key: key,
+            },
+            None,
+        )
+    };
+    let day1 = snapshot(&[["t1", "10"], ["t2", "20"]], Some(vec![0]));
+    // A modified existing row isn't looked at
+    let day2 = snapshot(&[["t1", "10"], ["t2", "25"], ["t3", "30"]], Some(vec![0]));
+
+    let diff = SnapshotDiff::compute_append_only(&day1, &day2, &DiffOptions::default()).unwrap();
+    assert_eq!(diff.summary.rows_added, 1);
+    assert!(matches!(
+        diff.changes.as_slice(),
+        [Change::RowAdded { index: 2, key: Some(key), .. }] if key == &["t3".to_string()]
+    ));
+
+    let day3 = snapshot(&[["t1", "10"], ["t3", "30"]], Some(vec![0]));
+    assert!(matches!(
+        SnapshotDiff::compute_append_only(&day2, &day3, &DiffOptions::default()),
+        Err(GitSheetsError::AppendOnlyViolation(_))
+    ));
+
+    let mut reshaped = day2.clone();
+    reshaped.table.headers[1] = "Total".to_string();
+    assert!(matches!(
+        SnapshotDiff::compute_append_only(&day1, &reshaped, &DiffOptions::default()),
+        Err(GitSheetsError::AppendOnlyViolation(_))
+    ));
+
+    let unkeyed = snapshot(&[["t1", "10"]], None);
+    assert!(matches!(
+        SnapshotDiff::compute_append_only(&unkeyed, &unkeyed, &DiffOptions::default()),
+        Err(GitSheetsError::NoPrimaryKey)
+    ));
+}
+
+#[test]
+fn test_streamed_changes_tally_to_the_computed_summary() {
+    let snapshot = |rows: &[[&str; 2]]| {
+        Snapshot::new(
+            Table {
+                headers: vec!["ID".to_string(), "Qty".to_string()],
+                rows: rows
+                    .iter()
+                    .map(|row| row.iter().map(|s| s.to_string()).collect())
+                    .collect(),
+                primary_key: Some(vec![0]),
+            },
+            None,
+        )
+    };
+    let from = snapshot(&[["1", "10"], ["2", "20"], ["3", "30"]]);
+    let to = snapshot(&[["1", "11"], ["3", "30"], ["4", "40"]]);
+
+    let mut streamed = DiffSummary::default();
+    let mut lines = Vec::new();
+    for change in SnapshotDiff::iter_changes(&from, &to, &DiffOptions::default()).unwrap() {
+        streamed.record(&change);
+        lines.push(serde_json::to_string(&change).unwrap());
+    }
+
+    let computed = SnapshotDiff::compute(&from, &to).unwrap();
+    assert_eq!(
+        serde_json::to_value(&streamed).unwrap(),
+        serde_json::to_value(&computed.summary).unwrap()
+    );
+    // One self-contained JSON object per change
+    assert_eq!(lines.len(), computed.changes.len());
+    assert!(lines.iter().all(|line| !line.contains('\n')));
+}
+
+#[test]
+fn test_merkle_proof_proves_single_rows() {
+    let table = Table {
+        headers: vec!["ID".to_string(), "Qty".to_string()],
+        rows: (0..5)
+            .map(|i| vec![i.to_string(), (i * 10).to_string()])
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    let mut snapshot = Snapshot::new(table, None);
+    assert!(snapshot.hashes.merkle_proof(0).is_err());
+    snapshot.store_row_hashes();
+    let root = snapshot.hashes.merkle_root.clone().unwrap();
+
+    // Every row of an odd-sized table proves against the root
+    for (idx, row) in snapshot.table.rows.iter().enumerate() {
+        let proof = snapshot.hashes.merkle_proof(idx).unwrap();
+        assert!(verify_merkle_proof(&root, row, &proof));
+    }
+
+    let proof = snapshot.hashes.merkle_proof(2).unwrap();
+    let row = &snapshot.table.rows[2];
+    let mut tampered_row = row.clone();
+    tampered_row[1] = "999".to_string();
+    assert!(!verify_merkle_proof(&root, &tampered_row, &proof));
+
+    let mut tampered_proof = proof.clone();
+    tampered_proof[0].1 = hash_row(&tampered_row);
+    assert!(!verify_merkle_proof(&root, row, &tampered_proof));
+
+    let mut flipped = proof.clone();
+    flipped[0].0 = match flipped[0].0 {
+        Side::Left => Side::Right,
+        Side::Right => Side::Left,
+    };
+    assert!(!verify_merkle_proof(&root, row, &flipped));
+    assert!(!verify_merkle_proof(&hash_row(row), row, &proof));
+    assert!(snapshot.hashes.merkle_proof(5).is_err());
+}
+
+#[test]
+fn test_quality_warnings_flag_problems_at_capture_time() {
+    let rows: &[&[&str]] = &[
+        &["1", "5", "", "2020-01-01"],
+        &["1", "N/A", "", "2020-01-02"],
+        &["2", "7", ""],
+        &["3", "8", "", "2020-01-03"],
+    ];
+    let table = Table {
+        headers: ["ID", "Amount", "Notes", "Updated"]
+            .map(String::from)
+            .to_vec(),
+        rows: rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect(),
+        primary_key: Some(vec![0]),
+    };
+    assert_eq!(
+        table.quality_warnings(),
+        [
+            "1 primary key(s) appear on more than one row: '1'",
+            "1 row(s) don't have 4 cells, one per header (first at row 2)",
+            "column 'Notes' is entirely empty; it may be an export artifact",
+            "column 'Amount' is mostly float but 1 cell(s) aren't, e.g. \"N/A\"",
+            "column 'Updated' looks like a volatile timestamp; every snapshot will differ on it (consider --ignore-columns Updated)",
+        ]
+    );
+
+    // A clean table has nothing to report
+    let clean = table
+        .select_rows(&[0, 3])
+        .drop_columns(&["Notes".to_string(), "Updated".to_string()])
+        .unwrap();
+    assert!(clean.quality_warnings().is_empty());
+}
+
+#[test]
+fn test_select_keys_restores_only_the_named_rows() {
+    let mut table = Table {
+        headers: vec!["ID".to_string(), "Name".to_string()],
+        rows: ["Ann", "Bob", "Cy", "Di", "Ed"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| vec![(i + 1).to_string(), name.to_string()])
+            .collect(),
+        primary_key: None,
+    };
+    let keys = vec![vec!["4".to_string()], vec!["2".to_string()]];
+    assert!(matches!(
+        table.select_keys(&keys),
+        Err(GitSheetsError::NoPrimaryKey)
+    ));
+
+    // Two of five rows, in the order asked for, with the headers
+    table.set_primary_key(vec![0]);
+    let patch = table.select_keys(&keys).unwrap();
+    assert_eq!(patch.headers, table.headers);
+    assert_eq!(patch.rows, vec![vec!["4", "Di"], vec!["2", "Bob"]]);
+    assert_eq!(
+        String::from_utf8(patch.to_csv_bytes().unwrap()).unwrap(),
+        "ID,Name\n4,Di\n2,Bob\n"
+    );
+
+    let missing = vec![vec!["2".to_string()], vec!["9".to_string()]];
+    match table.select_keys(&missing) {
+        Err(GitSheetsError::KeyNotFound(msg)) => assert_eq!(msg, "'9' not in the table"),
+        other => panic!("expected KeyNotFound, got {other:?}"),
+    }
+
+    // Every row of a duplicated key comes back, not just the first
+    table.rows.push(vec!["2".to_string(), "Bea".to_string()]);
+    let patch = table.select_keys(&[vec!["2".to_string()]]).unwrap();
+    assert_eq!(patch.rows, vec![vec!["2", "Bob"], vec!["2", "Bea"]]);
+}
+
+#[test]
+fn test_keys_options_keep_every_row_of_a_duplicated_key() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(
+        dir.path().join("data.csv"),
+        "ID,Name\n1,Ann\n2,Bob\n2,Bea\n3,Cy\n",
+    )
+    .unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "-k", "ID"]);
+    git_sheets(
+        dir.path(),
+        &["snapshot", "data.csv", "-k", "ID", "--keys", "2"],
+    );
+
+    let mut snapshots: Vec<(Snapshot, PathBuf)> = snapshot_files(dir.path())
+        .into_iter()
+        .map(|file| (Snapshot::load(&file).unwrap(), file))
+        .collect();
+    snapshots.sort_by_key(|(s, _)| s.timestamp);
+    assert_eq!(snapshots[1].0.table.rows.len(), 2);
+
+    let full = snapshots[0].1.to_str().unwrap();
+    let restored = git_sheets(dir.path(), &["restore", full, "--keys", "2"]);
+    assert_eq!(restored, "ID,Name\n2,Bob\n2,Bea\n");
+}
+
+#[test]
+fn test_columns_list_type_fill_and_key() {
+    let table = Table {
+        headers: vec!["ID".to_string(), "Zip".to_string(), "Notes".to_string()],
+        rows: vec![
+            vec!["1".to_string(), "02134".to_string(), "".to_string()],
+            vec!["2".to_string(), "10001".to_string(), "late".to_string()],
+            vec!["3".to_string()],
+        ],
+        primary_key: Some(vec![0]),
+    };
+    let mut snapshot = Snapshot::new(table, None);
+    snapshot
+        .column_types
+        .insert("Zip".to_string(), ColumnType::Code);
+
+    let columns = snapshot.columns();
+    let summary: Vec<_> = columns
+        .iter()
+        .map(|c| {
+            (
+                c.index,
+                c.name.as_str(),
+                c.column_type,
+                c.declared,
+                c.non_empty,
+                c.primary_key,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (0, "ID", ColumnType::Integer, false, 3, true),
+            (1, "Zip", ColumnType::Code, true, 2, false),
+            (2, "Notes", ColumnType::Text, false, 1, false),
+        ]
+    );
+    assert_eq!(
+        serde_json::to_value(&columns[1]).unwrap(),
+        serde_json::json!({
+            "index": 1,
+            "name": "Zip",
+            "type": "code",
+            "declared": true,
+            "non_empty": 2,
+            "primary_key": false
+        })
+    );
+}
+
+#[test]
+fn test_sharded_files_concatenate_into_one_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let shard = |name: &str, csv: &str| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, csv).unwrap();
+        path
+    };
+    let jan = shard("jan.csv", "ID,Amount\n1,5\n2,6\n");
+    let feb = shard("feb.csv", "ID,Amount\n3,7\n");
+    let mar = shard("mar.csv", "ID,Amt\n4,8\n");
+
+    // One header row, every shard's rows in order
+    let mut table = Table::from_csv(&jan).unwrap();
+    table.append_rows(Table::from_csv(&feb).unwrap()).unwrap();
+    assert_eq!(table.headers, vec!["ID", "Amount"]);
+    assert_eq!(
+        table.rows,
+        vec![vec!["1", "5"], vec!["2", "6"], vec!["3", "7"]]
+    );
+
+    match table.append_rows(Table::from_csv(&mar).unwrap()) {
+        Err(GitSheetsError::InvalidColumn(msg)) => {
+            assert_eq!(msg, "column 2 is 'Amt', expected 'Amount'")
+        }
+        other => panic!("expected InvalidColumn, got {other:?}"),
+    }
+    assert_eq!(table.rows.len(), 3);
+
+    // Each shard is recorded by its contents
+    let mut snapshot = Snapshot::new(table, Some("Q1".to_string()));
+    snapshot.add_file_dependency(&jan).unwrap();
+    snapshot.add_file_dependency(&feb).unwrap();
+    assert_eq!(snapshot.dependencies.len(), 2);
+    snapshot.verify_dependencies_in(dir.path()).unwrap();
+    std::fs::write(&feb, "ID,Amount\n3,70\n").unwrap();
+    assert!(matches!(
+        snapshot.verify_dependencies_in(dir.path()),
+        Err(GitSheetsError::DependencyHashMismatch(_))
+    ));
+}
+
+#[test]
+fn test_headerless_csv_gets_synthetic_headers() {
+    let csv = b"1,Ann,5\n2,Bob,6\n3,Cy,7\n".to_vec();
+    let options = LoadOptions {
+        no_header: true,
+        ..LoadOptions::default()
+    };
+    let (table, _) = Table::from_csv_bytes(csv.clone(), &options).unwrap();
+    assert_eq!(table.headers, vec!["column_1", "column_2", "column_3"]);
+    assert_eq!(table.rows.len(), 3);
+    assert_eq!(table.rows[0], vec!["1", "Ann", "5"]);
+
+    // Read with a header line, the first row would be lost to it
+    let (with_header, _) = Table::from_csv_bytes(csv.clone(), &LoadOptions::default()).unwrap();
+    assert_eq!(with_header.rows.len(), 2);
+
+    // Written back without the generated headers, it's the original file
+    let mut out = Vec::new();
+    let write = WriteOptions {
+        header: false,
+        ..WriteOptions::default()
+    };
+    table.write_csv_with(&mut out, &write).unwrap();
+    assert_eq!(out, csv);
+}
+
+#[test]
+fn test_headerless_snapshots_diff_by_position() {
+    let options = LoadOptions {
+        no_header: true,
+        ..LoadOptions::default()
+    };
+    let load = |csv: &[u8]| {
+        let (table, _) = Table::from_csv_bytes(csv.to_vec(), &options).unwrap();
+        let mut snapshot = Snapshot::new(table, None);
+        snapshot.synthetic_headers = true;
+        snapshot
+    };
+    let from = load(b"a,1\nb,2\n");
+    let to = load(b"a,1\nb,3\nc,4\n");
+
+    let diff = SnapshotDiff::compute(&from, &to).unwrap();
+    assert_eq!(
+        diff.changes,
+        vec![
+            Change::RowAdded {
+                index: 2,
+                data: vec!["c".to_string(), "4".to_string()],
+                key: None,
+            },
+            Change::CellChanged {
+                row: 1,
+                col: 1,
+                old: "2".to_string(),
+                new: "3".to_string(),
+                key: None,
+                kind: CellChangeKind::Value,
+                new_index: None,
+            },
+        ]
+    );
+    assert_eq!(diff.summary.rows_added, 1);
+}
+
+#[test]
+fn test_snapshot_equality_compares_contents_not_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    let table = Table {
+        headers: vec!["ID".to_string(), "Qty".to_string()],
+        rows: vec![vec!["1".to_string(), "10".to_string()]],
+        primary_key: Some(vec![0]),
+    };
+    let mut snapshot = Snapshot::new(table.clone(), Some("first".to_string()));
+    snapshot.add_dependency("rates".to_string(), None, "abc".to_string());
+    snapshot.save(&dir.path().join("a.toml")).unwrap();
+    assert_eq!(
+        Snapshot::load(&dir.path().join("a.toml")).unwrap(),
+        snapshot
+    );
+
+    // Taken again later: new id, timestamp and checksum, same contents
+    let mut again = snapshot.clone();
+    again.id = format!("{}-later", snapshot.id);
+    again.timestamp += chrono::Duration::hours(1);
+    again.seal().unwrap();
+    assert_eq!(again, snapshot);
+
+    let mut edited = snapshot.clone();
+    edited.table.rows[0][1] = "11".to_string();
+    assert_ne!(edited, snapshot);
+    let mut relabelled = snapshot.clone();
+    relabelled.message = Some("second".to_string());
+    assert_ne!(relabelled, snapshot);
+    assert_ne!(
+        Snapshot::new(table.clone(), Some("first".to_string())),
+        snapshot
+    );
+}
+
+#[test]
+fn test_repo_lock_is_exclusive_until_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock_file = dir.path().join(".gitsheets/lock");
+    let held = RepoLock::acquire(dir.path(), Duration::ZERO).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&lock_file).unwrap(),
+        std::process::id().to_string()
+    );
+
+    let start = std::time::Instant::now();
+    match RepoLock::acquire(dir.path(), Duration::from_millis(120)) {
+        Err(GitSheetsError::RepositoryLocked(msg)) => {
+            assert!(msg.contains(&format!("pid {}", std::process::id())))
+        }
+        other => panic!("expected RepositoryLocked, got {other:?}"),
+    }
+    assert!(start.elapsed() >= Duration::from_millis(120));
+
+    // Released on drop, even on an error path
+    let failing = || -> gitsheets::core::Result<()> {
+        let _lock = held;
+        Err(GitSheetsError::EmptyTable)
+    };
+    assert!(failing().is_err());
+    assert!(!lock_file.exists());
+    drop(RepoLock::acquire(dir.path(), Duration::ZERO).unwrap());
+    assert!(!lock_file.exists());
+}
+
+#[test]
+fn test_logically_equal_edit_still_fails_checksum() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("s.toml");
+    let mut snapshot = Snapshot::new(
+        Table {
+            headers: vec!["ID".to_string(), "Name".to_string()],
+            rows: vec![vec!["1".to_string(), "Alice".to_string()]],
+            primary_key: Some(vec![0]),
+        },
+        Some("original".to_string()),
+    );
+    snapshot.seal().unwrap();
+    snapshot.save(&path).unwrap();
+
+    // Lowercasing a cell keeps the logical content but not the file
+    let edited = std::fs::read_to_string(&path)
+        .unwrap()
+        .replace("\"Alice\"", "\"alice\"");
+    std::fs::write(&path, edited).unwrap();
+    assert!(matches!(
+        Snapshot::load(&path),
+        Err(GitSheetsError::ChecksumMismatch(_))
+    ));
+    assert!(Snapshot::load_unverified(&path).unwrap().verify_logical());
+}
+
+#[test]
+fn test_logical_hash_ignores_formatting_and_order() {
+    let strings = |cells: &[&str]| cells.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    let table = Table {
+        headers: strings(&["ID", "Name", "Amount", "Opened"]),
+        rows: vec![
+            strings(&["1", "Alice", "1000.5", "2024-03-01"]),
+            strings(&["2", "Bob", "0", "2024-03-02"]),
+            strings(&["007", "Carol", "12", ""]),
+        ],
+        primary_key: Some(vec![0]),
+    };
+    // The same data re-exported: columns and rows moved, cells reformatted
+    let reexported = Table {
+        headers: strings(&["Opened ", "amount", "id", "NAME"]),
+        rows: vec![
+            strings(&["", "12.00", "007", "CAROL"]),
+            strings(&["03/02/2024", "0.0", "2", " bob "]),
+            strings(&["2024/03/01", "$1,000.50", "1", "alice"]),
+        ],
+        primary_key: None,
+    };
+
+    let snapshot = Snapshot::new(table.clone(), None);
+    assert_eq!(
+        snapshot.hashes.logical_hash.as_deref(),
+        Some(logical_hash(&reexported).as_str())
+    );
+    assert_ne!(
+        TableHashes::compute(&reexported).table_hash,
+        snapshot.hashes.table_hash
+    );
+
+    let mut moved = snapshot.clone();
+    moved.table = reexported.clone();
+    assert!(!moved.verify());
+    assert!(moved.verify_logical());
+
+    // Leading zeros are kept, so `007` and `7` differ
+    let mut renumbered = moved.clone();
+    renumbered.table.rows[0][2] = "7".to_string();
+    assert!(!renumbered.verify_logical());
+
+    // Snapshots from before logical hashes have nothing to compare
+    moved.hashes.logical_hash = None;
+    assert!(!moved.verify_logical());
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet_export_round_trips_column_types() {
+    use arrow_array::{Array, BooleanArray, Date32Array, Float64Array, Int64Array, StringArray};
+    use arrow_schema::DataType;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let table = Table {
+        headers: ["ID", "Price", "Active", "Opened", "Name", "Code"]
+            .map(String::from)
+            .to_vec(),
+        rows: [
+            ["1", "9.5", "TRUE", "2024-01-31", "Alice", "007"],
+            ["2", "", "false", "02/29/2024", "Bob", "008"],
+        ]
+        .iter()
+        .map(|row| row.map(String::from).to_vec())
+        .collect(),
+        primary_key: None,
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.parquet");
+    assert!(table.write_parquet(&path).unwrap().is_empty());
+
+    let file = std::fs::File::open(&path).unwrap();
+    let mut reader = ParquetRecordBatchReaderBuilder::try_new(file)
+        .unwrap()
+        .build()
+        .unwrap();
+    let batch = reader.next().unwrap().unwrap();
+    let types: Vec<DataType> = batch
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| field.data_type().clone())
+        .collect();
+    assert_eq!(
+        types,
+        [
+            DataType::Int64,
+            DataType::Float64,
+            DataType::Boolean,
+            DataType::Date32,
+            DataType::Utf8,
+            // Leading zeros would be lost as a number
+            DataType::Utf8,
+        ]
+    );
+
+    let column = |idx: usize| batch.column(idx).as_any();
+    let ids = column(0).downcast_ref::<Int64Array>().unwrap();
+    assert_eq!(ids.values(), &[1, 2]);
+    let prices = column(1).downcast_ref::<Float64Array>().unwrap();
+    assert_eq!(prices.value(0), 9.5);
+    assert!(prices.is_null(1));
+    let active = column(2).downcast_ref::<BooleanArray>().unwrap();
+    assert!(active.value(0) && !active.value(1));
+    let opened = column(3).downcast_ref::<Date32Array>().unwrap();
+    assert_eq!(opened.value_as_date(1).unwrap().to_string(), "2024-02-29");
+    let names = column(4).downcast_ref::<StringArray>().unwrap();
+    assert_eq!(names.value(1), "Bob");
+}
+
+#[test]
+fn test_side_by_side_diff_of_empty_tables() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("empty.csv"), "").unwrap();
+    git_sheets(dir.path(), &["snapshot", "empty.csv", "-m", "one"]);
+    git_sheets(dir.path(), &["snapshot", "empty.csv", "-m", "two"]);
+
+    let files = snapshot_files(dir.path());
+    assert_eq!(files.len(), 2);
+    let (from, to) = (files[0].to_str().unwrap(), files[1].to_str().unwrap());
+    git_sheets(dir.path(), &["diff", from, to, "-f", "side-by-side"]);
+}
+
+#[test]
+fn test_snapshot_dry_run_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "--dry-run"]);
+
+    // Not even the lock's directory
+    let entries: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["data.csv"]);
+}
+
+#[test]
+fn test_spot_check_skips_the_file_checksum() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n2,6\n").unwrap();
+    git_sheets(
+        dir.path(),
+        &["snapshot", "data.csv", "--row-hashes", "-m", "one"],
+    );
+    let file = snapshot_files(dir.path()).remove(0);
+    let content = std::fs::read_to_string(&file).unwrap();
+    std::fs::write(&file, content.replace("\"one\"", "\"uno\"")).unwrap();
+    let file = file.to_str().unwrap();
+
+    // Only rows are sampled, so a message edit goes unnoticed
+    git_sheets(dir.path(), &["verify", file, "--spot-check", "2"]);
+    git_sheets_fails(dir.path(), &["verify", file]);
+
+    // Spot-checking needs a file rather than falling back to --all
+    git_sheets_fails(dir.path(), &["verify", "--spot-check", "2"]);
+
+    // A sampled row that was edited fails
+    let content = std::fs::read_to_string(file).unwrap();
+    std::fs::write(file, content.replace("\"6\",", "\"7\",")).unwrap();
+    let stderr = git_sheets_fails(dir.path(), &["verify", file, "--spot-check", "2"]);
+    assert!(stderr.contains("Spot Check Failed"), "{stderr}");
+}
+
+#[test]
+fn test_failed_diff_leaves_output_file_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "-m", "one"]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,6\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "-m", "two"]);
+    std::fs::write(dir.path().join("diff.json"), "kept").unwrap();
+
+    // Without a terminal the review fails after the diff was started
+    let files = snapshot_files(dir.path());
+    let (from, to) = (files[0].to_str().unwrap(), files[1].to_str().unwrap());
+    let stderr = git_sheets_fails(
+        dir.path(),
+        &["diff", from, to, "--interactive", "-o", "diff.json"],
+    );
+    assert!(stderr.contains("Terminal Required"), "{stderr}");
+    let kept = std::fs::read_to_string(dir.path().join("diff.json")).unwrap();
+    assert_eq!(kept, "kept");
+}
+
+#[test]
+fn test_context_rows_leave_out_modified_rows_and_name_their_table() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    for content in [
+        "ID,Name,Qty,Flag\n1,alpha,50,x\n2,beta,60,y\n3,gamma,70,z\n4,delta,80,w\n",
+        "ID,Name,Qty,Flag\n1,alpha,50,x\n2,betta,60,y\n5,eps,90,v\n4,delta,80,w\n",
+    ] {
+        std::fs::write(dir.path().join("data.csv"), content).unwrap();
+        git_sheets(dir.path(), &["snapshot", "data.csv", "-k", "ID"]);
+    }
+    let mut snapshots: Vec<(Snapshot, PathBuf)> = snapshot_files(dir.path())
+        .into_iter()
+        .map(|file| (Snapshot::load(&file).unwrap(), file))
+        .collect();
+    snapshots.sort_by_key(|(s, _)| s.timestamp);
+    let (from, to) = (
+        snapshots[0].1.to_str().unwrap(),
+        snapshots[1].1.to_str().unwrap(),
+    );
+
+    let out = git_sheets(dir.path(), &["diff", from, to, "-U", "1"]);
+    // Row 2 sits next to the added row in `to`, but it was modified
+    assert!(!out.contains("new 1:"), "{out}");
+    assert!(out.contains(r#"old 0: ["1", "alpha", "50", "x"]"#), "{out}");
+    assert!(out.contains(r#"old 3: ["4", "delta", "80", "w"]"#), "{out}");
+    assert!(out.contains(r#"new 3: ["4", "delta", "80", "w"]"#), "{out}");
+}
+
+#[test]
+fn test_newer_schema_warning_reaches_the_cli() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "--json", "-m", "one"]);
+
+    // Unsealed, as written before checksums, so the bump needs no reseal
+    let files: Vec<PathBuf> = std::fs::read_dir(dir.path().join("snapshots"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    let mut value: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&files[0]).unwrap()).unwrap();
+    value.as_object_mut().unwrap().remove("checksum");
+    value["schema_version"] = (Snapshot::SCHEMA_VERSION + 1).into();
+    std::fs::write(&files[0], value.to_string()).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_git-sheets"))
+        .args(["--no-git", "verify", files[0].to_str().unwrap()])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("newer than this git-sheets"), "{stderr}");
+}
+
+#[test]
+fn test_fsck_names_the_missing_parent_of_a_delta() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv"]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,6\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "--delta"]);
+
+    let (delta, base): (Vec<_>, Vec<_>) = snapshot_files(dir.path())
+        .into_iter()
+        .map(|file| (Snapshot::load(&file).unwrap(), file))
+        .partition(|(snapshot, _)| snapshot.delta.is_some());
+    std::fs::remove_file(&base[0].1).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_git-sheets"))
+        .args(["--no-git", "fsck"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected = format!(
+        "parent '{}' of '{}' can't be loaded",
+        base[0].0.id, delta[0].0.id
+    );
+    assert!(stdout.contains(&expected), "{stdout}");
+}
+
+#[test]
+fn test_git_options_fail_plainly_when_git_is_disabled() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv"]);
+
+    let file = snapshot_files(dir.path()).remove(0);
+    let stderr = git_sheets_fails(dir.path(), &["diff", file.to_str().unwrap(), "--vs-head"]);
+    assert!(
+        stderr.contains("Git Disabled: --vs-head needs git"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_undo_needs_a_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "-m", "first"]);
+
+    let file = snapshot_files(dir.path()).remove(0);
+    let stderr = git_sheets_fails(dir.path(), &["undo", file.to_str().unwrap()]);
+    assert!(stderr.contains("No Parent"), "{stderr}");
+}
+
+#[test]
+fn test_catalog_lists_each_snapshot_with_its_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n").unwrap();
+    git_sheets(
+        dir.path(),
+        &["snapshot", "data.csv", "-m", "first", "--author", "ana"],
+    );
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty,Note\n1,5,x\n2,6,y\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "-m", "second"]);
+    std::fs::write(dir.path().join("catalog.csv"), "stale").unwrap();
+    git_sheets(dir.path(), &["catalog", "-o", "catalog.csv"]);
+
+    let mut reader = csv::Reader::from_path(dir.path().join("catalog.csv")).unwrap();
+    let headers: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+    assert_eq!(
+        headers,
+        [
+            "id",
+            "timestamp",
+            "message",
+            "author",
+            "source",
+            "rows",
+            "columns",
+            "table_hash"
+        ]
+    );
+    let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(records.len(), 2);
+
+    let snapshots: Vec<Snapshot> = snapshot_files(dir.path())
+        .iter()
+        .map(|file| Snapshot::load(file).unwrap())
+        .collect();
+    for record in &records {
+        let snapshot = snapshots.iter().find(|s| s.id == record[0]).unwrap();
+        assert_eq!(&record[1], snapshot.timestamp.to_rfc3339());
+        assert_eq!(&record[4], "data.csv");
+        assert_eq!(&record[7], snapshot.hashes.table_hash);
+    }
+
+    // Oldest first
+    assert_eq!(
+        (
+            &records[0][2],
+            &records[0][3],
+            &records[0][5],
+            &records[0][6]
+        ),
+        ("first", "ana", "1", "2")
+    );
+    assert_eq!(
+        (
+            &records[1][2],
+            &records[1][3],
+            &records[1][5],
+            &records[1][6]
+        ),
+        ("second", "", "2", "3")
+    );
+}
+
+#[test]
+fn test_report_nets_changes_inside_its_window() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    for (content, message) in [
+        ("ID,Qty\n1,5\n", "first"),
+        ("ID,Qty\n1,6\n", "second"),
+        ("ID,Qty\n1,6\n2,7\n", "third"),
+    ] {
+        std::fs::write(dir.path().join("data.csv"), content).unwrap();
+        git_sheets(dir.path(), &["snapshot", "data.csv", "-m", message]);
+    }
+    let mut snapshots: Vec<Snapshot> = snapshot_files(dir.path())
+        .iter()
+        .map(|file| Snapshot::load(file).unwrap())
+        .collect();
+    snapshots.sort_by_key(|s| s.timestamp);
+    let stamp = |idx: usize| snapshots[idx].timestamp.to_rfc3339();
+
+    // The whole history: the cell change and the added row
+    let all = git_sheets(dir.path(), &["report", "--source", "data.csv"]);
+    assert!(all.contains("3 snapshot(s) in the window"), "{all}");
+    assert!(all.contains("Rows added: 1"), "{all}");
+    assert!(all.contains("Qty: 1 cell(s)"), "{all}");
+
+    // From the second snapshot on, only the added row
+    let since = stamp(1);
+    let late = git_sheets(
+        dir.path(),
+        &["report", "--source", "data.csv", "--since", &since],
+    );
+    assert!(late.contains("2 snapshot(s) in the window"), "{late}");
+    assert!(late.contains("Rows added: 1"), "{late}");
+    assert!(!late.contains("Qty:"), "{late}");
+
+    // Up to the first snapshot there is nothing to compare
+    let until = stamp(0);
+    let early = git_sheets(
+        dir.path(),
+        &["report", "--source", "data.csv", "--until", &until],
+    );
+    assert!(early.contains("nothing to compare"), "{early}");
+}
+
+#[test]
+fn test_changelog_writes_each_change_of_a_column() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    for content in ["ID,Qty\n1,5\n2,6\n", "ID,Qty\n1,5\n2,8\n"] {
+        std::fs::write(dir.path().join("data.csv"), content).unwrap();
+        git_sheets(dir.path(), &["snapshot", "data.csv", "-k", "ID"]);
+    }
+    std::fs::write(dir.path().join("changes.csv"), "stale").unwrap();
+    git_sheets(
+        dir.path(),
+        &["changelog", "--col", "Qty", "-o", "changes.csv"],
+    );
+
+    let mut reader = csv::Reader::from_path(dir.path().join("changes.csv")).unwrap();
+    let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        (&records[0][2], &records[0][3], &records[0][4]),
+        ("2", "6", "8")
+    );
+}
+
+#[test]
+fn test_snapshot_source_is_stored_relative_to_the_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    let absolute = dir.path().join("data.csv");
+    for (content, path) in [
+        ("ID,Qty\n1,5\n", "./data.csv"),
+        ("ID,Qty\n1,6\n", "data.csv"),
+        ("ID,Qty\n1,7\n", absolute.to_str().unwrap()),
+    ] {
+        std::fs::write(&absolute, content).unwrap();
+        git_sheets(dir.path(), &["snapshot", path]);
+    }
+
+    let mut snapshots: Vec<Snapshot> = snapshot_files(dir.path())
+        .iter()
+        .map(|file| Snapshot::load(file).unwrap())
+        .collect();
+    snapshots.sort_by_key(|s| s.timestamp);
+    for snapshot in &snapshots {
+        assert_eq!(snapshot.source.as_deref(), Some("data.csv"));
+    }
+    // However the file was named, each snapshot follows the one before
+    assert_eq!(snapshots[0].parent, None);
+    assert_eq!(snapshots[1].parent.as_ref(), Some(&snapshots[0].id));
+    assert_eq!(snapshots[2].parent.as_ref(), Some(&snapshots[1].id));
+}
+
+#[test]
+fn test_key_subset_snapshots_are_never_a_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    git_sheets(dir.path(), &["init", "."]);
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n2,6\n3,7\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "-k", "ID"]);
+    git_sheets(
+        dir.path(),
+        &["snapshot", "data.csv", "-k", "ID", "--keys", "1"],
+    );
+    std::fs::write(dir.path().join("data.csv"), "ID,Qty\n1,5\n2,6\n3,8\n").unwrap();
+    git_sheets(dir.path(), &["snapshot", "data.csv", "-k", "ID", "--delta"]);
+
+    let mut snapshots: Vec<(Snapshot, PathBuf)> = snapshot_files(dir.path())
+        .into_iter()
+        .map(|file| (Snapshot::load(&file).unwrap(), file))
+        .collect();
+    snapshots.sort_by_key(|(s, _)| s.timestamp);
+    assert!(snapshots[1].0.key_subset.is_some());
+    // The delta follows the last full snapshot, not the one-row subset
+    assert_eq!(snapshots[2].0.parent.as_ref(), Some(&snapshots[0].0.id));
+    let latest = Snapshot::load_materialized(&snapshots[2].1).unwrap();
+    assert_eq!(latest.table.rows.len(), 3);
+    let (full, delta) = (
+        snapshots[0].1.to_str().unwrap(),
+        snapshots[2].1.to_str().unwrap(),
+    );
+    let out = git_sheets(dir.path(), &["diff", full, delta]);
+    assert!(out.contains("Rows added: 0"), "{out}");
+}